@@ -0,0 +1,29 @@
+// Blobs melting into each other over a checkered floor. Only the scene is described here: the
+// wallpaper raymarches, lights and fogs it.
+
+#import desktop::sdf
+
+fn blob_center(index: f32) -> vec3<f32> {
+    let t = uniforms.time * uniforms.speed + index * 2.1;
+    return vec3<f32>(sin(t * 0.9) * 1.4, 0.9 + sin(t * 1.3) * 0.4, cos(t * 0.7) * 1.4);
+}
+
+fn blobs(p: vec3<f32>) -> f32 {
+    var d = sd_sphere(p - blob_center(0.0), 0.6);
+    for (var i = 1; i < 4; i += 1) {
+        d = op_smooth_union(d, sd_sphere(p - blob_center(f32(i)), 0.45), uniforms.smoothness);
+    }
+    return d;
+}
+
+fn map(p: vec3<f32>) -> f32 {
+    return op_union(blobs(p), sd_plane(p, vec3<f32>(0.0, 1.0, 0.0), 0.0));
+}
+
+fn material(p: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    if (blobs(p) < 0.01) {
+        return uniforms.color.rgb;
+    }
+    let check = (i32(floor(p.x)) + i32(floor(p.z))) & 1;
+    return mix(vec3<f32>(0.25), vec3<f32>(0.4), f32(check));
+}
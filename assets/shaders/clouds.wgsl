@@ -0,0 +1,50 @@
+// Clouds drifting across the sky, from layers of the noise texture bound to the first channel.
+
+@group(1) @binding(1)
+var noise_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var noise_sampler: sampler;
+
+struct Uniforms {
+    time: f32,
+    resolution: vec2<f32>,
+    speed: f32,
+    cover: f32,
+    sky: vec4<f32>,
+    cloud: vec4<f32>,
+};
+
+@group(1) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+struct FragmentInput {
+    #import bevy_sprite::mesh2d_vertex_output
+};
+
+// The texture repeats and is filtered linearly, which makes it smooth value noise once scaled up.
+fn noise(p: vec2<f32>) -> f32 {
+    return textureSample(noise_texture, noise_sampler, p / 256.0).r;
+}
+
+fn fbm(p: vec2<f32>) -> f32 {
+    var value = 0.0;
+    var amplitude = 0.5;
+    var q = p;
+    for (var octave = 0; octave < 5; octave += 1) {
+        value += amplitude * noise(q);
+        q = q * 2.03 + vec2<f32>(17.0, 31.0);
+        amplitude *= 0.5;
+    }
+    return value;
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    let aspect = uniforms.resolution.x / max(uniforms.resolution.y, 1.0);
+    let p = in.uv * vec2<f32>(aspect, 1.0) * 24.0;
+    let drift = vec2<f32>(uniforms.time * uniforms.speed, 0.0);
+    let density = fbm(p + drift + 4.0 * fbm(p * 0.5 - drift * 0.3));
+    let amount = smoothstep(1.0 - uniforms.cover, 1.2 - uniforms.cover, density);
+    let sky = mix(uniforms.sky.rgb, uniforms.sky.rgb * 0.6, in.uv.y);
+    return vec4<f32>(mix(sky, uniforms.cloud.rgb, amount), 1.0);
+}
@@ -1,6 +1,6 @@
 #import bevy_pbr::mesh_types
-// The time since startup data is in the globals binding which is part of the mesh_view_bindings import
 #import bevy_pbr::mesh_view_bindings
+#import desktop::color
 
 struct CustomMaterial {
     time: f32
@@ -9,25 +9,25 @@ struct CustomMaterial {
 @group(1) @binding(0)
 var<uniform> material: CustomMaterial;
 
-fn oklab_to_linear_srgb(c: vec3<f32>) -> vec3<f32> {
-    let L = c.x;
-    let a = c.y;
-    let b = c.z;
-
-    let l_ = L + 0.3963377774 * a + 0.2158037573 * b;
-    let m_ = L - 0.1055613458 * a - 0.0638541728 * b;
-    let s_ = L - 0.0894841775 * a - 1.2914855480 * b;
+// Laid out by the parameters in wallpapers/cube_demo.wallpaper.ron.
+struct Parameters {
+    speed: f32,
+    blend: u32,
+    gradient: u32,
+    center_from: vec4<f32>,
+    center_to: vec4<f32>,
+    edge_from: vec4<f32>,
+    edge_to: vec4<f32>,
+};
 
-    let l = l_ * l_ * l_;
-    let m = m_ * m_ * m_;
-    let s = s_ * s_ * s_;
+@group(1) @binding(1)
+var<uniform> parameters: Parameters;
+@group(1) @binding(2)
+var pattern_texture: texture_2d<f32>;
+@group(1) @binding(3)
+var pattern_sampler: sampler;
 
-    return vec3<f32>(
-        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
-        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
-        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
-    );
-}
+let BLEND_OKLAB: u32 = 0u;
 
 @fragment
 fn fragment(
@@ -49,20 +49,34 @@ fn fragment(
     // var value3 = (noise3 + 1.0) / 2.0;
     
     // return vec4<f32>(uv);
-    let speed = 2.0;
-    // The globals binding contains various global values like time
-    // which is the time since startup in seconds
-    let t_1 = sin(globals.time * speed) * 0.5 + 0.5;
-    let t_2 = cos(globals.time * speed);
+    let speed = parameters.speed;
+    // The wallpaper clock rather than globals.time, so that the demo pauses and follows a fixed
+    // step like every other wallpaper.
+    let t_1 = sin(material.time * speed) * 0.5 + 0.5;
+    let t_2 = cos(material.time * speed);
 
-    let distance_to_center = distance(uv, vec2<f32>(0.5)) * 1.4;
+    var distance_to_center = 0.5;
+    if (parameters.gradient != 0u) {
+        distance_to_center = distance(uv, vec2<f32>(0.5)) * 1.4;
+    }
 
-    // blending is done in a perceptual color space: https://bottosson.github.io/posts/oklab/
-    let red = vec3<f32>(0.627955, 0.224863, 0.125846);
-    let green = vec3<f32>(0.86644, -0.233887, 0.179498);
-    let blue = vec3<f32>(0.701674, 0.274566, -0.169156);
-    let white = vec3<f32>(1.0, 0.0, 0.0);
-    let mixed = mix(mix(red, blue, t_1), mix(green, white, t_2), distance_to_center);
+    let center_from = parameters.center_from.rgb;
+    let center_to = parameters.center_to.rgb;
+    let edge_from = parameters.edge_from.rgb;
+    let edge_to = parameters.edge_to.rgb;
+    var color: vec3<f32>;
+    if (parameters.blend == BLEND_OKLAB) {
+        // blending is done in a perceptual color space
+        let mixed = mix(
+            mix(linear_srgb_to_oklab(center_from), linear_srgb_to_oklab(center_to), t_1),
+            mix(linear_srgb_to_oklab(edge_from), linear_srgb_to_oklab(edge_to), t_2),
+            distance_to_center
+        );
+        color = oklab_to_linear_srgb(mixed);
+    } else {
+        color = mix(mix(center_from, center_to, t_1), mix(edge_from, edge_to, t_2), distance_to_center);
+    }
 
-    return vec4<f32>(oklab_to_linear_srgb(mixed), 1.0);
+    let pattern = textureSample(pattern_texture, pattern_sampler, uv).rgb;
+    return vec4<f32>(color * pattern, 1.0);
 }
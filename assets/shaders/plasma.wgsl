@@ -0,0 +1,35 @@
+#import bevy_sprite::mesh2d_view_bindings
+
+// Filled in by name: `time` and `resolution` by the app, the rest from the parameters in
+// wallpapers/plasma.wallpaper.ron.
+struct Uniforms {
+    time: f32,
+    resolution: vec2<f32>,
+    speed: f32,
+    scale: f32,
+    low: vec4<f32>,
+    high: vec4<f32>,
+};
+
+@group(1) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+struct FragmentInput {
+    #import bevy_sprite::mesh2d_vertex_output
+};
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    let aspect = uniforms.resolution.x / max(uniforms.resolution.y, 1.0);
+    let p = (in.uv - 0.5) * vec2<f32>(aspect, 1.0) * uniforms.scale;
+    let t = uniforms.time * uniforms.speed;
+
+    var v = sin(p.x + t);
+    v += sin((p.y + t) * 0.5);
+    v += sin((p.x + p.y + t) * 0.5);
+    let c = p + 0.5 * vec2<f32>(sin(t / 3.0), cos(t / 2.0));
+    v += sin(sqrt(dot(c, c) + 1.0) + t);
+
+    let blend = sin(v * 3.14159265) * 0.5 + 0.5;
+    return vec4<f32>(mix(uniforms.low.rgb, uniforms.high.rgb, blend), 1.0);
+}
@@ -0,0 +1,25 @@
+// Colors the reaction-diffusion simulation by how much of chemical B there is.
+
+@group(1) @binding(1)
+var chemicals_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var chemicals_sampler: sampler;
+
+struct Uniforms {
+    background: vec4<f32>,
+    foreground: vec4<f32>,
+};
+
+@group(1) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+struct FragmentInput {
+    #import bevy_sprite::mesh2d_vertex_output
+};
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    let b = textureSample(chemicals_texture, chemicals_sampler, in.uv).g;
+    let amount = smoothstep(0.1, 0.4, b);
+    return vec4<f32>(mix(uniforms.background.rgb, uniforms.foreground.rgb, amount), 1.0);
+}
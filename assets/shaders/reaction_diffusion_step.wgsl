@@ -0,0 +1,60 @@
+// Gray-Scott reaction-diffusion. The red channel holds chemical A, which is fed in everywhere, and
+// the green channel chemical B, which eats A to make more of itself and slowly dies off.
+
+struct Uniforms {
+    feed: f32,
+    kill: f32,
+    seeds: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+@group(0) @binding(1)
+var state: texture_2d<f32>;
+@group(0) @binding(2)
+var next: texture_storage_2d<rgba16float, write>;
+
+fn hash(p: vec2<u32>) -> f32 {
+    var h = p.x * 374761393u + p.y * 668265263u;
+    h = (h ^ (h >> 13u)) * 1274126177u;
+    return f32(h ^ (h >> 16u)) / 4294967295.0;
+}
+
+// Reads the state, wrapping around at the edges.
+fn chemicals(p: vec2<i32>) -> vec2<f32> {
+    let size = vec2<i32>(textureDimensions(state));
+    return textureLoad(state, (p + size) % size, 0).rg;
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn seed(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<u32>(textureDimensions(state));
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+    // Drops of B in cells of 12 pixels.
+    var b = 0.0;
+    if (hash(id.xy / 12u) < uniforms.seeds) {
+        b = 1.0;
+    }
+    textureStore(next, vec2<i32>(id.xy), vec4<f32>(1.0, b, 0.0, 1.0));
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn react(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<u32>(textureDimensions(state));
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+    let p = vec2<i32>(id.xy);
+    let here = chemicals(p);
+    let laplacian = 0.2 * (chemicals(p + vec2<i32>(1, 0)) + chemicals(p - vec2<i32>(1, 0))
+            + chemicals(p + vec2<i32>(0, 1)) + chemicals(p - vec2<i32>(0, 1)))
+        + 0.05 * (chemicals(p + vec2<i32>(1, 1)) + chemicals(p - vec2<i32>(1, 1))
+            + chemicals(p + vec2<i32>(1, -1)) + chemicals(p - vec2<i32>(1, -1)))
+        - here;
+    let reaction = here.x * here.y * here.y;
+    let a = here.x + laplacian.x - reaction + uniforms.feed * (1.0 - here.x);
+    let b = here.y + 0.5 * laplacian.y + reaction - (uniforms.kill + uniforms.feed) * here.y;
+    textureStore(next, p, vec4<f32>(clamp(a, 0.0, 1.0), clamp(b, 0.0, 1.0), 0.0, 1.0));
+}
@@ -0,0 +1,24 @@
+// Shows the trails pass, with the values that built up in its float buffer squashed back into
+// displayable range.
+
+@group(1) @binding(1)
+var trails_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var trails_sampler: sampler;
+
+struct Uniforms {
+    exposure: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+struct FragmentInput {
+    #import bevy_sprite::mesh2d_vertex_output
+};
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    let color = textureSample(trails_texture, trails_sampler, in.uv).rgb * uniforms.exposure;
+    return vec4<f32>(color / (1.0 + color), 1.0);
+}
@@ -0,0 +1,38 @@
+// Fades out what it drew the frame before and adds a few orbiting lights on top, leaving trails
+// behind them.
+
+struct Uniforms {
+    time: f32,
+    resolution: vec2<f32>,
+    speed: f32,
+    fade: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> uniforms: Uniforms;
+// This pass's own buffer, from the frame before.
+@group(1) @binding(1)
+var previous_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var previous_sampler: sampler;
+
+struct FragmentInput {
+    #import bevy_sprite::mesh2d_vertex_output
+};
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    let aspect = uniforms.resolution.x / max(uniforms.resolution.y, 1.0);
+    let p = (in.uv - 0.5) * vec2<f32>(aspect, 1.0);
+    let t = uniforms.time * uniforms.speed;
+
+    var color = textureSample(previous_texture, previous_sampler, in.uv).rgb * uniforms.fade;
+    for (var i = 0; i < 5; i += 1) {
+        let phase = f32(i) * 1.2566371;
+        let center = vec2<f32>(cos(t + phase), sin(t * 1.3 + phase * 2.0)) * 0.35;
+        let light = 0.0004 / max(dot(p - center, p - center), 0.00001);
+        let tint = 0.5 + 0.5 * cos(vec3<f32>(0.0, 2.1, 4.2) + phase);
+        color += tint * light;
+    }
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,4 @@
+// Name: crossfade
+vec4 transition(vec2 uv) {
+    return mix(getFromColor(uv), getToColor(uv), progress);
+}
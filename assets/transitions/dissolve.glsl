@@ -0,0 +1,13 @@
+// Name: dissolve
+uniform float grain; // = 512.0
+uniform float smoothness; // = 0.05
+
+float rand(vec2 co) {
+    return fract(sin(dot(co, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+vec4 transition(vec2 uv) {
+    float r = rand(floor(uv * vec2(ratio, 1.0) * grain));
+    float m = 1.0 - smoothstep(-smoothness, 0.0, r - progress * (1.0 + smoothness));
+    return mix(getFromColor(uv), getToColor(uv), m);
+}
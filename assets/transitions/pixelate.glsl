@@ -0,0 +1,12 @@
+// Name: pixelate
+uniform ivec2 squares_min; // = ivec2(20)
+uniform int steps; // = 50
+
+vec4 transition(vec2 uv) {
+    // the squares are largest halfway through
+    float d = min(progress, 1.0 - progress);
+    float dist = steps > 0 ? ceil(d * float(steps)) / float(steps) : d;
+    vec2 square_size = 2.0 * dist / vec2(squares_min);
+    vec2 p = dist > 0.0 ? (floor(uv / square_size) + 0.5) * square_size : uv;
+    return mix(getFromColor(p), getToColor(p), progress);
+}
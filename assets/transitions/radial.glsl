@@ -0,0 +1,10 @@
+// Name: radial
+uniform float smoothness; // = 1.0
+
+const float PI = 3.141592653589;
+
+vec4 transition(vec2 uv) {
+    vec2 p = uv * 2.0 - 1.0;
+    float m = smoothstep(0.0, smoothness, atan(p.y, p.x) - (progress - 0.5) * PI * 2.5);
+    return mix(getToColor(uv), getFromColor(uv), m);
+}
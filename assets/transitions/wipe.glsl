@@ -0,0 +1,12 @@
+// Name: wipe
+uniform vec2 direction; // = vec2(1.0, 0.0)
+uniform float smoothness; // = 0.1
+
+vec4 transition(vec2 uv) {
+    vec2 v = normalize(direction);
+    v /= abs(v.x) + abs(v.y);
+    // distance along the wipe direction, 0 where the wipe starts and 1 where it ends
+    float d = dot(uv - 0.5, v) + 0.5;
+    float m = 1.0 - smoothstep(-smoothness, 0.0, d - progress * (1.0 + smoothness));
+    return mix(getFromColor(uv), getToColor(uv), m);
+}
@@ -0,0 +1,191 @@
+use bevy::app::{App, CoreStage, Plugin};
+use bevy::asset::{Assets, Handle};
+use bevy::ecs::prelude::*;
+use bevy::math::Vec2;
+use bevy::render::camera::{Camera, CameraUpdateSystem, RenderTarget};
+use bevy::render::render_resource::{
+    Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+};
+use bevy::render::texture::{BevyDefault, Image};
+use bevy::utils::default;
+use bevy::window::{Window, WindowCreated, WindowResized, WindowScaleFactorChanged, Windows};
+
+/// Render layer reserved for the entities that present the canvas to the windows, so that they
+/// never show up in a wallpaper's own cameras and vice versa.
+pub const PRESENTATION_LAYER: u8 = 31;
+
+/// Render layer the wallpaper being switched away from is moved to while it renders into the
+/// outgoing image, so that it stays out of the incoming wallpaper's cameras.
+pub const OUTGOING_LAYER: u8 = 30;
+
+#[derive(Default)]
+pub struct CanvasPlugin;
+
+impl Plugin for CanvasPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WallpaperCanvas>()
+            .add_system_to_stage(CoreStage::PostUpdate, resize_canvas.label(ResizeCanvas))
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                target_wallpaper_cameras
+                    .label(TargetWallpaperCameras)
+                    .before(CameraUpdateSystem),
+            );
+    }
+}
+
+/// Label for the system that resizes the canvas to the primary window at the render scale.
+#[derive(SystemLabel)]
+pub struct ResizeCanvas;
+
+/// Label for the system that points newly spawned wallpaper cameras at the live canvas. Cameras
+/// spawned with an image as their target keep drawing to it.
+#[derive(SystemLabel)]
+pub struct TargetWallpaperCameras;
+
+/// Cameras that draw to a window rather than the canvas.
+#[derive(Component)]
+pub struct PresentationCamera;
+
+/// The offscreen images wallpapers render into instead of the windows.
+///
+/// There are two so that the outgoing wallpaper can keep rendering into one while the incoming one
+/// renders into the other. They're a [`scale`](WallpaperCanvas::scale) of the primary window's
+/// resolution, and stretched over the windows when they're presented.
+#[derive(Resource)]
+pub struct WallpaperCanvas {
+    images: [Handle<Image>; 2],
+    live: usize,
+    scale: f32,
+}
+
+impl FromWorld for WallpaperCanvas {
+    fn from_world(world: &mut World) -> Self {
+        let mut images = world.resource_mut::<Assets<Image>>();
+        WallpaperCanvas {
+            images: [images.add(canvas_image()), images.add(canvas_image())],
+            live: 0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl WallpaperCanvas {
+    /// The image the active wallpaper renders into.
+    pub fn live(&self) -> &Handle<Image> {
+        &self.images[self.live]
+    }
+
+    /// The image the previous wallpaper renders into while it's transitioned away from.
+    pub fn outgoing(&self) -> &Handle<Image> {
+        &self.images[1 - self.live]
+    }
+
+    /// Makes the outgoing image live, leaving the current live image untouched.
+    pub fn swap(&mut self) {
+        self.live = 1 - self.live;
+    }
+
+    /// The fraction of the primary window's resolution the canvas renders at.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Renders at a different fraction of the window's resolution from the end of this update on.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+    }
+
+    /// The resolution the canvas renders at for `window`. Wallpaper cameras draw to it with one
+    /// world unit per pixel.
+    pub fn resolution(&self, window: &Window) -> Vec2 {
+        let window = Vec2::new(
+            window.physical_width() as f32,
+            window.physical_height() as f32,
+        );
+        (window * self.scale).round().max(Vec2::ONE)
+    }
+
+    /// Resizes both images to the resolution for `window`.
+    pub fn fit(&self, images: &mut Assets<Image>, window: &Window) {
+        let resolution = self.resolution(window);
+        let size = Extent3d {
+            width: resolution.x as u32,
+            height: resolution.y as u32,
+            ..default()
+        };
+        self.resize(images, size);
+    }
+
+    /// Resizes both images, which clears them.
+    pub fn resize(&self, images: &mut Assets<Image>, size: Extent3d) {
+        for handle in &self.images {
+            if let Some(image) = images.get_mut(handle) {
+                if image.texture_descriptor.size != size {
+                    image.resize(size);
+                }
+            }
+        }
+    }
+}
+
+fn canvas_image() -> Image {
+    let size = Extent3d {
+        width: 1,
+        height: 1,
+        ..default()
+    };
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("wallpaper_canvas"),
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::bevy_default(),
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC
+                | TextureUsages::RENDER_ATTACHMENT,
+        },
+        ..default()
+    };
+    image.resize(size);
+    image
+}
+
+fn resize_canvas(
+    windows: Res<Windows>,
+    canvas: Res<WallpaperCanvas>,
+    mut images: ResMut<Assets<Image>>,
+    mut created: EventReader<WindowCreated>,
+    mut resized: EventReader<WindowResized>,
+    mut scale_factor_changed: EventReader<WindowScaleFactorChanged>,
+    mut fitted_scale: Local<f32>,
+) {
+    let primary_changed = created.iter().any(|event| event.id.is_primary())
+        | resized.iter().any(|event| event.id.is_primary())
+        | scale_factor_changed
+            .iter()
+            .any(|event| event.id.is_primary());
+    if !primary_changed && *fitted_scale == canvas.scale {
+        return;
+    }
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+
+    canvas.fit(&mut images, window);
+    *fitted_scale = canvas.scale;
+}
+
+fn target_wallpaper_cameras(
+    canvas: Res<WallpaperCanvas>,
+    mut cameras: Query<&mut Camera, (Added<Camera>, Without<PresentationCamera>)>,
+) {
+    for mut camera in &mut cameras {
+        if let RenderTarget::Window(_) = camera.target {
+            camera.target = RenderTarget::Image(canvas.live().clone());
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use std::path::{Path, PathBuf};
+
+use bevy::math::UVec2;
+use bevy::render::color::Color;
+use bevy::utils::{Duration, HashMap};
+
+use crate::control::ControlCommand;
+use crate::quality::Quality;
+use crate::wallpaper::FitMode;
+use crate::wallpaper_render_plugin::HeadlessSettings;
+
+const USAGE: &str = "\
+usage: desktop [IMAGE|VIDEO|FOLDER|MANIFEST...] [options]
+       desktop export WALLPAPER -o OUTPUT [export options] [options]
+       desktop COMMAND [ARGUMENTS]
+
+Folders are played as image sequences, .wallpaper.ron manifests naming a shader draw it, and
+.gltf and .glb scenes play their animations. Exports render a built in wallpaper like plasma, or
+one of those files, without showing it. Commands are carried out by the running wallpaper.
+
+commands:
+    screenshot [PATH]   save what the wallpaper shows to PATH (default: screenshot.png)
+    pause, resume       stop and restart the wallpaper's animation
+    speed FACTOR        animate this many times as fast as real time
+    next, previous      switch to the next or previous wallpaper in the playlist
+    goto INDEX          switch to the wallpaper at INDEX in the playlist, counting from 0
+    reshuffle           shuffle the playlist again and start over
+    set NAME=VALUE      set a parameter of the wallpaper until it's reset
+    reset [NAME]        set a parameter, or every one, back to what it was
+    seek SECONDS        jump to this far into the video wallpaper
+    video-speed FACTOR  play the video wallpaper this many times as fast
+    preview WALLPAPER   print the path of an image of WALLPAPER, rendering it first unless its
+                        manifest names one. Rendered ones have an animated .apng next to them
+    previews            list the previews found so far, which the running wallpaper renders in
+                        the background for wallpapers whose manifest doesn't name one
+
+options:
+    --fit MODE          fill, fit, stretch, center or tile (default: fill)
+    --background COLOR  hex color shown around images that don't cover the screen
+    --interval SECONDS  switch to the next image after this long (default: as in the config
+                        file, or never)
+    --shuffle           play the images in a random order
+    --switch-on-wake    switch to the next image when the computer wakes from sleep
+    --transition NAME   crossfade, wipe, dissolve, radial, pixelate, another transition in
+                        assets/transitions, or none for a hard cut (default: crossfade, or as in
+                        the config file)
+    --transition-time SECS
+                        how long switching images takes (default: 1)
+    --speed FACTOR      video playback speed (default: 1)
+    --fps RATE          frame rate of image sequences (default: 30)
+    --no-loop           stop videos on their last frame instead of looping
+    --quality PRESET    low, medium, high or ultra, for every wallpaper and power state
+                        (default: high, or as in the config file)
+    --set NAME=VALUE    set a wallpaper parameter, overriding the config file
+    --config PATH       read settings from this file instead of the default one
+    --fixed-step SECS   advance wallpaper time by this much every frame instead of in real time
+    --seed NUMBER       shuffle the same way on every run (default: 0 with --fixed-step,
+                        --headless or export, random otherwise)
+    --headless DIR      render offscreen and write PNG frames to this folder
+    --size WxH          size of the frames rendered with --headless (default: 1920x1080)
+    --frames COUNT      number of frames rendered with --headless (default: 1)
+    --frame-time SECS   wallpaper time between frames rendered with --headless (default: 1/30)
+
+export options:
+    -o, --output PATH   a .gif or .png animation, an .mp4, .webm, .mov or .mkv video encoded with
+                        ffmpeg when built with it, or a folder for PNG frames
+    --duration TIME     how much to render, like 10s or 500ms (default: 10s)
+    --fps RATE          frames per second (default: 30)
+    --size WxH          (default: 1920x1080)";
+
+/// Options passed on the command line.
+#[derive(Debug)]
+pub struct Cli {
+    /// Images, videos and shader wallpaper manifests to rotate through instead of the built in
+    /// wallpaper.
+    pub images: Vec<PathBuf>,
+    pub fit: FitMode,
+    pub background: Color,
+    pub interval: Option<Duration>,
+    pub shuffle: bool,
+    pub switch_on_wake: bool,
+    pub transition: Option<String>,
+    pub transition_time: Option<Duration>,
+    pub speed: f32,
+    pub frame_rate: f32,
+    pub looping: bool,
+    pub quality: Option<Quality>,
+    /// Wallpaper parameters set with `--set`, by name.
+    pub parameters: HashMap<String, String>,
+    pub config: Option<PathBuf>,
+    /// Wallpaper time per update, for runs that repeat exactly.
+    pub fixed_step: Option<Duration>,
+    pub seed: Option<u64>,
+    /// Render offscreen into this folder of PNG frames instead of becoming the wallpaper.
+    pub headless: Option<PathBuf>,
+    /// Render offscreen into this video, animation or folder of frames, for `desktop export`.
+    pub export: Option<PathBuf>,
+    /// What gets rendered offscreen.
+    pub offscreen: HeadlessSettings,
+    /// A built in wallpaper to play instead of the default one.
+    pub wallpaper: Option<String>,
+    /// Send this to the running wallpaper instead of starting one.
+    pub control: Option<ControlCommand>,
+    /// Where the screenshot asked for with `desktop screenshot` is moved to.
+    pub screenshot: Option<PathBuf>,
+}
+
+impl Default for Cli {
+    fn default() -> Self {
+        Cli {
+            images: Vec::new(),
+            fit: FitMode::default(),
+            background: Color::BLACK,
+            interval: None,
+            shuffle: false,
+            switch_on_wake: false,
+            transition: None,
+            transition_time: None,
+            speed: 1.0,
+            frame_rate: 30.0,
+            looping: true,
+            quality: None,
+            parameters: HashMap::default(),
+            config: None,
+            fixed_step: None,
+            seed: None,
+            headless: None,
+            export: None,
+            offscreen: HeadlessSettings::default(),
+            wallpaper: None,
+            control: None,
+            screenshot: None,
+        }
+    }
+}
+
+impl Cli {
+    /// Parses the process arguments, exiting with usage information if they're invalid.
+    pub fn parse() -> Self {
+        match Self::try_parse(std::env::args().skip(1)) {
+            Ok(cli) => cli,
+            Err(err) => {
+                eprintln!("error: {err}\n\n{USAGE}");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    /// Whether this run renders offscreen instead of becoming the wallpaper.
+    pub fn is_offscreen(&self) -> bool {
+        self.headless.is_some() || self.export.is_some()
+    }
+
+    fn try_parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut cli = Cli::default();
+        let mut args = args.peekable();
+        if args.next_if(|arg| arg == "screenshot").is_some() {
+            let path = args.next().unwrap_or_else(|| "screenshot.png".to_string());
+            if let Some(arg) = args
+                .next()
+                .or_else(|| path.starts_with('-').then(|| path.clone()))
+            {
+                return Err(format!("unexpected {arg}, screenshot only takes a path"));
+            }
+            cli.control = Some(ControlCommand::Screenshot);
+            cli.screenshot = Some(PathBuf::from(path));
+            return Ok(cli);
+        }
+        if let Some(name) = args.next_if(|arg| ControlCommand::NAMES.contains(&arg.as_str())) {
+            let line = std::iter::once(name)
+                .chain(args)
+                .collect::<Vec<_>>()
+                .join(" ");
+            cli.control = Some(ControlCommand::parse(&line)?);
+            return Ok(cli);
+        }
+        let exporting = args.next_if(|arg| arg == "export").is_some();
+        let mut output = None;
+        let mut duration = Duration::from_secs(10);
+        let mut export_frame_rate = 30.0;
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "-o" | "--output" if exporting => output = Some(PathBuf::from(value()?)),
+                "--duration" if exporting => duration = parse_duration(&value()?)?,
+                "--fps" if exporting => export_frame_rate = parse_frame_rate(&value()?)?,
+                "--fit" => cli.fit = value()?.parse()?,
+                "--background" => {
+                    let value = value()?;
+                    cli.background = Color::hex(value.trim_start_matches('#'))
+                        .map_err(|_| format!("invalid color {value:?}"))?;
+                }
+                "--interval" => {
+                    let value = value()?;
+                    let seconds: f32 = value
+                        .parse()
+                        .map_err(|_| format!("invalid interval {value:?}"))?;
+                    cli.interval = Some(Duration::from_secs_f32(seconds));
+                }
+                "--shuffle" => cli.shuffle = true,
+                "--switch-on-wake" => cli.switch_on_wake = true,
+                "--transition" => cli.transition = Some(value()?),
+                "--transition-time" => {
+                    let value = value()?;
+                    let seconds: f32 = value
+                        .parse()
+                        .ok()
+                        .filter(|seconds: &f32| *seconds > 0.0)
+                        .ok_or_else(|| format!("invalid transition time {value:?}"))?;
+                    cli.transition_time = Some(Duration::from_secs_f32(seconds));
+                }
+                "--speed" => {
+                    let value = value()?;
+                    cli.speed = value
+                        .parse()
+                        .ok()
+                        .filter(|speed: &f32| *speed >= 0.0)
+                        .ok_or_else(|| format!("invalid speed {value:?}"))?;
+                }
+                "--fps" => cli.frame_rate = parse_frame_rate(&value()?)?,
+                "--no-loop" => cli.looping = false,
+                "--quality" => cli.quality = Some(value()?.parse()?),
+                "--set" => {
+                    let value = value()?;
+                    let (name, value) = value
+                        .split_once('=')
+                        .ok_or_else(|| format!("expected NAME=VALUE, got {value:?}"))?;
+                    cli.parameters.insert(name.to_string(), value.to_string());
+                }
+                "--config" => cli.config = Some(PathBuf::from(value()?)),
+                "--fixed-step" => {
+                    let value = value()?;
+                    let seconds: f32 = value
+                        .parse()
+                        .ok()
+                        .filter(|seconds: &f32| *seconds >= 0.0)
+                        .ok_or_else(|| format!("invalid step {value:?}"))?;
+                    cli.fixed_step = Some(Duration::from_secs_f32(seconds));
+                }
+                "--seed" => {
+                    let value = value()?;
+                    cli.seed = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid seed {value:?}"))?,
+                    );
+                }
+                "--headless" => cli.headless = Some(PathBuf::from(value()?)),
+                "--size" => {
+                    let value = value()?;
+                    cli.offscreen.size = value
+                        .split_once('x')
+                        .and_then(|(width, height)| {
+                            Some((width.parse().ok()?, height.parse().ok()?))
+                        })
+                        .map(|(width, height)| UVec2::new(width, height))
+                        .filter(|size| size.min_element() > 0)
+                        .ok_or_else(|| format!("invalid size {value:?}"))?;
+                }
+                "--frames" => {
+                    let value = value()?;
+                    cli.offscreen.frames = value
+                        .parse()
+                        .map_err(|_| format!("invalid frame count {value:?}"))?;
+                }
+                "--frame-time" => {
+                    let value = value()?;
+                    let seconds: f32 = value
+                        .parse()
+                        .ok()
+                        .filter(|seconds: &f32| *seconds >= 0.0)
+                        .ok_or_else(|| format!("invalid frame time {value:?}"))?;
+                    cli.offscreen.frame_time = Duration::from_secs_f32(seconds);
+                }
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                flag if flag.starts_with('-') => return Err(format!("unknown option {flag}")),
+                _ if exporting && (cli.wallpaper.is_some() || !cli.images.is_empty()) => {
+                    return Err("export takes one wallpaper".to_string());
+                }
+                name if exporting && !Path::new(name).exists() => {
+                    cli.wallpaper = Some(name.to_string());
+                }
+                path => {
+                    // Asset paths are relative to the assets folder, so pin down the ones given on
+                    // the command line before they get there.
+                    let path = std::fs::canonicalize(path)
+                        .map_err(|err| format!("can't open {path}: {err}"))?;
+                    cli.images.push(path);
+                }
+            }
+        }
+        if exporting {
+            if cli.wallpaper.is_none() && cli.images.is_empty() {
+                return Err("export needs a wallpaper".to_string());
+            }
+            let output =
+                output.ok_or_else(|| "export needs an output, given with -o".to_string())?;
+            cli.offscreen.frames = (duration.as_secs_f32() * export_frame_rate)
+                .round()
+                .max(1.0) as u32;
+            cli.offscreen.frame_time = Duration::from_secs_f32(1.0 / export_frame_rate);
+            cli.export = Some(output);
+        }
+        Ok(cli)
+    }
+}
+
+fn parse_frame_rate(value: &str) -> Result<f32, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|rate: &f32| *rate > 0.0)
+        .ok_or_else(|| format!("invalid frame rate {value:?}"))
+}
+
+/// Parses a duration in seconds, with an optional `s` or `ms` suffix.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, scale) = match value.strip_suffix("ms") {
+        Some(millis) => (millis, 0.001),
+        None => (value.strip_suffix('s').unwrap_or(value), 1.0),
+    };
+    number
+        .parse::<f32>()
+        .ok()
+        .filter(|number| *number > 0.0)
+        .map(|number| Duration::from_secs_f32(number * scale))
+        .ok_or_else(|| format!("invalid duration {value:?}"))
+}
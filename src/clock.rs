@@ -0,0 +1,118 @@
+use bevy::app::{App, CoreStage, Plugin};
+use bevy::ecs::prelude::*;
+use bevy::time::{Time, TimeSystem};
+use bevy::utils::Duration;
+
+#[derive(Default)]
+pub struct ClockPlugin;
+
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WallpaperClock>().add_system_to_stage(
+            CoreStage::First,
+            tick_clock.label(WallpaperClockSystem).after(TimeSystem),
+        );
+    }
+}
+
+/// Label for the system that advances the [`WallpaperClock`]. Anything reading the clock in
+/// [`CoreStage::First`] should run after it.
+#[derive(SystemLabel)]
+pub struct WallpaperClockSystem;
+
+/// The time wallpapers animate by.
+///
+/// It follows [`Time`], but can be paused and sped up or slowed down without affecting timers
+/// that have to keep real time, like the playlist's. Those tick by [`WallpaperClock::frame_delta`]
+/// so that they follow a fixed step too.
+#[derive(Debug, Resource)]
+pub struct WallpaperClock {
+    elapsed: Duration,
+    delta: Duration,
+    frame_delta: Duration,
+    speed: f32,
+    paused: bool,
+    step: Option<Duration>,
+}
+
+impl Default for WallpaperClock {
+    fn default() -> Self {
+        WallpaperClock {
+            elapsed: Duration::ZERO,
+            delta: Duration::ZERO,
+            frame_delta: Duration::ZERO,
+            speed: 1.0,
+            paused: false,
+            step: None,
+        }
+    }
+}
+
+impl WallpaperClock {
+    /// Wallpaper time since startup.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.elapsed.as_secs_f32()
+    }
+
+    /// How far the clock advanced in the last update, which is zero while paused.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// How much time the last update stood for regardless of pausing and speed: the fixed step if
+    /// there is one, otherwise the real time that passed.
+    pub fn frame_delta(&self) -> Duration {
+        self.frame_delta
+    }
+
+    /// Sets how many seconds of wallpaper time pass per real second.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Advance by exactly `step` every update instead of by the real time that passed, so that
+    /// every run of the same updates renders the same frames however long they take.
+    pub fn set_step(&mut self, step: Option<Duration>) {
+        self.step = step;
+    }
+
+    /// How long to wait in real time for `duration` of wallpaper time to pass, or `None` if it
+    /// never will because the clock is stopped.
+    pub fn real_duration(&self, duration: Duration) -> Option<Duration> {
+        if self.paused || self.speed == 0.0 {
+            None
+        } else {
+            Some(duration.div_f32(self.speed))
+        }
+    }
+}
+
+fn tick_clock(time: Res<Time>, mut clock: ResMut<WallpaperClock>) {
+    clock.frame_delta = clock.step.unwrap_or_else(|| time.delta());
+    clock.delta = if clock.paused {
+        Duration::ZERO
+    } else {
+        clock.frame_delta.mul_f32(clock.speed)
+    };
+    clock.elapsed += clock.delta;
+}
@@ -0,0 +1,190 @@
+use std::path::{Path, PathBuf};
+
+use bevy::utils::{tracing::warn, HashMap};
+use serde::Deserialize;
+
+use crate::post_process::PostProcessDeclaration;
+use crate::power::PowerState;
+use crate::quality::Quality;
+use crate::transition::DEFAULT_TRANSITION;
+
+/// Settings read from the config file:
+///
+/// ```ron
+/// (
+///     quality: Some(Medium),
+///     wallpaper_quality: {
+///         "clouds": Low,
+///     },
+///     power_quality: {
+///         Battery: Medium,
+///         Saver: Low,
+///     },
+///     parameters: {
+///         "cube_demo": {
+///             "speed": 4.0,
+///             "pattern": "textures/stripes.png",
+///         },
+///     },
+///     post: {
+///         "plasma": (tonemapping: Aces, grain: Some((intensity: 0.02))),
+///     },
+///     static_on: Some(Battery),
+///     render_scale: Some(1.0),
+///     frame_budget: Some(16.0),
+///     interval: Some(600.0),
+///     switch_on_wake: true,
+///     transition: "wipe",
+///     transition_time: 2.0,
+/// )
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The quality preset, unless one is given on the command line.
+    pub quality: Option<Quality>,
+    /// Quality presets by wallpaper name, replacing the one above while that wallpaper is up.
+    pub wallpaper_quality: HashMap<String, Quality>,
+    /// The highest quality preset in each power state, which wallpapers set higher are lowered to.
+    pub power_quality: HashMap<PowerState, Quality>,
+    /// Overrides for the parameters wallpapers declare in their manifests, by wallpaper name and
+    /// then parameter name.
+    pub parameters: HashMap<String, HashMap<String, ron::Value>>,
+    /// Post-processing by wallpaper name, replacing what the wallpaper's manifest declares.
+    pub post: HashMap<String, PostProcessDeclaration>,
+    /// The power state from which on the wallpaper stops animating and Windows shows a still
+    /// frame of it instead, `Battery` or `Saver`. `None` keeps it animating.
+    pub static_on: Option<PowerState>,
+    /// The fraction of the screen's resolution wallpapers render at, from more than 0 to 1. With
+    /// a frame budget it's the most they render at. `None` goes by the quality preset.
+    pub render_scale: Option<f32>,
+    /// Milliseconds frames may take on the CPU or the GPU, which the render scale is lowered to
+    /// keep them under. Waiting for vsync doesn't count. `None` keeps the render scale as it is.
+    pub frame_budget: Option<f32>,
+    /// Seconds each wallpaper in a playlist stays up, unless `--interval` is given. `None` keeps
+    /// it up until it's switched with a command or on wake.
+    pub interval: Option<f32>,
+    /// Whether waking the computer from sleep switches to the next wallpaper in the playlist.
+    pub switch_on_wake: bool,
+    /// The transition between wallpapers in a playlist, the name of one in `assets/transitions` or
+    /// `"none"` for a hard cut, unless `--transition` is given.
+    pub transition: String,
+    /// Seconds transitions take, unless `--transition-time` is given.
+    pub transition_time: f32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            quality: None,
+            wallpaper_quality: HashMap::default(),
+            power_quality: HashMap::from_iter([
+                (PowerState::Battery, Quality::Medium),
+                (PowerState::Saver, Quality::Low),
+            ]),
+            parameters: HashMap::default(),
+            post: HashMap::default(),
+            static_on: Some(PowerState::Saver),
+            render_scale: None,
+            frame_budget: None,
+            interval: None,
+            switch_on_wake: false,
+            transition: DEFAULT_TRANSITION.to_string(),
+            transition_time: 1.0,
+        }
+    }
+}
+
+impl Config {
+    /// Where the config file lives unless another one is given on the command line.
+    pub fn default_path() -> Option<PathBuf> {
+        let app_data = std::env::var_os("APPDATA")?;
+        Some(Path::new(&app_data).join("desktop").join("config.ron"))
+    }
+
+    /// The config file [`Config::load`] reads for `path`, if there's one to read.
+    pub fn path(path: Option<&Path>) -> Option<PathBuf> {
+        match path {
+            Some(path) => Some(path.to_owned()),
+            None => Self::default_path().filter(|path| path.exists()),
+        }
+    }
+
+    /// Reads the config file at `path`, or the one at the default path if there is one.
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let Some(path) = Self::path(path) else {
+            return Ok(Config::default());
+        };
+        let source = std::fs::read_to_string(&path)
+            .map_err(|err| format!("can't read {}: {err}", path.display()))?;
+        let config: Config = ron::from_str(&source)
+            .map_err(|err| format!("invalid config {}: {err}", path.display()))?;
+        if let Some(scale) = config
+            .render_scale
+            .filter(|scale| !(*scale > 0.0 && *scale <= 1.0))
+        {
+            return Err(format!(
+                "invalid render scale {scale} in {}, expected more than 0 and at most 1",
+                path.display()
+            ));
+        }
+        if let Some(budget) = config.frame_budget.filter(|budget| *budget <= 0.0) {
+            return Err(format!(
+                "invalid frame budget {budget} in {}, expected more than 0",
+                path.display()
+            ));
+        }
+        if let Some(interval) = config.interval.filter(|interval| *interval <= 0.0) {
+            return Err(format!(
+                "invalid interval {interval} in {}, expected more than 0",
+                path.display()
+            ));
+        }
+        if config.transition_time <= 0.0 {
+            return Err(format!(
+                "invalid transition time {} in {}, expected more than 0",
+                config.transition_time,
+                path.display()
+            ));
+        }
+        for (wallpaper, post) in &config.post {
+            post.validate().map_err(|err| {
+                format!(
+                    "invalid post-processing for {wallpaper:?} in {}: {err}",
+                    path.display()
+                )
+            })?;
+        }
+        Ok(config)
+    }
+
+    /// The parameter overrides by wallpaper, in the same form they're given on the command line.
+    /// Values that can't be written that way are left out with a warning.
+    pub fn parameter_overrides(&self) -> HashMap<String, HashMap<String, String>> {
+        self.parameters
+            .iter()
+            .map(|(wallpaper, parameters)| {
+                let parameters = parameters
+                    .iter()
+                    .filter_map(|(name, value)| {
+                        let value = match value {
+                            ron::Value::String(value) => value.clone(),
+                            ron::Value::Number(number) => number.into_f64().to_string(),
+                            ron::Value::Bool(value) => value.to_string(),
+                            _ => {
+                                warn!(
+                                    "Ignoring config value for {:?} of {:?}, expected a string, \
+                                     number or boolean",
+                                    name, wallpaper
+                                );
+                                return None;
+                            }
+                        };
+                        Some((name.clone(), value))
+                    })
+                    .collect();
+                (wallpaper.clone(), parameters)
+            })
+            .collect()
+    }
+}
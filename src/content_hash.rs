@@ -0,0 +1,24 @@
+/// 64 bit FNV-1a, which unlike the standard library's hashers is the same on every build, as a
+/// cache key has to be.
+pub struct ContentHash(u64);
+
+impl Default for ContentHash {
+    fn default() -> Self {
+        ContentHash(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl ContentHash {
+    pub fn write(&mut self, bytes: &[u8]) {
+        // The length keeps the end of one file from running into the next.
+        for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    /// The hash as a file name.
+    pub fn key(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
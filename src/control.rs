@@ -0,0 +1,353 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+
+use bevy::app::{App, Plugin};
+use bevy::ecs::prelude::*;
+use bevy::utils::{
+    tracing::{info, warn},
+    Duration,
+};
+use crossbeam_channel::Receiver;
+
+use crate::clock::WallpaperClock;
+use crate::parameters::{ParameterCommand, Parameters};
+use crate::playlist::{Playlist, PlaylistCommand};
+use crate::previews::Previews;
+use crate::screenshot::Screenshots;
+use crate::static_fallback::StaticFallback;
+use crate::wallpaper::{VideoCommand, WallpaperRegistry};
+use crate::wallpaper_render_plugin::EventLoopWaker;
+
+/// Where the running wallpaper listens for commands, one line per connection, which it answers
+/// with `ok` and what was asked for, if anything, or `error: ` and what went wrong.
+const CONTROL_ADDRESS: &str = "127.0.0.1:47219";
+
+/// How long the running wallpaper waits for a command once something connects.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long `desktop` waits for the running wallpaper to answer a command, which for a preview
+/// that has to be rendered first includes exporting it.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// What other runs of `desktop` can ask the running wallpaper to do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlCommand {
+    /// Save the live canvas to a PNG file in the screenshots folder, answering with its path.
+    /// Anything can connect to the control port, so where the file goes isn't up to it.
+    Screenshot,
+    /// Stop the wallpaper clock.
+    Pause,
+    Resume,
+    /// Run the wallpaper clock this many times as fast as real time.
+    Speed(f32),
+    Playlist(PlaylistCommand),
+    Parameter(ParameterCommand),
+    Video(VideoCommand),
+    /// Find or render a preview of the named wallpaper, answering with the path of the image.
+    Preview(String),
+    /// List the previews found so far, answering with [`PREVIEW_SEPARATOR`] between them and a
+    /// tab between each wallpaper's name and the path of its preview.
+    Previews,
+}
+
+/// What the answer to [`ControlCommand::Previews`] has between previews, which can't be in a name
+/// or a path on Windows.
+pub const PREVIEW_SEPARATOR: char = '|';
+
+impl ControlCommand {
+    /// The names commands start with.
+    pub const NAMES: &'static [&'static str] = &[
+        "screenshot",
+        "pause",
+        "resume",
+        "speed",
+        "next",
+        "previous",
+        "goto",
+        "reshuffle",
+        "set",
+        "reset",
+        "seek",
+        "video-speed",
+        "preview",
+        "previews",
+    ];
+
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let (name, argument) = line.split_once(' ').unwrap_or((line, ""));
+        match (name, argument) {
+            ("screenshot", "") => Ok(ControlCommand::Screenshot),
+            ("pause", "") => Ok(ControlCommand::Pause),
+            ("resume", "") => Ok(ControlCommand::Resume),
+            ("speed", speed) => speed
+                .parse()
+                .ok()
+                .filter(|speed: &f32| *speed >= 0.0)
+                .map(ControlCommand::Speed)
+                .ok_or_else(|| format!("invalid speed {speed:?}")),
+            ("next", "") => Ok(ControlCommand::Playlist(PlaylistCommand::Next)),
+            ("previous", "") => Ok(ControlCommand::Playlist(PlaylistCommand::Previous)),
+            ("goto", index) => index
+                .parse()
+                .map(|index| ControlCommand::Playlist(PlaylistCommand::Goto(index)))
+                .map_err(|_| format!("invalid playlist index {index:?}")),
+            ("reshuffle", "") => Ok(ControlCommand::Playlist(PlaylistCommand::Reshuffle)),
+            ("set", value) => {
+                let (name, value) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("expected NAME=VALUE, got {value:?}"))?;
+                Ok(ControlCommand::Parameter(ParameterCommand::Set {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                }))
+            }
+            ("reset", "") => Ok(ControlCommand::Parameter(ParameterCommand::ResetAll)),
+            ("reset", name) => Ok(ControlCommand::Parameter(ParameterCommand::Reset {
+                name: name.to_string(),
+            })),
+            ("seek", seconds) => seconds
+                .parse()
+                .ok()
+                .filter(|seconds: &f32| *seconds >= 0.0)
+                .map(|seconds| {
+                    ControlCommand::Video(VideoCommand::Seek(Duration::from_secs_f32(seconds)))
+                })
+                .ok_or_else(|| format!("invalid position {seconds:?}")),
+            ("video-speed", speed) => speed
+                .parse()
+                .ok()
+                .filter(|speed: &f32| *speed >= 0.0)
+                .map(|speed| ControlCommand::Video(VideoCommand::SetSpeed(speed)))
+                .ok_or_else(|| format!("invalid speed {speed:?}")),
+            ("preview", name) if !name.is_empty() => Ok(ControlCommand::Preview(name.to_string())),
+            ("previews", "") => Ok(ControlCommand::Previews),
+            _ => Err(format!("unknown command {line:?}")),
+        }
+    }
+
+    fn to_line(&self) -> String {
+        match self {
+            ControlCommand::Screenshot => "screenshot".to_string(),
+            ControlCommand::Pause => "pause".to_string(),
+            ControlCommand::Resume => "resume".to_string(),
+            ControlCommand::Speed(speed) => format!("speed {speed}"),
+            ControlCommand::Playlist(PlaylistCommand::Next) => "next".to_string(),
+            ControlCommand::Playlist(PlaylistCommand::Previous) => "previous".to_string(),
+            ControlCommand::Playlist(PlaylistCommand::Goto(index)) => format!("goto {index}"),
+            ControlCommand::Playlist(PlaylistCommand::Reshuffle) => "reshuffle".to_string(),
+            ControlCommand::Parameter(ParameterCommand::Set { name, value }) => {
+                format!("set {name}={value}")
+            }
+            ControlCommand::Parameter(ParameterCommand::Reset { name }) => format!("reset {name}"),
+            ControlCommand::Parameter(ParameterCommand::ResetAll) => "reset".to_string(),
+            ControlCommand::Video(VideoCommand::Seek(to)) => format!("seek {}", to.as_secs_f32()),
+            ControlCommand::Video(VideoCommand::SetSpeed(speed)) => format!("video-speed {speed}"),
+            ControlCommand::Preview(name) => format!("preview {name}"),
+            ControlCommand::Previews => "previews".to_string(),
+        }
+    }
+}
+
+/// Sends `command` to the running wallpaper and waits for it to be carried out, returning what it
+/// answered with, which is empty for most commands.
+pub fn send(command: &ControlCommand) -> Result<String, String> {
+    let mut stream = TcpStream::connect(CONTROL_ADDRESS)
+        .map_err(|err| format!("can't reach the running wallpaper: {err}"))?;
+    stream
+        .set_read_timeout(Some(REPLY_TIMEOUT))
+        .map_err(|err| err.to_string())?;
+    writeln!(stream, "{}", command.to_line())
+        .map_err(|err| format!("can't send {command:?}: {err}"))?;
+    let mut reply = String::new();
+    BufReader::new(stream)
+        .read_line(&mut reply)
+        .map_err(|err| format!("no answer from the running wallpaper: {err}"))?;
+    match reply.trim_end() {
+        "ok" => Ok(String::new()),
+        reply => match reply.strip_prefix("ok ") {
+            Some(answer) => Ok(answer.to_string()),
+            None => Err(reply.strip_prefix("error: ").unwrap_or(reply).to_string()),
+        },
+    }
+}
+
+/// Moves a screenshot the running wallpaper saved to `to`, which may be on another drive.
+pub fn move_screenshot(from: &Path, to: &Path) -> Result<(), String> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to).map_err(|err| format!("can't write {}: {err}", to.display()))?;
+    let _ = std::fs::remove_file(from);
+    Ok(())
+}
+
+/// Listens for [`ControlCommand`]s on a thread of its own.
+#[derive(Default)]
+pub struct ControlPlugin;
+
+impl Plugin for ControlPlugin {
+    fn build(&self, app: &mut App) {
+        let listener = match TcpListener::bind(CONTROL_ADDRESS) {
+            Ok(listener) => listener,
+            Err(err) => {
+                warn!(
+                    "Can't listen for commands on {}, is another wallpaper running? {}",
+                    CONTROL_ADDRESS, err
+                );
+                return;
+            }
+        };
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        // A wallpaper that stopped to save power only updates when it's woken.
+        let waker = app.world.get_resource::<EventLoopWaker>().cloned();
+        let spawned = std::thread::Builder::new()
+            .name("control listener".to_string())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    // A client that never finishes its line shouldn't hold up everyone after it.
+                    let _ = stream.set_read_timeout(Some(COMMAND_TIMEOUT));
+                    let mut line = String::new();
+                    if BufReader::new(&stream).read_line(&mut line).is_err() {
+                        continue;
+                    }
+                    match ControlCommand::parse(line.trim_end()) {
+                        Ok(command) => {
+                            if sender.send((command, stream)).is_err() {
+                                return;
+                            }
+                            if let Some(waker) = &waker {
+                                waker.wake();
+                            }
+                        }
+                        Err(err) => reply(stream, Err(err)),
+                    }
+                }
+            });
+        if let Err(err) = spawned {
+            warn!("Can't start listening for commands: {}", err);
+            return;
+        }
+        app.insert_resource(ControlCommands(receiver))
+            .add_system(run_control_commands);
+    }
+}
+
+/// Commands that came in, with the connection to answer on.
+#[derive(Resource)]
+struct ControlCommands(Receiver<(ControlCommand, TcpStream)>);
+
+#[allow(clippy::too_many_arguments)]
+fn run_control_commands(
+    commands: Res<ControlCommands>,
+    mut screenshots: Option<ResMut<Screenshots>>,
+    mut clock: ResMut<WallpaperClock>,
+    playlist: Res<Playlist>,
+    mut playlist_commands: EventWriter<PlaylistCommand>,
+    parameters: Res<Parameters>,
+    mut parameter_commands: EventWriter<ParameterCommand>,
+    mut video_commands: EventWriter<VideoCommand>,
+    mut previews: Option<ResMut<Previews>>,
+    registry: Res<WallpaperRegistry>,
+    fallback: Option<Res<StaticFallback>>,
+) {
+    let stopped = fallback.map_or(false, |fallback| fallback.is_static());
+    for (command, stream) in commands.0.try_iter() {
+        info!("Received {:?}", command);
+        match command {
+            // No frame gets rendered to take one of until the power comes back.
+            ControlCommand::Screenshot if stopped => reply(
+                stream,
+                Err("the wallpaper is stopped to save power".to_string()),
+            ),
+            ControlCommand::Screenshot => match &mut screenshots {
+                Some(screenshots) => match screenshots.new_path() {
+                    Ok(path) => {
+                        let saved = path.display().to_string();
+                        screenshots.take(
+                            path,
+                            Box::new(move |result| answer(stream, result.map(|()| saved))),
+                        );
+                    }
+                    Err(err) => reply(stream, Err(err)),
+                },
+                None => reply(stream, Err("screenshots aren't supported".to_string())),
+            },
+            ControlCommand::Pause => {
+                clock.pause();
+                reply(stream, Ok(()));
+            }
+            ControlCommand::Resume => {
+                clock.resume();
+                reply(stream, Ok(()));
+            }
+            ControlCommand::Speed(speed) => {
+                clock.set_speed(speed);
+                reply(stream, Ok(()));
+            }
+            ControlCommand::Playlist(PlaylistCommand::Goto(index))
+                if index >= playlist.entries.len() =>
+            {
+                let err = format!("the playlist has {} wallpapers", playlist.entries.len());
+                reply(stream, Err(err));
+            }
+            ControlCommand::Playlist(command) => {
+                playlist_commands.send(command);
+                reply(stream, Ok(()));
+            }
+            ControlCommand::Parameter(
+                ParameterCommand::Set { ref name, .. } | ParameterCommand::Reset { ref name },
+            ) if !parameters
+                .values()
+                .any(|(declared, _)| declared == name.as_str()) =>
+            {
+                let err = format!("the wallpaper has no parameter {name:?}");
+                reply(stream, Err(err));
+            }
+            ControlCommand::Parameter(command) => {
+                parameter_commands.send(command);
+                reply(stream, Ok(()));
+            }
+            ControlCommand::Video(command) => {
+                video_commands.send(command);
+                reply(stream, Ok(()));
+            }
+            ControlCommand::Preview(name) => match &mut previews {
+                Some(previews) => previews.request(
+                    &name,
+                    &registry,
+                    Box::new(move |result| {
+                        answer(stream, result.map(|path| path.display().to_string()))
+                    }),
+                ),
+                None => reply(stream, Err("previews aren't supported".to_string())),
+            },
+            ControlCommand::Previews => match &previews {
+                Some(previews) => {
+                    let list = previews
+                        .list()
+                        .into_iter()
+                        .map(|(name, path)| format!("{name}\t{}", path.display()))
+                        .collect::<Vec<_>>()
+                        .join(&PREVIEW_SEPARATOR.to_string());
+                    answer(stream, Ok(list));
+                }
+                None => reply(stream, Err("previews aren't supported".to_string())),
+            },
+        }
+    }
+}
+
+fn reply(stream: TcpStream, result: Result<(), String>) {
+    answer(stream, result.map(|()| String::new()));
+}
+
+/// Replies with what was asked for, which has to fit on one line.
+fn answer(mut stream: TcpStream, result: Result<String, String>) {
+    let line = match result {
+        Ok(answer) if answer.is_empty() => "ok".to_string(),
+        Ok(answer) => format!("ok {answer}"),
+        Err(err) => format!("error: {err}"),
+    };
+    let _ = writeln!(stream, "{line}");
+}
@@ -0,0 +1,238 @@
+use std::fs::File;
+use std::io::BufWriter;
+#[cfg(feature = "ffmpeg")]
+use std::io::Write;
+use std::path::Path;
+#[cfg(feature = "ffmpeg")]
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use bevy::utils::{
+    tracing::{info, warn},
+    Duration,
+};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, RgbaImage};
+
+use crate::wallpaper_render_plugin::{FrameSink, HeadlessSettings, PngFrames};
+
+/// How far the frame after the last one may be from the first, on average over its color
+/// channels from 0 to 1, for the export to count as looping seamlessly.
+const LOOP_TOLERANCE: f32 = 0.01;
+
+/// Makes the sink for `desktop export` to `output`, picked by its extension: `.gif` and `.png`
+/// animations are encoded here, `.mp4`, `.webm`, `.mov` and `.mkv` videos by piping the frames to
+/// ffmpeg when built with the `ffmpeg` feature, and anything else is a folder of PNG frames.
+///
+/// One more frame than `settings` asks for gets rendered, to check that the end of the export
+/// leads back into its start.
+pub fn exporter(
+    output: &Path,
+    settings: &mut HeadlessSettings,
+) -> Result<Box<dyn FrameSink>, String> {
+    let extension = output
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    let sink: Box<dyn FrameSink> = match extension.as_deref() {
+        Some("gif") => Box::new(Gif::new(output, settings.frame_time)?),
+        Some("png" | "apng") => Box::new(Apng::new(output, settings)?),
+        #[cfg(feature = "ffmpeg")]
+        Some("mp4" | "webm" | "mov" | "mkv") => Box::new(Ffmpeg::new(output, settings)?),
+        #[cfg(not(feature = "ffmpeg"))]
+        Some(extension @ ("mp4" | "webm" | "mov" | "mkv")) => {
+            return Err(format!(
+                "exporting .{extension} video needs desktop built with the ffmpeg feature"
+            ));
+        }
+        _ => Box::new(PngFrames::new(output)?),
+    };
+    let frames = settings.frames;
+    settings.frames += 1;
+    Ok(Box::new(LoopCheck {
+        sink,
+        frames,
+        written: 0,
+        first: None,
+    }))
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>, String> {
+    File::create(path)
+        .map(BufWriter::new)
+        .map_err(|err| format!("can't create {}: {err}", path.display()))
+}
+
+/// Passes on all but the last frame, which is compared with the first to see whether the
+/// export loops without a jump.
+struct LoopCheck {
+    sink: Box<dyn FrameSink>,
+    frames: u32,
+    written: u32,
+    first: Option<RgbaImage>,
+}
+
+impl FrameSink for LoopCheck {
+    fn write(&mut self, frame: RgbaImage) -> Result<(), String> {
+        if self.written == self.frames {
+            if let Some(first) = &self.first {
+                check_loop(first, &frame);
+            }
+            return Ok(());
+        }
+        if self.written == 0 {
+            self.first = Some(frame.clone());
+        }
+        self.written += 1;
+        self.sink.write(frame)
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        self.sink.finish()
+    }
+}
+
+fn check_loop(first: &RgbaImage, next: &RgbaImage) {
+    let channels = first.as_raw().len().max(1);
+    let difference = first
+        .as_raw()
+        .iter()
+        .zip(next.as_raw())
+        .map(|(a, b)| a.abs_diff(*b) as f32 / 255.0)
+        .sum::<f32>()
+        / channels as f32;
+    if difference > LOOP_TOLERANCE {
+        warn!(
+            "The export doesn't loop seamlessly: after its last frame the wallpaper is {:.1}% away \
+             from the first. A duration that's a whole number of the wallpaper's cycles would be.",
+            difference * 100.0
+        );
+    } else {
+        info!("The export loops seamlessly");
+    }
+}
+
+struct Gif {
+    encoder: GifEncoder<BufWriter<File>>,
+    delay: Delay,
+}
+
+impl Gif {
+    fn new(path: &Path, frame_time: Duration) -> Result<Self, String> {
+        // Speed 10 of 30 quantizes colors well enough at a fraction of the slowest setting's cost.
+        let mut encoder = GifEncoder::new_with_speed(create(path)?, 10);
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|err| err.to_string())?;
+        Ok(Gif {
+            encoder,
+            delay: Delay::from_saturating_duration(frame_time),
+        })
+    }
+}
+
+impl FrameSink for Gif {
+    fn write(&mut self, frame: RgbaImage) -> Result<(), String> {
+        self.encoder
+            .encode_frame(image::Frame::from_parts(frame, 0, 0, self.delay))
+            .map_err(|err| format!("can't encode GIF frame: {err}"))
+    }
+}
+
+struct Apng {
+    writer: png::Writer<BufWriter<File>>,
+}
+
+impl Apng {
+    fn new(path: &Path, settings: &HeadlessSettings) -> Result<Self, String> {
+        let error = |err: png::EncodingError| format!("can't encode {}: {err}", path.display());
+        let mut encoder = png::Encoder::new(create(path)?, settings.size.x, settings.size.y);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        // Zero plays loops forever.
+        encoder.set_animated(settings.frames, 0).map_err(error)?;
+        let millis = settings.frame_time.as_millis().min(u16::MAX as u128) as u16;
+        encoder.set_frame_delay(millis, 1000).map_err(error)?;
+        let writer = encoder.write_header().map_err(error)?;
+        Ok(Apng { writer })
+    }
+}
+
+impl FrameSink for Apng {
+    fn write(&mut self, frame: RgbaImage) -> Result<(), String> {
+        self.writer
+            .write_image_data(frame.as_raw())
+            .map_err(|err| format!("can't encode PNG frame: {err}"))
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        self.writer
+            .finish()
+            .map_err(|err| format!("can't finish PNG: {err}"))
+    }
+}
+
+/// Encodes video with an `ffmpeg` found on the PATH, writing raw frames to its input.
+#[cfg(feature = "ffmpeg")]
+struct Ffmpeg {
+    child: Child,
+    stdin: ChildStdin,
+}
+
+#[cfg(feature = "ffmpeg")]
+impl Ffmpeg {
+    fn new(path: &Path, settings: &HeadlessSettings) -> Result<Self, String> {
+        let frame_rate = 1.0 / settings.frame_time.as_secs_f64();
+        let mut child = Command::new("ffmpeg")
+            .args([
+                "-loglevel",
+                "error",
+                "-y",
+                "-f",
+                "rawvideo",
+                "-pix_fmt",
+                "rgba",
+            ])
+            .arg("-video_size")
+            .arg(format!("{}x{}", settings.size.x, settings.size.y))
+            .arg("-framerate")
+            .arg(frame_rate.to_string())
+            .args(["-i", "-"])
+            // Most players only take 4:2:0, which needs an even size.
+            .args([
+                "-vf",
+                "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+                "-pix_fmt",
+                "yuv420p",
+            ])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("exporting video needs ffmpeg on the PATH: {err}"))?;
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| "can't pipe frames to ffmpeg".to_string())?;
+        Ok(Ffmpeg { child, stdin })
+    }
+}
+
+#[cfg(feature = "ffmpeg")]
+impl FrameSink for Ffmpeg {
+    fn write(&mut self, frame: RgbaImage) -> Result<(), String> {
+        self.stdin
+            .write_all(frame.as_raw())
+            .map_err(|err| format!("can't pipe frame to ffmpeg: {err}"))
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        let Ffmpeg { mut child, stdin } = *self;
+        // ffmpeg finishes the video once its input ends.
+        drop(stdin);
+        let status = child
+            .wait()
+            .map_err(|err| format!("can't wait for ffmpeg: {err}"))?;
+        if !status.success() {
+            return Err(format!("ffmpeg exited with {status}"));
+        }
+        Ok(())
+    }
+}
@@ -1,21 +1,286 @@
+mod canvas;
+mod cli;
+mod clock;
+mod config;
+mod content_hash;
+mod control;
+mod export;
+mod parameters;
+mod playlist;
+mod post_process;
+mod power;
+mod previews;
+mod quality;
+mod render_scale;
+mod screenshot;
+mod shader_library;
+mod shader_material;
+mod static_fallback;
+mod transition;
+mod wallpaper;
 mod wallpaper_render_plugin;
+mod warm_up;
+
+use std::path::Path;
 
 use bevy::prelude::*;
 use bevy::{
     reflect::TypeUuid,
     render::render_resource::{AsBindGroup, ShaderRef},
+    utils::Duration,
 };
 
-use wallpaper_render_plugin::WallpaperRenderPlugin;
+use canvas::CanvasPlugin;
+use cli::Cli;
+use clock::{ClockPlugin, WallpaperClock};
+use config::Config;
+use control::{ControlCommand, ControlPlugin};
+use parameters::{
+    ParameterBlock, ParameterOverrides, Parameters, ParametersPlugin, ResolveParameters,
+};
+use playlist::{Playlist, PlaylistEntry, PlaylistOrder, PlaylistPlugin};
+use post_process::{PostProcessOverrides, PostProcessPlugin};
+use power::PowerPlugin;
+use previews::PreviewPlugin;
+use quality::{QualityPlugin, QualityPresets};
+use render_scale::{RenderScale, RenderScalePlugin};
+use screenshot::ScreenshotPlugin;
+use shader_library::ShaderLibraryPlugin;
+use shader_material::ShaderMaterialPlugin;
+use static_fallback::{StaticFallback, StaticFallbackPlugin};
+use transition::{Transition, TransitionPlugin};
+use wallpaper::{
+    is_gltf, is_shader_wallpaper, is_video, GltfWallpaper, ImageWallpaper, ShaderWallpaper,
+    VideoWallpaper, Wallpaper, WallpaperAppExt, WallpaperPlugin, WallpaperRegistry,
+};
+use wallpaper_render_plugin::{
+    FrameSink, HeadlessOutput, HeadlessPlugin, PngFrames, WallpaperRenderPlugin,
+};
+use warm_up::WarmUpPlugin;
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins.build().disable::<bevy::winit::WinitPlugin>())
-        .add_plugin(WallpaperRenderPlugin)
+    let cli = Cli::parse();
+    if let Some(command) = &cli.control {
+        match control::send(command) {
+            Ok(answer) => {
+                if let (ControlCommand::Screenshot, Some(path)) = (command, &cli.screenshot) {
+                    // The running wallpaper only saves screenshots to a folder of its own.
+                    if let Err(err) = control::move_screenshot(Path::new(&answer), path) {
+                        eprintln!("error: {err}");
+                        std::process::exit(1);
+                    }
+                    println!("Saved {}", path.display());
+                } else if let ControlCommand::Previews = command {
+                    for preview in answer
+                        .split(control::PREVIEW_SEPARATOR)
+                        .filter(|preview| !preview.is_empty())
+                    {
+                        println!("{preview}");
+                    }
+                } else if !answer.is_empty() {
+                    println!("{answer}");
+                }
+            }
+            Err(err) => {
+                eprintln!("error: {err}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let config = Config::load(cli.config.as_deref()).unwrap_or_else(|err| {
+        eprintln!("error: {err}");
+        std::process::exit(2);
+    });
+    if cli.is_offscreen() {
+        if let Err(err) = wallpaper_render_plugin::check_adapter() {
+            eprintln!("error: {err}");
+            std::process::exit(1);
+        }
+    }
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins.build().disable::<bevy::winit::WinitPlugin>());
+    let mut offscreen = cli.offscreen.clone();
+    let output = match (&cli.export, &cli.headless) {
+        (Some(path), _) => Some(export::exporter(path, &mut offscreen)),
+        (None, Some(folder)) => {
+            Some(PngFrames::new(folder).map(|frames| Box::new(frames) as Box<dyn FrameSink>))
+        }
+        (None, None) => None,
+    };
+    match output {
+        Some(output) => {
+            let output = output.unwrap_or_else(|err| {
+                eprintln!("error: {err}");
+                std::process::exit(2);
+            });
+            app.add_plugin(HeadlessPlugin)
+                .insert_resource(offscreen)
+                .insert_resource(HeadlessOutput(output));
+        }
+        None => {
+            app.add_plugin(WallpaperRenderPlugin)
+                .add_plugin(WarmUpPlugin)
+                .add_plugin(ScreenshotPlugin)
+                .add_plugin(ControlPlugin)
+                .add_plugin(PreviewPlugin {
+                    config: cli.config.clone(),
+                })
+                .add_plugin(PowerPlugin)
+                .add_plugin(StaticFallbackPlugin)
+                .insert_resource(StaticFallback::new(config.static_on));
+        }
+    }
+    app.add_plugin(ClockPlugin)
+        .add_plugin(WallpaperPlugin)
+        .add_plugin(CanvasPlugin)
+        .add_plugin(TransitionPlugin)
+        .add_plugin(PlaylistPlugin)
+        .add_plugin(ParametersPlugin)
+        .add_plugin(ShaderLibraryPlugin)
+        .add_plugin(ShaderMaterialPlugin)
+        .add_plugin(PostProcessPlugin)
+        .add_plugin(QualityPlugin)
         .add_plugin(MaterialPlugin::<CustomMaterial>::default())
-        .add_startup_system(setup)
+        .insert_resource(quality_presets(&cli, &config))
+        .insert_resource(ParameterOverrides::new(
+            config.parameter_overrides(),
+            cli.parameters.clone(),
+        ))
+        .insert_resource(PostProcessOverrides(config.post.clone()))
+        .add_wallpaper("cube_demo", Wallpaper::scene(setup))
+        .add_wallpaper_manifest("cube_demo", "wallpapers/cube_demo.wallpaper.ron")
+        .add_wallpaper(
+            "plasma",
+            Wallpaper::Shader(ShaderWallpaper::new("wallpapers/plasma.wallpaper.ron")),
+        )
+        .add_wallpaper(
+            "trails",
+            Wallpaper::Shader(ShaderWallpaper::new("wallpapers/trails.wallpaper.ron")),
+        )
+        .add_wallpaper(
+            "clouds",
+            Wallpaper::Shader(ShaderWallpaper::new("wallpapers/clouds.wallpaper.ron")),
+        )
+        .add_wallpaper(
+            "reaction_diffusion",
+            Wallpaper::Shader(ShaderWallpaper::new(
+                "wallpapers/reaction_diffusion.wallpaper.ron",
+            )),
+        )
+        .add_wallpaper(
+            "blobs",
+            Wallpaper::Shader(ShaderWallpaper::new("wallpapers/blobs.wallpaper.ron")),
+        )
         .add_system(change_color)
-        .run();
+        .add_system(apply_parameters.after(ResolveParameters));
+
+    // Offscreen renders come out at the size they're asked for.
+    if !cli.is_offscreen() {
+        app.add_plugin(RenderScalePlugin)
+            .insert_resource(RenderScale {
+                budget: config
+                    .frame_budget
+                    .map(|millis| Duration::from_secs_f32(millis / 1000.0)),
+                // The most it goes up to is set by the quality preset.
+                ..default()
+            });
+    }
+
+    let mut playlist = if cli.images.is_empty() {
+        let name = cli.wallpaper.as_deref().unwrap_or("cube_demo");
+        if !app.world.resource::<WallpaperRegistry>().contains(name) {
+            eprintln!("error: there is no built in wallpaper called {name:?}");
+            std::process::exit(2);
+        }
+        Playlist::new(vec![PlaylistEntry::new(name)])
+    } else {
+        let interval = cli
+            .interval
+            .or_else(|| config.interval.map(Duration::from_secs_f32));
+        let mut entries = Vec::new();
+        for path in &cli.images {
+            let name = path.to_string_lossy().into_owned();
+            let wallpaper = if is_shader_wallpaper(path) {
+                Wallpaper::Shader(ShaderWallpaper::new(name.clone()))
+            } else if is_gltf(path) {
+                // A manifest next to the scene can give it parameters and a camera rig.
+                let manifest = path.with_extension("wallpaper.ron");
+                if manifest.is_file() {
+                    app.add_wallpaper_manifest(name.clone(), manifest.to_string_lossy());
+                }
+                Wallpaper::Gltf(GltfWallpaper::new(name.clone()))
+            } else if is_video(path) {
+                Wallpaper::Video(VideoWallpaper {
+                    path: path.clone(),
+                    fit: cli.fit,
+                    background: cli.background,
+                    looping: cli.looping,
+                    speed: cli.speed,
+                    frame_rate: cli.frame_rate,
+                })
+            } else {
+                Wallpaper::Image(ImageWallpaper {
+                    path: name.clone(),
+                    fit: cli.fit,
+                    background: cli.background,
+                })
+            };
+            app.add_wallpaper(name.clone(), wallpaper);
+            let mut entry = PlaylistEntry::new(name);
+            if let Some(interval) = interval {
+                entry = entry.with_duration(interval);
+            }
+            entries.push(entry);
+        }
+        let order = if cli.shuffle {
+            PlaylistOrder::Shuffled
+        } else {
+            PlaylistOrder::Sequential
+        };
+        Playlist::new(entries).with_order(order)
+    };
+    playlist = playlist.with_switch_on_wake(cli.switch_on_wake || config.switch_on_wake);
+    let transition = cli.transition.as_deref().unwrap_or(&config.transition);
+    if transition != "none" {
+        let duration = cli
+            .transition_time
+            .unwrap_or_else(|| Duration::from_secs_f32(config.transition_time));
+        playlist = playlist.with_transition(Transition::new(transition, duration));
+    }
+
+    // Runs that are meant to repeat exactly shuffle the same way too.
+    let repeatable = cli.fixed_step.is_some() || cli.is_offscreen();
+    if let Some(seed) = cli.seed.or(repeatable.then_some(0)) {
+        playlist = playlist.with_seed(seed);
+    }
+    app.insert_resource(playlist);
+    // Headless rendering steps the clock itself once the wallpaper has loaded.
+    if !cli.is_offscreen() {
+        app.world
+            .resource_mut::<WallpaperClock>()
+            .set_step(cli.fixed_step);
+    }
+
+    app.run();
+}
+
+/// The quality presets from the config file, or the one given on the command line for everything.
+fn quality_presets(cli: &Cli, config: &Config) -> QualityPresets {
+    let render_scale = config.render_scale;
+    match cli.quality {
+        Some(global) => QualityPresets {
+            global,
+            render_scale,
+            ..default()
+        },
+        None => QualityPresets {
+            global: config.quality.unwrap_or_default(),
+            wallpapers: config.wallpaper_quality.clone(),
+            power: config.power_quality.clone(),
+            render_scale,
+        },
+    }
 }
 
 #[derive(Component)]
@@ -26,6 +291,11 @@ struct Cube;
 pub struct CustomMaterial {
     #[uniform(0)]
     time: f32,
+    #[uniform(1)]
+    parameters: ParameterBlock,
+    #[texture(2)]
+    #[sampler(3)]
+    pattern: Option<Handle<Image>>,
     alpha_mode: AlphaMode,
 }
 
@@ -41,9 +311,19 @@ impl Material for CustomMaterial {
     }
 }
 
-fn change_color(mut materials: ResMut<Assets<CustomMaterial>>, time: Res<Time>) {
+fn change_color(mut materials: ResMut<Assets<CustomMaterial>>, clock: Res<WallpaperClock>) {
     for material in materials.iter_mut() {
-        material.1.time = time.elapsed_seconds() as f32;
+        material.1.time = clock.elapsed_seconds();
+    }
+}
+
+fn apply_parameters(parameters: Res<Parameters>, mut materials: ResMut<Assets<CustomMaterial>>) {
+    if !parameters.is_changed() {
+        return;
+    }
+    for (_, material) in materials.iter_mut() {
+        material.parameters = parameters.block().clone();
+        material.pattern = parameters.texture("pattern");
     }
 }
 
@@ -65,6 +345,8 @@ fn setup(
         transform: Transform::from_xyz(0.0, 0.5, 0.0),
         material: materials.add(CustomMaterial {
             time: 0.,
+            parameters: default(),
+            pattern: None,
             alpha_mode: AlphaMode::Blend,
         }),
         ..default()
@@ -0,0 +1,328 @@
+use bevy::app::{App, Plugin};
+use bevy::asset::{AssetEvent, AssetServer, Assets, Handle};
+use bevy::ecs::prelude::*;
+use bevy::math::UVec4;
+use bevy::render::color::Color;
+use bevy::render::render_resource::ShaderType;
+use bevy::render::texture::Image;
+use bevy::utils::{tracing::warn, HashMap};
+
+use crate::shader_material::{UniformLayout, UniformValue};
+use crate::wallpaper::{
+    parse_hex_color, ParameterKind, WallpaperManifest, WallpaperRegistry, WallpaperSwitched,
+};
+
+/// Number of 16 byte rows in a [`ParameterBlock`].
+const PARAMETER_BLOCK_SIZE: usize = 16;
+
+#[derive(Default)]
+pub struct ParametersPlugin;
+
+impl Plugin for ParametersPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Parameters>()
+            .init_resource::<ParameterOverrides>()
+            .add_event::<ParameterCommand>()
+            .add_system(resolve_parameters.label(ResolveParameters));
+    }
+}
+
+/// Label for the system that updates [`Parameters`]. Systems copying them into materials should
+/// run after it to pick up changes in the same frame.
+#[derive(SystemLabel)]
+pub struct ResolveParameters;
+
+/// The value of a parameter declared in a [`WallpaperManifest`].
+#[derive(Debug, Clone)]
+pub enum ParameterValue {
+    Float(f32),
+    Color(Color),
+    Bool(bool),
+    /// Index of the chosen option.
+    Enum(usize),
+    Texture(Option<Handle<Image>>),
+}
+
+impl ParameterValue {
+    /// The value as written into a uniform buffer. Textures are bound separately instead.
+    pub fn uniform(&self) -> Option<UniformValue> {
+        match self {
+            ParameterValue::Float(value) => Some(UniformValue::F32(*value)),
+            ParameterValue::Color(color) => {
+                Some(UniformValue::Vec4(color.as_linear_rgba_f32().into()))
+            }
+            ParameterValue::Bool(value) => Some(UniformValue::U32(u32::from(*value))),
+            ParameterValue::Enum(index) => Some(UniformValue::U32(*index as u32)),
+            ParameterValue::Texture(_) => None,
+        }
+    }
+}
+
+/// Changes the parameters of the active wallpaper while it's showing. They stick for as long as
+/// the app runs, even when switching away and back again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParameterCommand {
+    /// Sets a parameter from a value written the same way as on the command line.
+    Set {
+        name: String,
+        value: String,
+    },
+    /// Goes back to the value from the command line, config or manifest.
+    Reset {
+        name: String,
+    },
+    ResetAll,
+}
+
+/// Values users chose for parameters, from lowest to highest precedence. All of them are written
+/// the way they would be on the command line, and only parsed once the wallpaper they're for
+/// says what type they are.
+#[derive(Debug, Default, Resource)]
+pub struct ParameterOverrides {
+    /// From the config file, by wallpaper and then parameter name.
+    pub config: HashMap<String, HashMap<String, String>>,
+    /// From the command line, for every wallpaper declaring a parameter of that name.
+    pub cli: HashMap<String, String>,
+    /// From [`ParameterCommand`]s, by wallpaper and then parameter name.
+    commands: HashMap<String, HashMap<String, String>>,
+}
+
+impl ParameterOverrides {
+    pub fn new(
+        config: HashMap<String, HashMap<String, String>>,
+        cli: HashMap<String, String>,
+    ) -> Self {
+        ParameterOverrides {
+            config,
+            cli,
+            commands: HashMap::default(),
+        }
+    }
+
+    fn get(&self, wallpaper: &str, name: &str) -> Option<&String> {
+        let scoped = |overrides: &HashMap<String, HashMap<String, String>>| {
+            overrides.get(wallpaper).and_then(|values| values.get(name))
+        };
+        scoped(&self.commands)
+            .or_else(|| self.cli.get(name))
+            .or_else(|| scoped(&self.config))
+    }
+}
+
+/// The parameters of the active wallpaper, with overrides applied.
+#[derive(Default, Resource)]
+pub struct Parameters {
+    wallpaper: Option<String>,
+    manifest: Option<Handle<WallpaperManifest>>,
+    values: Vec<(String, ParameterValue)>,
+    block: ParameterBlock,
+}
+
+impl Parameters {
+    /// The name of the active wallpaper.
+    pub fn wallpaper(&self) -> Option<&str> {
+        self.wallpaper.as_deref()
+    }
+
+    /// The manifest of the active wallpaper, if it has one.
+    pub fn manifest(&self) -> Option<&Handle<WallpaperManifest>> {
+        self.manifest.as_ref()
+    }
+
+    /// The values to bind as a wallpaper material's parameter uniform.
+    pub fn block(&self) -> &ParameterBlock {
+        &self.block
+    }
+
+    /// Every parameter with its value, in the order the manifest declares them.
+    pub fn values(&self) -> impl Iterator<Item = (&str, &ParameterValue)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    /// The image set for a texture parameter, if there is one.
+    pub fn texture(&self, name: &str) -> Option<Handle<Image>> {
+        self.values
+            .iter()
+            .find_map(|(parameter, value)| match value {
+                ParameterValue::Texture(texture) if parameter == name => texture.clone(),
+                _ => None,
+            })
+    }
+}
+
+/// Parameter values packed into a uniform buffer in the order the manifest declares them,
+/// following WGSL's layout rules for uniforms. Shaders read it through a struct with the same
+/// fields.
+///
+/// The block holds the raw bits of every value as `u32`s, so that the bits of `u32` fields, which
+/// can be NaNs or denormals as floats, are never handled as floats on the way to the GPU. Shaders
+/// that declare it as an `array<vec4<u32>, 16>` instead read float lanes back with `bitcast<f32>`.
+#[derive(Debug, Clone, Default, ShaderType)]
+pub struct ParameterBlock {
+    data: [UVec4; PARAMETER_BLOCK_SIZE],
+}
+
+impl ParameterBlock {
+    fn pack(values: &[(String, ParameterValue)]) -> Self {
+        let uniforms: Vec<(&str, UniformValue)> = values
+            .iter()
+            .filter_map(|(name, value)| Some((name.as_str(), value.uniform()?)))
+            .collect();
+        let layout = UniformLayout::sequential(
+            uniforms
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.uniform_type())),
+        );
+        let mut bytes = vec![0; std::mem::size_of::<[UVec4; PARAMETER_BLOCK_SIZE]>()];
+        for (name, value) in uniforms {
+            if !layout.write(&mut bytes, name, value) {
+                warn!(
+                    "Parameter {:?} and the ones after it don't fit in the {} byte parameter block",
+                    name,
+                    bytes.len()
+                );
+                break;
+            }
+        }
+        ParameterBlock {
+            data: std::array::from_fn(|index| {
+                let word = |offset| {
+                    let start = (index * 4 + offset) * 4;
+                    u32::from_le_bytes(bytes[start..start + 4].try_into().unwrap())
+                };
+                UVec4::new(word(0), word(1), word(2), word(3))
+            }),
+        }
+    }
+}
+
+fn parse_value(
+    kind: &ParameterKind,
+    value: &str,
+    asset_server: &AssetServer,
+) -> Result<ParameterValue, String> {
+    match kind {
+        ParameterKind::Float { min, max, .. } => value
+            .parse::<f32>()
+            .ok()
+            .filter(|value| value.is_finite())
+            .map(|value| ParameterValue::Float(value.clamp(*min, *max)))
+            .ok_or_else(|| format!("invalid number {value:?}")),
+        ParameterKind::Color { .. } => parse_hex_color(value).map(ParameterValue::Color),
+        ParameterKind::Bool { .. } => match value {
+            "true" | "on" | "yes" | "1" => Ok(ParameterValue::Bool(true)),
+            "false" | "off" | "no" | "0" => Ok(ParameterValue::Bool(false)),
+            _ => Err(format!("invalid boolean {value:?}")),
+        },
+        ParameterKind::Enum { options, .. } => options
+            .iter()
+            .position(|option| option == value)
+            .map(ParameterValue::Enum)
+            .ok_or_else(|| format!("{value:?} is not one of {}", options.join(", "))),
+        ParameterKind::Texture { .. } => Ok(ParameterValue::Texture(
+            (!value.is_empty()).then(|| asset_server.load(value)),
+        )),
+    }
+}
+
+fn default_value(kind: &ParameterKind, asset_server: &AssetServer) -> ParameterValue {
+    match kind {
+        ParameterKind::Float { default, .. } => ParameterValue::Float(*default),
+        ParameterKind::Color { default } => ParameterValue::Color(*default),
+        ParameterKind::Bool { default } => ParameterValue::Bool(*default),
+        ParameterKind::Enum { options, default } => ParameterValue::Enum(
+            options
+                .iter()
+                .position(|option| option == default)
+                .unwrap_or_default(),
+        ),
+        ParameterKind::Texture { default } => ParameterValue::Texture(
+            default
+                .as_ref()
+                .map(|path| asset_server.load(path.as_str())),
+        ),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn resolve_parameters(
+    registry: Res<WallpaperRegistry>,
+    asset_server: Res<AssetServer>,
+    manifests: Res<Assets<WallpaperManifest>>,
+    mut manifest_events: EventReader<AssetEvent<WallpaperManifest>>,
+    mut switched: EventReader<WallpaperSwitched>,
+    mut commands: EventReader<ParameterCommand>,
+    mut overrides: ResMut<ParameterOverrides>,
+    mut parameters: ResMut<Parameters>,
+) {
+    let mut changed = false;
+    if let Some(event) = switched.iter().last() {
+        parameters.wallpaper = Some(event.name.clone());
+        parameters.manifest = registry.load_manifest(&event.name, &asset_server);
+        changed = true;
+    }
+    for event in manifest_events.iter() {
+        if let AssetEvent::Created { handle } | AssetEvent::Modified { handle } = event {
+            changed |= parameters.manifest.as_ref() == Some(handle);
+        }
+    }
+
+    let Some(wallpaper) = parameters.wallpaper.clone() else {
+        return;
+    };
+    let manifest = parameters
+        .manifest
+        .as_ref()
+        .and_then(|manifest| manifests.get(manifest));
+    for command in commands.iter() {
+        let values = overrides.commands.entry(wallpaper.clone()).or_default();
+        match command {
+            ParameterCommand::Set { name, value } => {
+                let declared = manifest.map_or(false, |manifest| {
+                    manifest
+                        .parameters
+                        .iter()
+                        .any(|parameter| parameter.name == *name)
+                });
+                if !declared {
+                    warn!("Wallpaper {:?} has no parameter {:?}", wallpaper, name);
+                    continue;
+                }
+                values.insert(name.clone(), value.clone());
+            }
+            ParameterCommand::Reset { name } => {
+                values.remove(name);
+            }
+            ParameterCommand::ResetAll => values.clear(),
+        }
+        changed = true;
+    }
+    if !changed {
+        return;
+    }
+
+    let declarations = manifest.map_or(&[][..], |manifest| &manifest.parameters[..]);
+    let values: Vec<(String, ParameterValue)> = declarations
+        .iter()
+        .map(|declaration| {
+            let value = overrides
+                .get(&wallpaper, &declaration.name)
+                .and_then(|value| {
+                    parse_value(&declaration.kind, value, &asset_server)
+                        .map_err(|err| {
+                            warn!(
+                                "Ignoring {:?} for parameter {:?} of {:?}: {}",
+                                value, declaration.name, wallpaper, err
+                            );
+                        })
+                        .ok()
+                })
+                .unwrap_or_else(|| default_value(&declaration.kind, &asset_server));
+            (declaration.name.clone(), value)
+        })
+        .collect();
+    parameters.block = ParameterBlock::pack(&values);
+    parameters.values = values;
+}
@@ -0,0 +1,258 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::app::{App, CoreStage, Plugin};
+use bevy::ecs::prelude::*;
+use bevy::time::{Timer, TimerMode};
+use bevy::utils::{tracing::warn, Duration};
+
+use crate::clock::WallpaperClock;
+use crate::transition::Transition;
+use crate::wallpaper::{SwitchWallpaper, WallpaperRegistry};
+use crate::wallpaper_render_plugin::{NextUpdate, SystemWoke};
+
+#[derive(Default)]
+pub struct PlaylistPlugin;
+
+impl Plugin for PlaylistPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Playlist>()
+            .add_event::<PlaylistCommand>()
+            .add_system_to_stage(CoreStage::PreUpdate, advance_playlist);
+    }
+}
+
+/// The order in which the entries of a [`Playlist`] are visited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistOrder {
+    /// Entries play in the order they were added.
+    Sequential,
+    /// Entries play in a random order, which is reshuffled every time the playlist wraps around.
+    Shuffled,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlaylistEntry {
+    /// The name the wallpaper was registered under.
+    pub wallpaper: String,
+    /// How long the wallpaper stays up before switching to the next entry. `None` keeps it up
+    /// until a [`PlaylistCommand`] or wake switches it.
+    pub duration: Option<Duration>,
+    /// How to switch to this entry, overriding [`Playlist::transition`].
+    pub transition: Option<Transition>,
+}
+
+impl PlaylistEntry {
+    pub fn new(wallpaper: impl Into<String>) -> Self {
+        PlaylistEntry {
+            wallpaper: wallpaper.into(),
+            duration: None,
+            transition: None,
+        }
+    }
+
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+}
+
+/// An ordered list of wallpapers to rotate through.
+#[derive(Debug, Resource)]
+pub struct Playlist {
+    pub entries: Vec<PlaylistEntry>,
+    pub order: PlaylistOrder,
+    /// Whether waking the system from sleep advances to the next entry.
+    pub switch_on_wake: bool,
+    /// How to switch between entries that don't specify their own transition.
+    pub transition: Option<Transition>,
+    /// Indices into `entries` in the order they are played.
+    play_order: Vec<usize>,
+    /// Position in `play_order` of the entry that is up, or `None` before the first switch.
+    position: Option<usize>,
+    timer: Option<Timer>,
+    rng: u64,
+}
+
+impl Default for Playlist {
+    fn default() -> Self {
+        Playlist::new(Vec::new())
+    }
+}
+
+impl Playlist {
+    pub fn new(entries: Vec<PlaylistEntry>) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_nanos() as u64)
+            .unwrap_or_default();
+        Playlist {
+            entries,
+            order: PlaylistOrder::Sequential,
+            switch_on_wake: false,
+            transition: None,
+            play_order: Vec::new(),
+            position: None,
+            timer: None,
+            // xorshift gets stuck on a zero state
+            rng: seed | 1,
+        }
+    }
+
+    /// Shuffle with the same order on every run instead of a different one each time.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        // xorshift gets stuck on a zero state
+        self.rng = seed | 1;
+        self
+    }
+
+    pub fn with_order(mut self, order: PlaylistOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn with_switch_on_wake(mut self, switch_on_wake: bool) -> Self {
+        self.switch_on_wake = switch_on_wake;
+        self
+    }
+
+    pub fn with_transition(mut self, transition: Transition) -> Self {
+        self.transition = Some(transition);
+        self
+    }
+
+    /// The entry that is currently up.
+    pub fn current(&self) -> Option<&PlaylistEntry> {
+        self.position
+            .and_then(|position| self.play_order.get(position))
+            .and_then(|index| self.entries.get(*index))
+    }
+
+    fn rebuild_play_order(&mut self) {
+        self.play_order = (0..self.entries.len()).collect();
+        if self.order == PlaylistOrder::Shuffled {
+            // Fisher-Yates
+            for i in (1..self.play_order.len()).rev() {
+                let j = (self.next_random() % (i as u64 + 1)) as usize;
+                self.play_order.swap(i, j);
+            }
+        }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    fn step(&mut self, forward: bool) {
+        let len = self.entries.len();
+        if self.play_order.len() != len {
+            self.rebuild_play_order();
+        }
+        self.position = Some(match (self.position, forward) {
+            (None, _) => 0,
+            (Some(position), true) if position + 1 >= len => {
+                if self.order == PlaylistOrder::Shuffled {
+                    self.rebuild_play_order();
+                }
+                0
+            }
+            (Some(position), true) => position + 1,
+            (Some(0), false) => len - 1,
+            (Some(position), false) => position - 1,
+        });
+    }
+
+    fn goto(&mut self, index: usize) {
+        if self.play_order.len() != self.entries.len() {
+            self.rebuild_play_order();
+        }
+        self.position = self.play_order.iter().position(|i| *i == index);
+    }
+}
+
+/// Commands that switch the playlist without waiting for the current entry's timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistCommand {
+    Next,
+    Previous,
+    /// Jump to the entry at this index in [`Playlist::entries`].
+    Goto(usize),
+    /// Reshuffle the play order and start over from its first entry.
+    Reshuffle,
+}
+
+fn advance_playlist(
+    clock: Res<WallpaperClock>,
+    mut playlist: ResMut<Playlist>,
+    registry: Res<WallpaperRegistry>,
+    mut commands: EventReader<PlaylistCommand>,
+    mut woke: EventReader<SystemWoke>,
+    mut switch_events: EventWriter<SwitchWallpaper>,
+    mut next_update: ResMut<NextUpdate>,
+) {
+    if playlist.entries.is_empty() {
+        return;
+    }
+
+    let mut switched = playlist.position.is_none();
+    if switched {
+        playlist.step(true);
+    }
+
+    for command in commands.iter() {
+        match *command {
+            PlaylistCommand::Next => playlist.step(true),
+            PlaylistCommand::Previous => playlist.step(false),
+            PlaylistCommand::Goto(index) if index < playlist.entries.len() => playlist.goto(index),
+            PlaylistCommand::Goto(index) => {
+                warn!("Playlist has no entry {}", index);
+                continue;
+            }
+            PlaylistCommand::Reshuffle => {
+                playlist.rebuild_play_order();
+                playlist.position = Some(0);
+            }
+        }
+        switched = true;
+    }
+
+    if woke.iter().last().is_some() && playlist.switch_on_wake && !switched {
+        playlist.step(true);
+        switched = true;
+    }
+
+    if !switched {
+        if let Some(timer) = playlist.timer.as_mut() {
+            if timer.tick(clock.frame_delta()).just_finished() {
+                playlist.step(true);
+                switched = true;
+            }
+        }
+    }
+
+    if switched {
+        let Some(entry) = playlist.current().cloned() else {
+            return;
+        };
+        if !registry.contains(&entry.wallpaper) {
+            warn!(
+                "Playlist entry {:?} is not a registered wallpaper",
+                entry.wallpaper
+            );
+        }
+        playlist.timer = entry
+            .duration
+            .map(|duration| Timer::new(duration, TimerMode::Once));
+        switch_events.send(SwitchWallpaper {
+            name: entry.wallpaper,
+            transition: entry.transition.or_else(|| playlist.transition.clone()),
+        });
+    }
+
+    // Wallpapers that update reactively could otherwise sleep through the switch.
+    if let Some(timer) = &playlist.timer {
+        next_update.request_in(timer.remaining());
+    }
+}
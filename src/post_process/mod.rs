@@ -0,0 +1,387 @@
+mod node;
+
+use bevy::asset::load_internal_asset;
+use bevy::core_pipeline::bloom::BloomSettings;
+use bevy::core_pipeline::tonemapping::Tonemapping;
+use bevy::core_pipeline::{core_2d, core_3d};
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::camera::RenderTarget;
+use bevy::render::extract_component::ExtractComponentPlugin;
+use bevy::render::render_graph::RenderGraph;
+use bevy::render::render_resource::SpecializedRenderPipelines;
+use bevy::render::{RenderApp, RenderStage};
+use bevy::utils::HashMap;
+use serde::Deserialize;
+
+use crate::canvas::{PresentationCamera, TargetWallpaperCameras, WallpaperCanvas};
+use crate::clock::WallpaperClock;
+use crate::parameters::Parameters;
+use crate::quality::Quality;
+use crate::wallpaper::WallpaperManifest;
+use node::{prepare_post_process, PostProcessNode, PostProcessPipeline, PostProcessUniforms};
+
+const POST_PROCESS_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x51c9_2f0e_7d4a_b613);
+
+const POST_PROCESS_NODE: &str = "wallpaper_post_process";
+
+/// Runs the effects of the active wallpaper's [`PostProcessDeclaration`] on its cameras, as a node
+/// in bevy's 2D and 3D render graphs after their own tonemapping.
+#[derive(Default)]
+pub struct PostProcessPlugin;
+
+impl Plugin for PostProcessPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            POST_PROCESS_SHADER,
+            "post_process.wgsl",
+            Shader::from_wgsl
+        );
+        app.init_resource::<PostProcessOverrides>()
+            .add_plugin(ExtractComponentPlugin::<PostProcess>::default())
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                apply_post_process.after(TargetWallpaperCameras),
+            );
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<PostProcessPipeline>()
+            .init_resource::<SpecializedRenderPipelines<PostProcessPipeline>>()
+            .init_resource::<PostProcessUniforms>()
+            .add_system_to_stage(RenderStage::Prepare, prepare_post_process);
+
+        for (graph_name, input, tonemapping, end) in [
+            (
+                core_2d::graph::NAME,
+                core_2d::graph::input::VIEW_ENTITY,
+                core_2d::graph::node::TONEMAPPING,
+                core_2d::graph::node::END_MAIN_PASS_POST_PROCESSING,
+            ),
+            (
+                core_3d::graph::NAME,
+                core_3d::graph::input::VIEW_ENTITY,
+                core_3d::graph::node::TONEMAPPING,
+                core_3d::graph::node::END_MAIN_PASS_POST_PROCESSING,
+            ),
+        ] {
+            let node = PostProcessNode::new(&mut render_app.world);
+            let mut graphs = render_app.world.resource_mut::<RenderGraph>();
+            let graph = graphs.get_sub_graph_mut(graph_name).unwrap();
+            graph.add_node(POST_PROCESS_NODE, node);
+            graph
+                .add_slot_edge(
+                    graph.input_node().unwrap().id,
+                    input,
+                    POST_PROCESS_NODE,
+                    PostProcessNode::IN_VIEW,
+                )
+                .unwrap();
+            graph.add_node_edge(tonemapping, POST_PROCESS_NODE).unwrap();
+            graph.add_node_edge(POST_PROCESS_NODE, end).unwrap();
+        }
+    }
+}
+
+/// The effects applied to what a wallpaper's cameras draw, in this order: bloom, blur, chromatic
+/// aberration, tonemapping, color grading, vignette and grain. Users can replace a wallpaper's
+/// effects in their config file.
+///
+/// ```ron
+/// post: Some((
+///     bloom: Some((intensity: 0.2)),
+///     tonemapping: Aces,
+///     lut: Some("luts/warm.png"),
+///     vignette: Some((intensity: 0.4)),
+///     grain: Some((intensity: 0.03)),
+/// )),
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PostProcessDeclaration {
+    /// Makes bright parts of the image glow.
+    pub bloom: Option<BloomDeclaration>,
+    pub tonemapping: TonemappingCurve,
+    /// Asset path of a color grading lookup table: a strip of N slices of N by N texels, with red
+    /// going right and green going down within a slice, and blue going from slice to slice.
+    pub lut: Option<String>,
+    /// Darkens the image toward its corners.
+    pub vignette: Option<VignetteDeclaration>,
+    /// Noise that changes every frame, like film grain.
+    pub grain: Option<GrainDeclaration>,
+    /// Pulls the red and blue channels apart toward the edges, like a cheap lens.
+    pub chromatic_aberration: Option<ChromaticAberrationDeclaration>,
+    /// Blurs the whole image, for a wallpaper that should stay out of the way.
+    pub blur: Option<BlurDeclaration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BloomDeclaration {
+    pub intensity: f32,
+    /// How bright a color has to be to glow, before tonemapping.
+    pub threshold: f32,
+}
+
+impl Default for BloomDeclaration {
+    fn default() -> Self {
+        BloomDeclaration {
+            intensity: 0.3,
+            threshold: 1.0,
+        }
+    }
+}
+
+/// How colors too bright for the screen are brought down to it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum TonemappingCurve {
+    /// Clips them.
+    None,
+    /// Compresses each channel on its own, which washes bright colors out to white.
+    Reinhard,
+    /// Compresses the brightness, keeping the hue.
+    #[default]
+    ReinhardLuminance,
+    /// The filmic curve of the Academy Color Encoding System.
+    Aces,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct VignetteDeclaration {
+    /// How dark the corners get, from 0 to 1.
+    pub intensity: f32,
+    /// Where the darkening starts, from 0 at the center to 1 at the corners.
+    pub radius: f32,
+    /// How far the darkening takes to reach its full intensity.
+    pub smoothness: f32,
+}
+
+impl Default for VignetteDeclaration {
+    fn default() -> Self {
+        VignetteDeclaration {
+            intensity: 0.3,
+            radius: 0.5,
+            smoothness: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct GrainDeclaration {
+    pub intensity: f32,
+}
+
+impl Default for GrainDeclaration {
+    fn default() -> Self {
+        GrainDeclaration { intensity: 0.05 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ChromaticAberrationDeclaration {
+    /// How far apart the channels get at the edges, in fractions of the image.
+    pub intensity: f32,
+}
+
+impl Default for ChromaticAberrationDeclaration {
+    fn default() -> Self {
+        ChromaticAberrationDeclaration { intensity: 0.005 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BlurDeclaration {
+    /// In pixels.
+    pub radius: f32,
+}
+
+impl Default for BlurDeclaration {
+    fn default() -> Self {
+        BlurDeclaration { radius: 8.0 }
+    }
+}
+
+impl PostProcessDeclaration {
+    pub fn validate(&self) -> Result<(), String> {
+        let non_negative = [
+            (
+                "bloom intensity",
+                self.bloom.as_ref().map(|bloom| bloom.intensity),
+            ),
+            (
+                "bloom threshold",
+                self.bloom.as_ref().map(|bloom| bloom.threshold),
+            ),
+            (
+                "vignette intensity",
+                self.vignette.as_ref().map(|vignette| vignette.intensity),
+            ),
+            (
+                "vignette smoothness",
+                self.vignette.as_ref().map(|vignette| vignette.smoothness),
+            ),
+            (
+                "grain intensity",
+                self.grain.as_ref().map(|grain| grain.intensity),
+            ),
+            (
+                "chromatic aberration intensity",
+                self.chromatic_aberration
+                    .as_ref()
+                    .map(|aberration| aberration.intensity),
+            ),
+            ("blur radius", self.blur.as_ref().map(|blur| blur.radius)),
+        ];
+        for (name, value) in non_negative {
+            if let Some(value) = value {
+                if !(value.is_finite() && value >= 0.0) {
+                    return Err(format!("the {name} is negative"));
+                }
+            }
+        }
+        if let Some(lut) = &self.lut {
+            if lut.is_empty() {
+                return Err("the color grading lookup table has no path".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Post-processing the user set up for wallpapers in their config file, by wallpaper name. They
+/// replace the effects the wallpaper's manifest declares.
+#[derive(Debug, Default, Resource)]
+pub struct PostProcessOverrides(pub HashMap<String, PostProcessDeclaration>);
+
+/// The effects run on a wallpaper camera, with what they need from the main world.
+#[derive(Component, Clone)]
+pub struct PostProcess {
+    pub declaration: PostProcessDeclaration,
+    lut: Option<Handle<Image>>,
+    /// Seconds of wallpaper time, for the grain.
+    time: f32,
+    /// Whether the camera rendered in HDR and tonemapped before it got post-processing, to put
+    /// back when it's taken off.
+    restore: (bool, Option<Tonemapping>),
+}
+
+/// Puts the active wallpaper's post-processing on the cameras drawing to the canvas. They render
+/// in HDR while they have it, with bevy's tonemapping left to the post-processing.
+#[allow(clippy::type_complexity)]
+fn apply_post_process(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    clock: Res<WallpaperClock>,
+    canvas: Res<WallpaperCanvas>,
+    parameters: Res<Parameters>,
+    quality: Res<Quality>,
+    overrides: Res<PostProcessOverrides>,
+    manifests: Res<Assets<WallpaperManifest>>,
+    mut cameras: Query<
+        (
+            Entity,
+            &mut Camera,
+            Option<&mut Tonemapping>,
+            Option<&mut PostProcess>,
+        ),
+        Without<PresentationCamera>,
+    >,
+) {
+    let declaration = parameters
+        .wallpaper()
+        .and_then(|name| overrides.0.get(name))
+        .or_else(|| {
+            parameters
+                .manifest()
+                .and_then(|manifest| manifests.get(manifest))
+                .and_then(|manifest| manifest.post.as_ref())
+        })
+        .map(|declaration| {
+            let mut declaration = declaration.clone();
+            // Left out here, so a change of preset is picked up like a change of declaration.
+            if !quality.costly_effects() {
+                declaration.bloom = None;
+                declaration.grain = None;
+                declaration.chromatic_aberration = None;
+            }
+            declaration
+        });
+
+    for (entity, mut camera, tonemapping, post_process) in &mut cameras {
+        let on_canvas =
+            matches!(&camera.target, RenderTarget::Image(image) if image == canvas.live());
+        let declaration = declaration.as_ref().filter(|_| on_canvas);
+        match (declaration, post_process) {
+            (Some(declaration), Some(mut post_process)) => {
+                if post_process.declaration != *declaration {
+                    post_process.declaration = declaration.clone();
+                    post_process.lut = load_lut(declaration, &asset_server);
+                    set_bloom(&mut commands.entity(entity), declaration);
+                }
+                post_process.time = clock.elapsed_seconds();
+            }
+            (Some(declaration), None) => {
+                let restore = (camera.hdr, tonemapping.as_deref().cloned());
+                camera.hdr = true;
+                if let Some(mut tonemapping) = tonemapping {
+                    *tonemapping = Tonemapping::Disabled;
+                }
+                let mut entity = commands.entity(entity);
+                entity.insert(PostProcess {
+                    declaration: declaration.clone(),
+                    lut: load_lut(declaration, &asset_server),
+                    time: clock.elapsed_seconds(),
+                    restore,
+                });
+                set_bloom(&mut entity, declaration);
+            }
+            (None, Some(post_process)) => {
+                let (hdr, restore_tonemapping) = post_process.restore.clone();
+                camera.hdr = hdr;
+                if let (Some(mut tonemapping), Some(restore)) = (tonemapping, restore_tonemapping) {
+                    *tonemapping = restore;
+                }
+                commands
+                    .entity(entity)
+                    .remove::<PostProcess>()
+                    .remove::<BloomSettings>();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+fn load_lut(
+    declaration: &PostProcessDeclaration,
+    asset_server: &AssetServer,
+) -> Option<Handle<Image>> {
+    declaration
+        .lut
+        .as_ref()
+        .map(|path| asset_server.load(path.as_str()))
+}
+
+/// Bloom is bevy's own, which runs before tonemapping.
+fn set_bloom(entity: &mut EntityCommands, declaration: &PostProcessDeclaration) {
+    match &declaration.bloom {
+        Some(bloom) => {
+            entity.insert(BloomSettings {
+                intensity: bloom.intensity,
+                threshold: bloom.threshold,
+                ..default()
+            });
+        }
+        None => {
+            entity.remove::<BloomSettings>();
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use bevy::core_pipeline::fullscreen_vertex_shader::fullscreen_shader_vertex_state;
+use bevy::ecs::query::QueryItem;
+use bevy::prelude::*;
+use bevy::render::extract_component::ExtractComponent;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_graph::{Node, NodeRunError, RenderGraphContext, SlotInfo, SlotType};
+use bevy::render::render_resource::{
+    AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, CachedRenderPipelineId,
+    ColorTargetState, ColorWrites, DynamicUniformBuffer, FilterMode, FragmentState, LoadOp,
+    MultisampleState, Operations, PipelineCache, PrimitiveState, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor,
+    ShaderStages, ShaderType, SpecializedRenderPipeline, SpecializedRenderPipelines, TextureFormat,
+    TextureSampleType, TextureViewDimension,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice, RenderQueue};
+use bevy::render::texture::{BevyDefault, FallbackImage};
+use bevy::render::view::{ExtractedView, ViewTarget};
+
+use super::{PostProcess, TonemappingCurve, POST_PROCESS_SHADER};
+
+impl ExtractComponent for PostProcess {
+    type Query = &'static Self;
+    type Filter = With<Camera>;
+
+    fn extract_component(item: QueryItem<Self::Query>) -> Self {
+        item.clone()
+    }
+}
+
+/// The settings of every effect, with 0 leaving an effect out.
+#[derive(Clone, ShaderType)]
+struct PostProcessUniform {
+    blur_radius: f32,
+    chromatic_aberration: f32,
+    /// 0 for none, then in the order of [`TonemappingCurve`].
+    tonemapping: u32,
+    /// Whether the lookup table is bound rather than the fallback image.
+    lut: u32,
+    vignette_intensity: f32,
+    vignette_radius: f32,
+    vignette_smoothness: f32,
+    grain_intensity: f32,
+    time: f32,
+}
+
+impl PostProcessUniform {
+    fn new(post_process: &PostProcess, has_lut: bool) -> Self {
+        let declaration = &post_process.declaration;
+        let vignette = declaration.vignette.clone().unwrap_or_default();
+        PostProcessUniform {
+            blur_radius: declaration.blur.as_ref().map_or(0.0, |blur| blur.radius),
+            chromatic_aberration: declaration
+                .chromatic_aberration
+                .as_ref()
+                .map_or(0.0, |aberration| aberration.intensity),
+            tonemapping: match declaration.tonemapping {
+                TonemappingCurve::None => 0,
+                TonemappingCurve::Reinhard => 1,
+                TonemappingCurve::ReinhardLuminance => 2,
+                TonemappingCurve::Aces => 3,
+            },
+            lut: u32::from(has_lut),
+            vignette_intensity: if declaration.vignette.is_some() {
+                vignette.intensity
+            } else {
+                0.0
+            },
+            vignette_radius: vignette.radius,
+            vignette_smoothness: vignette.smoothness,
+            grain_intensity: declaration
+                .grain
+                .as_ref()
+                .map_or(0.0, |grain| grain.intensity),
+            time: post_process.time,
+        }
+    }
+}
+
+#[derive(Default, Resource)]
+pub(super) struct PostProcessUniforms(DynamicUniformBuffer<PostProcessUniform>);
+
+/// One fullscreen pass of the post-processing.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Pass {
+    BlurHorizontal,
+    BlurVertical,
+    Composite,
+}
+
+impl Pass {
+    fn entry_point(self) -> &'static str {
+        match self {
+            Pass::BlurHorizontal => "blur_horizontal",
+            Pass::BlurVertical => "blur_vertical",
+            Pass::Composite => "composite",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) struct PostProcessPipelineKey {
+    pass: Pass,
+    format: TextureFormat,
+}
+
+#[derive(Resource)]
+pub(super) struct PostProcessPipeline {
+    layout: BindGroupLayout,
+    sampler: Sampler,
+}
+
+impl FromWorld for PostProcessPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let texture = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        };
+        let layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("post_process_layout"),
+            entries: &[
+                texture(0),
+                sampler(1),
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(PostProcessUniform::min_size()),
+                    },
+                    count: None,
+                },
+                texture(3),
+                sampler(4),
+            ],
+        });
+        let sampler = render_device.create_sampler(&SamplerDescriptor {
+            label: Some("post_process_sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..default()
+        });
+        PostProcessPipeline { layout, sampler }
+    }
+}
+
+impl SpecializedRenderPipeline for PostProcessPipeline {
+    type Key = PostProcessPipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            label: Some("post_process_pipeline".into()),
+            layout: Some(vec![self.layout.clone()]),
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: POST_PROCESS_SHADER.typed(),
+                shader_defs: Vec::new(),
+                entry_point: key.pass.entry_point().into(),
+                targets: vec![Some(ColorTargetState {
+                    format: key.format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+        }
+    }
+}
+
+/// The passes to run on a view and where its settings are in [`PostProcessUniforms`].
+#[derive(Component)]
+pub(super) struct ViewPostProcess {
+    passes: Vec<CachedRenderPipelineId>,
+    uniform_offset: u32,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) fn prepare_post_process(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut pipeline_cache: ResMut<PipelineCache>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<PostProcessPipeline>>,
+    pipeline: Res<PostProcessPipeline>,
+    mut uniforms: ResMut<PostProcessUniforms>,
+    images: Res<RenderAssets<Image>>,
+    views: Query<(Entity, &ExtractedView, &PostProcess)>,
+) {
+    uniforms.0.clear();
+    for (entity, view, post_process) in &views {
+        let has_lut = post_process
+            .lut
+            .as_ref()
+            .map_or(false, |lut| images.contains_key(lut));
+        let uniform = PostProcessUniform::new(post_process, has_lut);
+        let format = if view.hdr {
+            ViewTarget::TEXTURE_FORMAT_HDR
+        } else {
+            TextureFormat::bevy_default()
+        };
+        let mut passes = Vec::new();
+        if uniform.blur_radius > 0.0 {
+            passes.push(Pass::BlurHorizontal);
+            passes.push(Pass::BlurVertical);
+        }
+        passes.push(Pass::Composite);
+        let passes = passes
+            .into_iter()
+            .map(|pass| {
+                pipelines.specialize(
+                    &mut pipeline_cache,
+                    &pipeline,
+                    PostProcessPipelineKey { pass, format },
+                )
+            })
+            .collect();
+        let uniform_offset = uniforms.0.push(uniform);
+        commands.entity(entity).insert(ViewPostProcess {
+            passes,
+            uniform_offset,
+        });
+    }
+    uniforms.0.write_buffer(&render_device, &render_queue);
+}
+
+/// Runs the post-processing passes of a view, each one from its main texture into the other.
+pub(super) struct PostProcessNode {
+    query: QueryState<(
+        &'static ViewTarget,
+        &'static ViewPostProcess,
+        &'static PostProcess,
+    )>,
+}
+
+impl PostProcessNode {
+    pub const IN_VIEW: &'static str = "view";
+
+    pub fn new(world: &mut World) -> Self {
+        PostProcessNode {
+            query: QueryState::new(world),
+        }
+    }
+}
+
+impl Node for PostProcessNode {
+    fn input(&self) -> Vec<SlotInfo> {
+        vec![SlotInfo::new(PostProcessNode::IN_VIEW, SlotType::Entity)]
+    }
+
+    fn update(&mut self, world: &mut World) {
+        self.query.update_archetypes(world);
+    }
+
+    fn run(
+        &self,
+        graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let view_entity = graph.get_input_entity(Self::IN_VIEW)?;
+        let Ok((target, view_post_process, post_process)) =
+            self.query.get_manual(world, view_entity)
+        else {
+            return Ok(());
+        };
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = world.resource::<PostProcessPipeline>();
+        let Some(uniforms) = world.resource::<PostProcessUniforms>().0.binding() else {
+            return Ok(());
+        };
+        // Every pass has to be ready, or the image would show up half processed.
+        let Some(passes) = view_post_process
+            .passes
+            .iter()
+            .map(|id| pipeline_cache.get_render_pipeline(*id))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(());
+        };
+        let fallback = world.resource::<FallbackImage>();
+        let lut = post_process
+            .lut
+            .as_ref()
+            .and_then(|lut| world.resource::<RenderAssets<Image>>().get(lut))
+            .unwrap_or(&**fallback);
+
+        for render_pipeline in passes {
+            let post_process_write = target.post_process_write();
+            let bind_group = render_context
+                .render_device
+                .create_bind_group(&BindGroupDescriptor {
+                    label: Some("post_process_bind_group"),
+                    layout: &pipeline.layout,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::TextureView(post_process_write.source),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::Sampler(&pipeline.sampler),
+                        },
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: uniforms.clone(),
+                        },
+                        BindGroupEntry {
+                            binding: 3,
+                            resource: BindingResource::TextureView(&lut.texture_view),
+                        },
+                        BindGroupEntry {
+                            binding: 4,
+                            resource: BindingResource::Sampler(&pipeline.sampler),
+                        },
+                    ],
+                });
+            let mut render_pass =
+                render_context
+                    .command_encoder
+                    .begin_render_pass(&RenderPassDescriptor {
+                        label: Some("post_process_pass"),
+                        color_attachments: &[Some(RenderPassColorAttachment {
+                            view: post_process_write.destination,
+                            resolve_target: None,
+                            ops: Operations {
+                                load: LoadOp::Clear(Default::default()),
+                                store: true,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                    });
+            render_pass.set_pipeline(render_pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[view_post_process.uniform_offset]);
+            render_pass.draw(0..3, 0..1);
+        }
+        Ok(())
+    }
+}
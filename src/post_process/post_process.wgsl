@@ -0,0 +1,120 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader
+#import desktop::color
+#import desktop::hash
+
+struct PostProcess {
+    blur_radius: f32,
+    chromatic_aberration: f32,
+    tonemapping: u32,
+    lut: u32,
+    vignette_intensity: f32,
+    vignette_radius: f32,
+    vignette_smoothness: f32,
+    grain_intensity: f32,
+    time: f32,
+};
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> settings: PostProcess;
+@group(0) @binding(3)
+var lut: texture_2d<f32>;
+@group(0) @binding(4)
+var lut_sampler: sampler;
+
+// Taps on either side of the center of the blur.
+let BLUR_TAPS: i32 = 8;
+
+// A gaussian blur along one axis, with its taps spread over the blur radius.
+fn blur(uv: vec2<f32>, axis: vec2<f32>) -> vec4<f32> {
+    let texel = axis / vec2<f32>(textureDimensions(source));
+    let spacing = settings.blur_radius / f32(BLUR_TAPS);
+    var sum = vec4<f32>(0.0);
+    var weights = 0.0;
+    for (var i = -BLUR_TAPS; i <= BLUR_TAPS; i += 1) {
+        // The radius ends at about 2.5 standard deviations.
+        let x = f32(i) / f32(BLUR_TAPS) * 2.5;
+        let weight = exp(-0.5 * x * x);
+        sum += textureSample(source, source_sampler, uv + texel * f32(i) * spacing) * weight;
+        weights += weight;
+    }
+    return sum / weights;
+}
+
+@fragment
+fn blur_horizontal(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(1.0, 0.0));
+}
+
+@fragment
+fn blur_vertical(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(0.0, 1.0));
+}
+
+fn tonemap_aces(c: vec3<f32>) -> vec3<f32> {
+    return clamp((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn tonemap(c: vec3<f32>) -> vec3<f32> {
+    switch settings.tonemapping {
+        case 1u: {
+            return c / (1.0 + c);
+        }
+        case 2u: {
+            let l = luminance(c);
+            return c / (1.0 + l);
+        }
+        case 3u: {
+            return tonemap_aces(c);
+        }
+        default: {
+            return clamp(c, vec3<f32>(0.0), vec3<f32>(1.0));
+        }
+    }
+}
+
+// Looks the color up in a strip of N slices of N by N texels, blending the two slices either
+// side of its blue. The table is indexed and filled with sRGB, which sampling decodes.
+fn grade(c: vec3<f32>) -> vec3<f32> {
+    let size = f32(textureDimensions(lut).y);
+    let index = clamp(linear_to_srgb(c), vec3<f32>(0.0), vec3<f32>(1.0)) * (size - 1.0);
+    let slice = floor(index.b);
+    let within = (index.rg + 0.5) / vec2<f32>(size * size, size);
+    let first = within + vec2<f32>(slice / size, 0.0);
+    let second = within + vec2<f32>(min(slice + 1.0, size - 1.0) / size, 0.0);
+    let a = textureSampleLevel(lut, lut_sampler, first, 0.0).rgb;
+    let b = textureSampleLevel(lut, lut_sampler, second, 0.0).rgb;
+    return mix(a, b, index.b - slice);
+}
+
+@fragment
+fn composite(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let center = in.uv - 0.5;
+    let offset = center * settings.chromatic_aberration;
+    let base = textureSample(source, source_sampler, in.uv);
+    let red = textureSample(source, source_sampler, in.uv + offset).r;
+    let blue = textureSample(source, source_sampler, in.uv - offset).b;
+    var color = tonemap(vec3<f32>(red, base.g, blue));
+
+    if (settings.lut != 0u) {
+        color = grade(color);
+    }
+
+    // 0 at the center and 1 in the corners.
+    let radius = length(center) * 2.0 / sqrt(2.0);
+    let edge = smoothstep(
+        settings.vignette_radius,
+        settings.vignette_radius + max(settings.vignette_smoothness, 0.0001),
+        radius
+    );
+    color *= 1.0 - settings.vignette_intensity * edge;
+
+    // New grain 24 times a second, like film.
+    let grain = hash13(vec3<f32>(in.position.xy, floor(settings.time * 24.0))) - 0.5;
+    color += grain * settings.grain_intensity;
+
+    return vec4<f32>(max(color, vec3<f32>(0.0)), base.a);
+}
@@ -0,0 +1,107 @@
+use bevy::app::{App, CoreStage, Plugin};
+use bevy::ecs::prelude::*;
+use bevy::utils::{
+    tracing::{info, warn},
+    Duration,
+};
+use crossbeam_channel::Receiver;
+use serde::Deserialize;
+use windows::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+use crate::wallpaper_render_plugin::EventLoopWaker;
+
+/// How often the power status is checked. Windows can tell us about changes with a message, but
+/// only to a top-level window, and ours is a child of the desktop.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How the machine is powered, from least to most in need of saving power.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+pub enum PowerState {
+    #[default]
+    Plugged,
+    Battery,
+    /// On battery with Windows' battery saver on.
+    Saver,
+}
+
+impl PowerState {
+    /// The current state, or `None` if Windows can't tell.
+    fn query() -> Option<Self> {
+        let mut status = SYSTEM_POWER_STATUS::default();
+        if !unsafe { GetSystemPowerStatus(&mut status) }.as_bool() {
+            return None;
+        }
+        Some(match (status.ACLineStatus, status.SystemStatusFlag) {
+            (_, 1) => PowerState::Saver,
+            (0, _) => PowerState::Battery,
+            // Desktops without a battery report being plugged in, and an unknown line status
+            // doesn't call for saving power either.
+            _ => PowerState::Plugged,
+        })
+    }
+}
+
+/// Sent when the machine starts or stops running on battery, or battery saver goes on or off.
+#[derive(Debug, Clone, Copy)]
+pub struct PowerStateChanged(pub PowerState);
+
+/// Keeps [`Power`] up to date on a thread of its own, waking the event loop when it changes.
+#[derive(Default)]
+pub struct PowerPlugin;
+
+impl Plugin for PowerPlugin {
+    fn build(&self, app: &mut App) {
+        let state = PowerState::query().unwrap_or_default();
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let waker = app.world.get_resource::<EventLoopWaker>().cloned();
+        let spawned = std::thread::Builder::new()
+            .name("power status".to_string())
+            .spawn(move || {
+                let mut last = state;
+                loop {
+                    std::thread::sleep(POLL_INTERVAL);
+                    let Some(state) = PowerState::query() else {
+                        continue;
+                    };
+                    if state == last {
+                        continue;
+                    }
+                    last = state;
+                    if sender.send(state).is_err() {
+                        return;
+                    }
+                    if let Some(waker) = &waker {
+                        waker.wake();
+                    }
+                }
+            });
+        if let Err(err) = spawned {
+            warn!("Can't start watching the power status: {}", err);
+        }
+        app.insert_resource(Power { state, receiver })
+            .add_event::<PowerStateChanged>()
+            .add_system_to_stage(CoreStage::PreUpdate, update_power_state);
+    }
+}
+
+/// How the machine is powered.
+#[derive(Resource)]
+pub struct Power {
+    state: PowerState,
+    receiver: Receiver<PowerState>,
+}
+
+impl Power {
+    pub fn state(&self) -> PowerState {
+        self.state
+    }
+}
+
+fn update_power_state(mut power: ResMut<Power>, mut changed: EventWriter<PowerStateChanged>) {
+    let Some(state) = power.receiver.try_iter().last() else {
+        return;
+    };
+    info!("Power state changed to {:?}", state);
+    power.state = state;
+    changed.send(PowerStateChanged(state));
+}
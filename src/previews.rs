@@ -0,0 +1,299 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use bevy::app::{App, Plugin};
+use bevy::asset::FileAssetIo;
+use bevy::ecs::prelude::*;
+use bevy::utils::{
+    tracing::{info, warn},
+    HashMap, HashSet,
+};
+use crossbeam_channel::{Receiver, Sender};
+use image::codecs::png::PngDecoder;
+use image::AnimationDecoder;
+
+use crate::config::Config;
+use crate::content_hash::ContentHash;
+use crate::static_fallback::StaticFallback;
+use crate::wallpaper::{WallpaperManifest, WallpaperRegistry};
+use crate::wallpaper_render_plugin::EventLoopWaker;
+use crate::warm_up::WarmUp;
+
+/// The size previews are rendered at, which is about what pickers show them at.
+const PREVIEW_SIZE: &str = "320x180";
+
+/// How much of the wallpaper the animated previews show, on a loop.
+const PREVIEW_DURATION: &str = "4s";
+
+const PREVIEW_FRAME_RATE: &str = "15";
+
+/// Called with the path of a wallpaper's preview, or what went wrong finding it.
+pub type PreviewCallback = Box<dyn FnOnce(Result<PathBuf, String>) + Send>;
+
+/// Previews of the wallpapers that have a manifest, found or rendered on request and, one at a
+/// time, in the background.
+///
+/// A preview is a still image. Rendered previews also have a short APNG that loops next to it,
+/// with the same name and an `.apng` extension, and the still is its middle frame.
+#[derive(Resource)]
+pub struct Previews {
+    /// The previews found so far, by wallpaper name.
+    previews: HashMap<String, PathBuf>,
+    /// Requests waiting for a preview that's being looked for, by wallpaper name.
+    waiting: HashMap<String, Vec<PreviewCallback>>,
+    /// The wallpapers previews have been looked for, whether one was found or not.
+    looked_for: HashSet<String>,
+    /// The config file given on the command line, which exports read instead of the default one.
+    config: Option<PathBuf>,
+    sender: Sender<(String, Result<PathBuf, String>)>,
+    receiver: Receiver<(String, Result<PathBuf, String>)>,
+    /// Wakes the event loop to hand over a preview while the wallpaper is stopped to save power.
+    waker: Option<EventLoopWaker>,
+}
+
+impl Default for Previews {
+    fn default() -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        Previews {
+            previews: HashMap::default(),
+            waiting: HashMap::default(),
+            looked_for: HashSet::default(),
+            config: None,
+            sender,
+            receiver,
+            waker: None,
+        }
+    }
+}
+
+impl Previews {
+    /// Calls `done` with the preview of the named wallpaper once it's been found, rendering it
+    /// with `desktop export` in the background if the manifest doesn't name one.
+    ///
+    /// Rendered previews are cached under a hash of the manifest, the files it refers to and the
+    /// config file the export reads, so a wallpaper only gets rendered again once one changes.
+    pub fn request(&mut self, name: &str, registry: &WallpaperRegistry, done: PreviewCallback) {
+        if let Some(preview) = self.previews.get(name) {
+            done(Ok(preview.clone()));
+            return;
+        }
+        let Some((_, manifest)) = registry
+            .manifests()
+            .find(|(wallpaper, _)| *wallpaper == name)
+        else {
+            done(Err(format!(
+                "{name:?} has no manifest to make a preview from"
+            )));
+            return;
+        };
+        if let Some(waiting) = self.waiting.get_mut(name) {
+            waiting.push(done);
+            return;
+        }
+        self.waiting.insert(name.to_string(), vec![done]);
+        self.looked_for.insert(name.to_string());
+
+        // Manifests given on the command line have absolute paths, which `join` keeps as they are.
+        let manifest = FileAssetIo::get_base_path().join("assets").join(manifest);
+        let wallpaper = name.to_string();
+        let config = Config::path(self.config.as_deref());
+        let sender = self.sender.clone();
+        let waker = self.waker.clone();
+        let spawned = std::thread::Builder::new()
+            .name(format!("preview {name}"))
+            .spawn(move || {
+                let preview = cache_dir()
+                    .ok_or_else(|| "LOCALAPPDATA isn't set".to_string())
+                    .and_then(|cache| {
+                        find_preview(&wallpaper, &manifest, config.as_deref(), &cache)
+                    });
+                let _ = sender.send((wallpaper, preview));
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            });
+        if let Err(err) = spawned {
+            let err = format!("can't start looking for a preview: {err}");
+            for done in self.waiting.remove(name).into_iter().flatten() {
+                done(Err(err.clone()));
+            }
+        }
+    }
+
+    /// The previews found so far, by wallpaper name, in order of name.
+    pub fn list(&self) -> Vec<(&str, &Path)> {
+        let mut previews: Vec<_> = self
+            .previews
+            .iter()
+            .map(|(name, path)| (name.as_str(), path.as_path()))
+            .collect();
+        previews.sort();
+        previews
+    }
+}
+
+/// Hands previews to the requests waiting for them, and looks for the previews of the wallpapers
+/// no one has asked about yet in the background.
+#[derive(Default)]
+pub struct PreviewPlugin {
+    /// The config file the wallpaper was started with, if one was given on the command line.
+    pub config: Option<PathBuf>,
+}
+
+impl Plugin for PreviewPlugin {
+    fn build(&self, app: &mut App) {
+        let waker = app.world.get_resource::<EventLoopWaker>().cloned();
+        app.insert_resource(Previews {
+            waker,
+            config: self.config.clone(),
+            ..Previews::default()
+        })
+        .add_system(receive_previews)
+        .add_system(look_for_previews.after(receive_previews));
+    }
+}
+
+/// Where rendered previews are kept, in the local app data folder since they can be made again.
+fn cache_dir() -> Option<PathBuf> {
+    let local_app_data = std::env::var_os("LOCALAPPDATA")?;
+    Some(Path::new(&local_app_data).join("desktop").join("previews"))
+}
+
+fn receive_previews(mut previews: ResMut<Previews>) {
+    let previews = &mut *previews;
+    for (name, preview) in previews.receiver.try_iter() {
+        match &preview {
+            Ok(path) => {
+                previews.previews.insert(name.clone(), path.clone());
+            }
+            Err(err) => warn!("No preview of {}: {}", name, err),
+        }
+        for done in previews.waiting.remove(&name).into_iter().flatten() {
+            done(preview.clone());
+        }
+    }
+}
+
+/// Looks for the preview of the next wallpaper no one has looked for one of yet, once nothing
+/// else is being looked for, so that rendering them doesn't hold up requests for long. Waits for
+/// the warm-up to finish and doesn't render while the wallpaper is stopped to save power.
+fn look_for_previews(
+    mut previews: ResMut<Previews>,
+    registry: Res<WallpaperRegistry>,
+    warm_up: Option<Res<WarmUp>>,
+    fallback: Option<Res<StaticFallback>>,
+) {
+    if !previews.waiting.is_empty()
+        || warm_up.map_or(false, |warm_up| !warm_up.finished())
+        || fallback.map_or(false, |fallback| fallback.is_static())
+    {
+        return;
+    }
+    let next = registry
+        .manifests()
+        .map(|(name, _)| name)
+        .find(|name| !previews.looked_for.contains(*name))
+        .map(str::to_string);
+    if let Some(name) = next {
+        previews.request(&name, &registry, Box::new(|_| {}));
+    }
+}
+
+/// The preview the manifest at `path` names, or the cached one rendered from it, rendering it
+/// first if there isn't one yet. Exports read `config`, or no config file if it's `None`.
+fn find_preview(
+    name: &str,
+    path: &Path,
+    config: Option<&Path>,
+    cache: &Path,
+) -> Result<PathBuf, String> {
+    let source =
+        std::fs::read(path).map_err(|err| format!("can't read {}: {err}", path.display()))?;
+    let manifest: WallpaperManifest = ron::de::from_bytes(&source)
+        .map_err(|err| format!("can't parse {}: {err}", path.display()))?;
+    let folder = path.parent().unwrap_or_else(|| Path::new(""));
+    if let Some(preview) = &manifest.preview {
+        return Ok(folder.join(preview));
+    }
+
+    // Previews look different once the renderer or their settings change, too, and the config
+    // file sets the quality, parameters and post-processing they're exported with.
+    let mut hash = ContentHash::default();
+    hash.write(env!("CARGO_PKG_VERSION").as_bytes());
+    hash.write(format!("{PREVIEW_SIZE} {PREVIEW_DURATION} {PREVIEW_FRAME_RATE}").as_bytes());
+    if let Some(config) = config {
+        hash.write(
+            &std::fs::read(config)
+                .map_err(|err| format!("can't read {}: {err}", config.display()))?,
+        );
+    }
+    hash.write(&source);
+    for file in manifest.files() {
+        let file = folder.join(file);
+        hash.write(
+            &std::fs::read(&file).map_err(|err| format!("can't read {}: {err}", file.display()))?,
+        );
+    }
+    let key = hash.key();
+    let still = cache.join(format!("{key}.png"));
+    let animation = cache.join(format!("{key}.apng"));
+    if !still.is_file() || !animation.is_file() {
+        std::fs::create_dir_all(cache)
+            .map_err(|err| format!("can't create {}: {err}", cache.display()))?;
+        render(name, config, &still, &animation)?;
+    }
+    Ok(still)
+}
+
+/// Exports a loop of the named wallpaper to `animation`, and saves its middle frame to `still`.
+fn render(name: &str, config: Option<&Path>, still: &Path, animation: &Path) -> Result<(), String> {
+    info!("Rendering a preview of {}", name);
+    // Written under another name first, so an export that gets cut off isn't taken for a
+    // finished one.
+    let partial = animation.with_extension("partial.apng");
+    let exe = std::env::current_exe().map_err(|err| err.to_string())?;
+    let mut command = Command::new(exe);
+    command
+        .arg("export")
+        .arg(name)
+        .arg("-o")
+        .arg(&partial)
+        .args([
+            "--size",
+            PREVIEW_SIZE,
+            "--duration",
+            PREVIEW_DURATION,
+            "--fps",
+            PREVIEW_FRAME_RATE,
+        ]);
+    if let Some(config) = config {
+        command.arg("--config").arg(config);
+    }
+    let status = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|err| format!("can't start exporting: {err}"))?;
+    if !status.success() {
+        let _ = std::fs::remove_file(&partial);
+        return Err(format!("the export exited with {status}"));
+    }
+
+    let file =
+        File::open(&partial).map_err(|err| format!("can't open {}: {err}", partial.display()))?;
+    let frames = PngDecoder::new(BufReader::new(file))
+        .and_then(|decoder| decoder.apng().into_frames().collect_frames())
+        .map_err(|err| format!("can't decode {}: {err}", partial.display()))?;
+    let middle = frames
+        .get(frames.len() / 2)
+        .ok_or_else(|| "the export has no frames".to_string())?;
+    middle
+        .buffer()
+        .save(still)
+        .map_err(|err| format!("can't write {}: {err}", still.display()))?;
+    std::fs::rename(&partial, animation)
+        .map_err(|err| format!("can't move {}: {err}", partial.display()))
+}
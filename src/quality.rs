@@ -0,0 +1,237 @@
+use std::str::FromStr;
+
+use bevy::app::{App, CoreStage, Plugin};
+use bevy::ecs::prelude::*;
+use bevy::pbr::{
+    DirectionalLight, DirectionalLightShadowMap, PointLight, PointLightShadowMap, SpotLight,
+};
+use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
+use bevy::render::view::Msaa;
+use bevy::utils::{tracing::info, HashMap};
+use serde::Deserialize;
+
+use crate::parameters::{Parameters, ResolveParameters};
+use crate::power::{Power, PowerState};
+use crate::render_scale::RenderScale;
+
+/// How much work wallpapers put into each frame, so that laptops can run the same wallpapers
+/// cheaper. Set in the config file or with `--quality`.
+///
+/// Besides what each kind of wallpaper does with it, a preset sets MSAA, shadows, the render scale
+/// and which post-processing effects run. Shaders see it as one of the `QUALITY_LOW`,
+/// `QUALITY_MEDIUM`, `QUALITY_HIGH` and `QUALITY_ULTRA` defines, to test with `#ifdef`.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Deserialize,
+    Resource,
+    ExtractResource,
+)]
+pub enum Quality {
+    Low,
+    Medium,
+    #[default]
+    High,
+    /// Bigger shadow maps and more rays than high, for machines with power to spare.
+    Ultra,
+}
+
+impl FromStr for Quality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" => Ok(Quality::Low),
+            "medium" => Ok(Quality::Medium),
+            "high" => Ok(Quality::High),
+            "ultra" => Ok(Quality::Ultra),
+            _ => Err(format!(
+                "unknown quality {s:?}, expected low, medium, high or ultra"
+            )),
+        }
+    }
+}
+
+impl Quality {
+    /// The define shaders are compiled with.
+    pub fn shader_def(self) -> String {
+        match self {
+            Quality::Low => "QUALITY_LOW",
+            Quality::Medium => "QUALITY_MEDIUM",
+            Quality::High => "QUALITY_HIGH",
+            Quality::Ultra => "QUALITY_ULTRA",
+        }
+        .to_string()
+    }
+
+    /// The fraction of the window's resolution wallpapers render at, unless the config file sets
+    /// one.
+    pub fn render_scale(self) -> f32 {
+        match self {
+            Quality::Low => 0.5,
+            Quality::Medium => 0.75,
+            Quality::High | Quality::Ultra => 1.0,
+        }
+    }
+
+    /// Whether bloom, grain and chromatic aberration run. The rest of the post-processing changes
+    /// how a wallpaper looks too much to leave out.
+    pub fn costly_effects(self) -> bool {
+        self > Quality::Low
+    }
+
+    /// MSAA samples, of which wgpu only supports 1 or 4.
+    fn msaa_samples(self) -> u32 {
+        match self {
+            Quality::Low => 1,
+            Quality::Medium | Quality::High | Quality::Ultra => 4,
+        }
+    }
+
+    /// The size of point and spot light shadow maps, with directional ones four times as big, or
+    /// `None` for no shadows. High is bevy's default.
+    fn shadow_map_size(self) -> Option<usize> {
+        match self {
+            Quality::Low => None,
+            Quality::Medium => Some(512),
+            Quality::High => Some(1024),
+            Quality::Ultra => Some(2048),
+        }
+    }
+}
+
+/// Picks the [`Quality`] preset for the active wallpaper and power state, and applies what it
+/// sets outside of wallpapers themselves.
+#[derive(Default)]
+pub struct QualityPlugin;
+
+impl Plugin for QualityPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Quality>()
+            .init_resource::<QualityPresets>()
+            .init_resource::<FullQuality>()
+            .add_plugin(ExtractResourcePlugin::<Quality>::default())
+            .add_system(choose_quality.label(ChooseQuality).after(ResolveParameters))
+            .add_system_to_stage(CoreStage::PostUpdate, apply_quality)
+            .add_system_to_stage(CoreStage::PostUpdate, apply_shadows);
+    }
+}
+
+/// Label for the system that updates [`Quality`]. Systems going by it should run after it to
+/// pick up changes in the same frame.
+#[derive(SystemLabel)]
+pub struct ChooseQuality;
+
+/// Which [`Quality`] preset is used when.
+#[derive(Debug, Default, Resource)]
+pub struct QualityPresets {
+    /// The preset wallpapers run at unless another one is set for them.
+    pub global: Quality,
+    /// Presets by wallpaper name, replacing the global one while that wallpaper is up.
+    pub wallpapers: HashMap<String, Quality>,
+    /// The highest preset wallpapers run at in each power state.
+    pub power: HashMap<PowerState, Quality>,
+    /// The render scale set in the config file, in place of the preset's.
+    pub render_scale: Option<f32>,
+}
+
+/// Raises the preset to at least [`Quality::High`] while it's set, whatever the power state, for
+/// frames that are kept rather than just shown.
+#[derive(Debug, Default, Resource)]
+pub struct FullQuality(pub bool);
+
+fn choose_quality(
+    presets: Res<QualityPresets>,
+    parameters: Res<Parameters>,
+    power: Option<Res<Power>>,
+    full_quality: Res<FullQuality>,
+    mut quality: ResMut<Quality>,
+) {
+    let wanted = parameters
+        .wallpaper()
+        .and_then(|name| presets.wallpapers.get(name))
+        .copied()
+        .unwrap_or(presets.global);
+    let wanted = power
+        .and_then(|power| presets.power.get(&power.state()))
+        .map_or(wanted, |highest| wanted.min(*highest));
+    let wanted = if full_quality.0 {
+        wanted.max(Quality::High)
+    } else {
+        wanted
+    };
+    if *quality != wanted {
+        info!("Switching to {:?} quality", wanted);
+        *quality = wanted;
+    }
+}
+
+fn apply_quality(
+    quality: Res<Quality>,
+    presets: Res<QualityPresets>,
+    mut msaa: ResMut<Msaa>,
+    mut point_shadows: ResMut<PointLightShadowMap>,
+    mut directional_shadows: ResMut<DirectionalLightShadowMap>,
+    render_scale: Option<ResMut<RenderScale>>,
+) {
+    if !quality.is_changed() {
+        return;
+    }
+    msaa.samples = quality.msaa_samples();
+    if let Some(size) = quality.shadow_map_size() {
+        point_shadows.size = size;
+        directional_shadows.size = size * 4;
+    }
+    if let Some(mut render_scale) = render_scale {
+        render_scale.max = presets.render_scale.unwrap_or(quality.render_scale());
+    }
+}
+
+/// Whether a light was set up to cast shadows, before the quality preset had its say.
+#[derive(Component)]
+struct CastsShadows(bool);
+
+/// Turns shadows off on every light while the preset has none, and back on for the lights that
+/// had them.
+fn apply_shadows(
+    mut commands: Commands,
+    quality: Res<Quality>,
+    mut point_lights: Query<(Entity, &mut PointLight, Option<&CastsShadows>)>,
+    mut spot_lights: Query<(Entity, &mut SpotLight, Option<&CastsShadows>)>,
+    mut directional_lights: Query<(Entity, &mut DirectionalLight, Option<&CastsShadows>)>,
+) {
+    let shadows = quality.shadow_map_size().is_some();
+    // What the light's shadows should be set to, if that isn't what they are.
+    let mut wanted = |entity: Entity, enabled: bool, casts: Option<&CastsShadows>| {
+        let casts = casts.map_or_else(
+            || {
+                commands.entity(entity).insert(CastsShadows(enabled));
+                enabled
+            },
+            |casts| casts.0,
+        );
+        (enabled != (casts && shadows)).then_some(casts && shadows)
+    };
+    for (entity, mut light, casts) in &mut point_lights {
+        if let Some(enabled) = wanted(entity, light.shadows_enabled, casts) {
+            light.shadows_enabled = enabled;
+        }
+    }
+    for (entity, mut light, casts) in &mut spot_lights {
+        if let Some(enabled) = wanted(entity, light.shadows_enabled, casts) {
+            light.shadows_enabled = enabled;
+        }
+    }
+    for (entity, mut light, casts) in &mut directional_lights {
+        if let Some(enabled) = wanted(entity, light.shadows_enabled, casts) {
+            light.shadows_enabled = enabled;
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use bevy::app::{App, CoreStage, Plugin};
+use bevy::ecs::prelude::*;
+use bevy::render::main_graph::node::CAMERA_DRIVER;
+use bevy::render::render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext};
+use bevy::render::render_resource::{Buffer, BufferDescriptor, BufferUsages, MapMode};
+use bevy::render::renderer::{RenderContext, RenderDevice, RenderQueue};
+use bevy::render::view::WindowSystem;
+use bevy::render::{RenderApp, RenderStage};
+use bevy::time::Time;
+use bevy::utils::{tracing::info, Duration, Instant};
+use crossbeam_channel::{Receiver, Sender};
+use wgpu::{BufferAsyncError, Features, Maintain, QuerySet, QuerySetDescriptor, QueryType};
+
+use crate::canvas::WallpaperCanvas;
+use crate::wallpaper::SIMULATION_NODE;
+
+const TIMER_BEGIN_NODE: &str = "render_scale_timer_begin";
+const TIMER_END_NODE: &str = "render_scale_timer_end";
+const RENDER_TIMER_NODE: &str = "render_scale_render_timer";
+
+/// The lowest render scale the governor goes down to.
+const MIN_SCALE: f32 = 0.25;
+
+/// Render scales are rounded to multiples of this. Every change clears the canvas and the buffers
+/// of feedback wallpapers, so small ones aren't worth it.
+const SCALE_STEP: f32 = 0.05;
+
+/// How often the governor reconsiders the render scale.
+const ADJUST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Below this fraction of the budget the governor raises the render scale again. The gap keeps it
+/// from going back and forth between two scales.
+const HEADROOM: f32 = 0.6;
+
+/// How much of each new frame time goes into the running average the governor goes by.
+const SMOOTHING: f32 = 0.1;
+
+/// How many frames can be timed on the GPU at once, while earlier timings are read back.
+const MAX_TIMINGS_IN_FLIGHT: usize = 4;
+
+/// Renders wallpapers at a fraction of the window's resolution, and with a frame budget lowers
+/// that fraction while frames take longer than the budget, on the CPU or the GPU, and raises it
+/// again once they're well within it.
+///
+/// GPU time is measured with timestamp queries where the adapter supports them, and otherwise the
+/// governor goes by CPU time alone. CPU time is the update plus the render world's work after
+/// it gets the frame's swapchain texture until the render graph has been recorded, so that waiting
+/// for vsync, to get the texture or to present it, doesn't count towards the budget.
+#[derive(Default)]
+pub struct RenderScalePlugin;
+
+impl Plugin for RenderScalePlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let (render_sender, render_receiver) = crossbeam_channel::unbounded();
+        app.init_resource::<RenderScale>()
+            .init_resource::<FullResolution>()
+            .insert_resource(FrameTimes {
+                gpu: receiver,
+                render: render_receiver,
+                update_started: None,
+                average: None,
+                since_adjusted: Duration::ZERO,
+            })
+            .add_system_to_stage(CoreStage::First, start_update_timer)
+            .add_system_to_stage(CoreStage::Last, govern_render_scale);
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .insert_resource(RenderTimer {
+                sender: render_sender,
+                started: None,
+            })
+            .add_system_to_stage(
+                RenderStage::Prepare,
+                start_render_timer.after(WindowSystem::Prepare),
+            );
+        let mut graph = render_app.world.resource_mut::<RenderGraph>();
+        graph.add_node(RENDER_TIMER_NODE, RenderTimerNode);
+        graph
+            .add_node_edge(CAMERA_DRIVER, RENDER_TIMER_NODE)
+            .unwrap();
+        let render_device = render_app.world.resource::<RenderDevice>();
+        let query_set = render_device
+            .features()
+            .contains(Features::TIMESTAMP_QUERY)
+            .then(|| {
+                render_device
+                    .wgpu_device()
+                    .create_query_set(&QuerySetDescriptor {
+                        label: Some("render_scale_timestamps"),
+                        ty: QueryType::Timestamp,
+                        count: 2,
+                    })
+            });
+        let Some(query_set) = query_set else {
+            info!("The GPU can't time frames, scaling resolution by CPU time only");
+            return;
+        };
+        let resolve = render_device.create_buffer(&BufferDescriptor {
+            label: Some("render_scale_timestamps"),
+            size: TIMESTAMPS_SIZE,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let period = render_app
+            .world
+            .resource::<RenderQueue>()
+            .get_timestamp_period();
+        render_app
+            .insert_resource(GpuTimer {
+                query_set,
+                resolve,
+                period,
+                sender,
+                free: Vec::new(),
+                current: None,
+                in_flight: Vec::new(),
+            })
+            .add_system_to_stage(RenderStage::Prepare, prepare_gpu_timer)
+            .add_system_to_stage(RenderStage::Cleanup, read_gpu_timings);
+        let mut graph = render_app.world.resource_mut::<RenderGraph>();
+        graph.add_node(TIMER_BEGIN_NODE, TimerNode::Begin);
+        graph.add_node(TIMER_END_NODE, TimerNode::End);
+        graph
+            .add_node_edge(TIMER_BEGIN_NODE, CAMERA_DRIVER)
+            .unwrap();
+        if graph.get_node_state(SIMULATION_NODE).is_ok() {
+            graph
+                .add_node_edge(TIMER_BEGIN_NODE, SIMULATION_NODE)
+                .unwrap();
+        }
+        graph.add_node_edge(CAMERA_DRIVER, TIMER_END_NODE).unwrap();
+    }
+}
+
+/// How the canvas resolution is picked.
+#[derive(Debug, Clone, Resource)]
+pub struct RenderScale {
+    /// The fraction of the window's resolution wallpapers render at, at most.
+    pub max: f32,
+    /// How long frames may take, or `None` to always render at [`RenderScale::max`].
+    pub budget: Option<Duration>,
+}
+
+impl Default for RenderScale {
+    fn default() -> Self {
+        RenderScale {
+            max: 1.0,
+            budget: None,
+        }
+    }
+}
+
+/// Holds the canvas at the window's full resolution, whatever the preset and the frame budget say,
+/// while it's set. For frames that are kept, like screenshots.
+#[derive(Debug, Default, Resource)]
+pub struct FullResolution(pub bool);
+
+#[derive(Resource)]
+struct FrameTimes {
+    /// GPU time of the frames timed in the render world.
+    gpu: Receiver<Duration>,
+    /// CPU time of the frames in the render world.
+    render: Receiver<Duration>,
+    update_started: Option<Instant>,
+    /// How long frames take on average, on whichever of the CPU or GPU takes longer.
+    average: Option<f32>,
+    since_adjusted: Duration,
+}
+
+fn start_update_timer(mut times: ResMut<FrameTimes>) {
+    times.update_started = Some(Instant::now());
+}
+
+/// Times the render world from when it has the swapchain texture until the render graph has been
+/// recorded, which leaves out acquiring the texture and presenting it, where vsync waits.
+#[derive(Resource)]
+struct RenderTimer {
+    sender: Sender<Duration>,
+    started: Option<Instant>,
+}
+
+fn start_render_timer(mut timer: ResMut<RenderTimer>) {
+    timer.started = Some(Instant::now());
+}
+
+struct RenderTimerNode;
+
+impl Node for RenderTimerNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        _render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let timer = world.resource::<RenderTimer>();
+        if let Some(started) = timer.started {
+            let _ = timer.sender.send(started.elapsed());
+        }
+        Ok(())
+    }
+}
+
+fn govern_render_scale(
+    time: Res<Time>,
+    settings: Res<RenderScale>,
+    full_resolution: Res<FullResolution>,
+    mut times: ResMut<FrameTimes>,
+    mut canvas: ResMut<WallpaperCanvas>,
+) {
+    let times = &mut *times;
+    let update = times
+        .update_started
+        .map_or(Duration::ZERO, |started| started.elapsed());
+    // The render world hasn't got to this frame yet, so the last one stands in for it.
+    let cpu = update + times.render.try_iter().last().unwrap_or_default();
+    // The GPU runs a frame or two behind, so its latest timing stands in for this frame's.
+    let gpu = times.gpu.try_iter().last().unwrap_or_default();
+    let frame_time = cpu.max(gpu).as_secs_f32();
+    let average = match times.average {
+        Some(average) => average + (frame_time - average) * SMOOTHING,
+        None => frame_time,
+    };
+    times.average = Some(average);
+
+    if full_resolution.0 {
+        if canvas.scale() != 1.0 {
+            canvas.set_scale(1.0);
+        }
+        return;
+    }
+    let Some(budget) = settings.budget else {
+        if canvas.scale() != settings.max {
+            canvas.set_scale(settings.max);
+        }
+        return;
+    };
+    if canvas.scale() > settings.max {
+        canvas.set_scale(settings.max);
+    }
+    times.since_adjusted += time.delta();
+    if times.since_adjusted < ADJUST_INTERVAL {
+        return;
+    }
+    times.since_adjusted = Duration::ZERO;
+
+    let budget = budget.as_secs_f32();
+    let scale = canvas.scale();
+    let wanted = if average > budget {
+        // Rendering takes about as long as there are pixels, which go with the square of the
+        // scale.
+        (scale * (budget / average).sqrt()).min(scale - SCALE_STEP)
+    } else if average < budget * HEADROOM {
+        scale + SCALE_STEP
+    } else {
+        scale
+    };
+    let wanted = ((wanted / SCALE_STEP).round() * SCALE_STEP).clamp(MIN_SCALE, settings.max);
+    if (wanted - scale).abs() >= SCALE_STEP / 2.0 {
+        info!(
+            "Frames take {:.1}ms for a budget of {:.1}ms, rendering at {:.0}% resolution",
+            average * 1000.0,
+            budget * 1000.0,
+            wanted * 100.0
+        );
+        canvas.set_scale(wanted);
+    }
+}
+
+/// Two timestamps of 8 bytes each.
+const TIMESTAMPS_SIZE: u64 = 16;
+
+enum Timing {
+    /// Waiting for the render graph to copy the timestamps into the buffer.
+    Copying(Buffer),
+    Mapping(Buffer, Receiver<Result<(), BufferAsyncError>>),
+}
+
+/// Times the render graph with timestamps written before and after it.
+#[derive(Resource)]
+struct GpuTimer {
+    query_set: QuerySet,
+    resolve: Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    sender: Sender<Duration>,
+    /// Readback buffers that aren't in use.
+    free: Vec<Buffer>,
+    /// The buffer this frame's timestamps get copied to, if there's one to spare.
+    current: Option<Buffer>,
+    in_flight: Vec<Timing>,
+}
+
+fn prepare_gpu_timer(mut timer: ResMut<GpuTimer>, render_device: Res<RenderDevice>) {
+    timer.current = timer.free.pop().or_else(|| {
+        (timer.in_flight.len() < MAX_TIMINGS_IN_FLIGHT).then(|| {
+            render_device.create_buffer(&BufferDescriptor {
+                label: Some("render_scale_timestamps_readback"),
+                size: TIMESTAMPS_SIZE,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        })
+    });
+}
+
+enum TimerNode {
+    Begin,
+    End,
+}
+
+impl Node for TimerNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let timer = world.resource::<GpuTimer>();
+        let Some(buffer) = &timer.current else {
+            return Ok(());
+        };
+        let encoder = &mut render_context.command_encoder;
+        match self {
+            TimerNode::Begin => encoder.write_timestamp(&timer.query_set, 0),
+            TimerNode::End => {
+                encoder.write_timestamp(&timer.query_set, 1);
+                encoder.resolve_query_set(&timer.query_set, 0..2, &timer.resolve, 0);
+                encoder.copy_buffer_to_buffer(&timer.resolve, 0, buffer, 0, TIMESTAMPS_SIZE);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Maps the buffers the timestamps were copied to this frame, and sends on the timings of the
+/// ones that are mapped.
+fn read_gpu_timings(mut timer: ResMut<GpuTimer>, render_device: Res<RenderDevice>) {
+    let timer = &mut *timer;
+    if let Some(buffer) = timer.current.take() {
+        timer.in_flight.push(Timing::Copying(buffer));
+    }
+    render_device.poll(Maintain::Poll);
+
+    for timing in std::mem::take(&mut timer.in_flight) {
+        match timing {
+            Timing::Copying(buffer) => {
+                let (sender, receiver) = crossbeam_channel::bounded(1);
+                buffer.slice(..).map_async(MapMode::Read, move |result| {
+                    let _ = sender.send(result);
+                });
+                timer.in_flight.push(Timing::Mapping(buffer, receiver));
+            }
+            Timing::Mapping(buffer, receiver) => match receiver.try_recv() {
+                Ok(Ok(())) => {
+                    let ticks = {
+                        let mapped = buffer.slice(..).get_mapped_range();
+                        let begin = u64::from_le_bytes(mapped[..8].try_into().unwrap());
+                        let end = u64::from_le_bytes(mapped[8..].try_into().unwrap());
+                        end.saturating_sub(begin)
+                    };
+                    buffer.unmap();
+                    let nanos = ticks as f64 * timer.period as f64;
+                    let _ = timer.sender.send(Duration::from_nanos(nanos as u64));
+                    timer.free.push(buffer);
+                }
+                // The buffer goes away with a failed mapping, and a new one takes its place.
+                Ok(Err(_)) => {}
+                Err(_) => timer.in_flight.push(Timing::Mapping(buffer, receiver)),
+            },
+        }
+    }
+}
@@ -0,0 +1,403 @@
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bevy::app::{App, Plugin};
+use bevy::asset::Handle;
+use bevy::ecs::prelude::*;
+use bevy::math::UVec2;
+use bevy::render::main_graph::node::CAMERA_DRIVER;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext};
+use bevy::render::render_resource::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoder, Extent3d, ImageCopyBuffer,
+    ImageDataLayout, MapMode, TextureFormat,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice};
+use bevy::render::texture::{GpuImage, Image};
+use bevy::render::{Extract, RenderApp, RenderStage};
+use bevy::utils::tracing::{error, info};
+use bevy::window::RequestRedraw;
+use crossbeam_channel::{Receiver, Sender};
+use image::RgbaImage;
+use wgpu::{BufferAsyncError, Maintain, COPY_BYTES_PER_ROW_ALIGNMENT};
+
+use crate::canvas::WallpaperCanvas;
+use crate::render_scale::FullResolution;
+
+const SCREENSHOT_NODE: &str = "wallpaper_screenshot";
+
+/// How many frames are rendered at full resolution before a screenshot is taken, for the canvas
+/// to be resized and drawn again at it.
+const SETTLE_FRAMES: u32 = 3;
+
+/// Saves the live canvas to PNG files on request. While screenshots are waiting the canvas is held
+/// at the window's full resolution, so they come out at the size of the screen whatever the
+/// render scale was. The frame is read back in the background, so rendering carries on while it's
+/// taken.
+#[derive(Default)]
+pub struct ScreenshotPlugin;
+
+impl Plugin for ScreenshotPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let pending = Arc::new(AtomicUsize::new(0));
+        app.insert_resource(Screenshots {
+            sender,
+            waiting: Vec::new(),
+            settled: 0,
+            pending: pending.clone(),
+            paths: 0,
+        })
+        .add_system(send_screenshots)
+        .add_system(keep_rendering_for_screenshots);
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .insert_resource(ScreenshotQueue {
+                receiver,
+                pending,
+                source: None,
+                captures: Vec::new(),
+            })
+            .add_system_to_stage(RenderStage::Extract, extract_screenshot_source)
+            .add_system_to_stage(RenderStage::Prepare, prepare_screenshots)
+            .add_system_to_stage(RenderStage::Cleanup, read_screenshots);
+        let mut graph = render_app.world.resource_mut::<RenderGraph>();
+        graph.add_node(SCREENSHOT_NODE, ScreenshotNode);
+        graph.add_node_edge(CAMERA_DRIVER, SCREENSHOT_NODE).unwrap();
+    }
+}
+
+/// Called with how saving a screenshot went.
+pub type ScreenshotCallback = Box<dyn FnOnce(Result<(), String>) + Send>;
+
+struct ScreenshotRequest {
+    path: PathBuf,
+    done: ScreenshotCallback,
+}
+
+/// Takes screenshots of the live canvas.
+#[derive(Resource)]
+pub struct Screenshots {
+    sender: Sender<ScreenshotRequest>,
+    /// Screenshots waiting for the canvas to render at full resolution.
+    waiting: Vec<ScreenshotRequest>,
+    /// How many frames have been rendered at full resolution while screenshots were waiting.
+    settled: u32,
+    /// Screenshots that haven't been saved yet, shared with the render world.
+    pending: Arc<AtomicUsize>,
+    /// How many paths [`Screenshots::new_path`] has handed out.
+    paths: usize,
+}
+
+impl Screenshots {
+    /// Saves a frame of the canvas to `path` once it renders at full resolution, calling `done`
+    /// once it's written.
+    pub fn take(&mut self, path: PathBuf, done: ScreenshotCallback) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        self.waiting.push(ScreenshotRequest { path, done });
+    }
+
+    /// A new path in the screenshots folder, which is the only place screenshots asked for over
+    /// the control port are saved to.
+    pub fn new_path(&mut self) -> Result<PathBuf, String> {
+        let local_app_data = std::env::var_os("LOCALAPPDATA")
+            .ok_or_else(|| "there's no local app data folder to save to".to_string())?;
+        let folder = Path::new(&local_app_data)
+            .join("desktop")
+            .join("screenshots");
+        std::fs::create_dir_all(&folder)
+            .map_err(|err| format!("can't create {}: {err}", folder.display()))?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.paths += 1;
+        Ok(folder.join(format!("{now}-{}.png", self.paths)))
+    }
+}
+
+/// Holds the canvas at full resolution while there are screenshots to take, and hands the waiting
+/// ones to the render world once a few frames have been rendered at it.
+fn send_screenshots(
+    mut screenshots: ResMut<Screenshots>,
+    canvas: Res<WallpaperCanvas>,
+    full_resolution: Option<ResMut<FullResolution>>,
+) {
+    let screenshots = &mut *screenshots;
+    let pending = screenshots.pending.load(Ordering::SeqCst) > 0;
+    let held = match full_resolution {
+        Some(mut full_resolution) => {
+            if full_resolution.0 != pending {
+                full_resolution.0 = pending;
+            }
+            true
+        }
+        None => false,
+    };
+    if screenshots.waiting.is_empty() {
+        return;
+    }
+    if held && canvas.scale() < 1.0 {
+        screenshots.settled = 0;
+        return;
+    }
+    screenshots.settled += 1;
+    if screenshots.settled < SETTLE_FRAMES {
+        return;
+    }
+    screenshots.settled = 0;
+    for request in screenshots.waiting.drain(..) {
+        let _ = screenshots.sender.send(request);
+    }
+}
+
+/// A reactive event loop could wait a long time before rendering the frame that gets read back,
+/// so keep it going until the screenshots are saved.
+fn keep_rendering_for_screenshots(
+    screenshots: Res<Screenshots>,
+    mut redraw: EventWriter<RequestRedraw>,
+) {
+    if screenshots.pending.load(Ordering::SeqCst) > 0 {
+        redraw.send(RequestRedraw);
+    }
+}
+
+enum CaptureState {
+    /// Waiting for the render graph to copy the canvas into the buffer.
+    Copying,
+    Mapping(Receiver<Result<(), BufferAsyncError>>),
+}
+
+struct Capture {
+    request: ScreenshotRequest,
+    buffer: Buffer,
+    size: UVec2,
+    format: TextureFormat,
+    state: CaptureState,
+    /// Whether the render graph copied the canvas into the buffer, which it doesn't once the
+    /// canvas is no longer the size the buffer was made for.
+    copied: AtomicBool,
+}
+
+/// The screenshots on their way through the render world.
+#[derive(Resource)]
+struct ScreenshotQueue {
+    receiver: Receiver<ScreenshotRequest>,
+    pending: Arc<AtomicUsize>,
+    source: Option<Handle<Image>>,
+    captures: Vec<Capture>,
+}
+
+impl ScreenshotQueue {
+    fn finish(&self, request: ScreenshotRequest, result: Result<(), String>) {
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+        (request.done)(result);
+    }
+}
+
+fn extract_screenshot_source(
+    mut queue: ResMut<ScreenshotQueue>,
+    canvas: Extract<Res<WallpaperCanvas>>,
+) {
+    queue.source = Some(canvas.live().clone_weak());
+}
+
+fn prepare_screenshots(
+    mut queue: ResMut<ScreenshotQueue>,
+    images: Res<RenderAssets<Image>>,
+    render_device: Res<RenderDevice>,
+) {
+    let requests: Vec<_> = queue.receiver.try_iter().collect();
+    for request in requests {
+        let image = queue.source.as_ref().and_then(|source| images.get(source));
+        let Some(image) = image else {
+            queue.finish(request, Err("nothing has been rendered yet".to_string()));
+            continue;
+        };
+        let size = image.size.as_uvec2();
+        queue.captures.push(Capture {
+            request,
+            buffer: readback_buffer(&render_device, size),
+            size,
+            format: image.texture_format,
+            state: CaptureState::Copying,
+            copied: AtomicBool::new(false),
+        });
+    }
+}
+
+/// Copies the canvas into the buffers of new screenshots once every camera has drawn to it.
+struct ScreenshotNode;
+
+impl Node for ScreenshotNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let queue = world.resource::<ScreenshotQueue>();
+        let Some(image) = queue
+            .source
+            .as_ref()
+            .and_then(|source| world.resource::<RenderAssets<Image>>().get(source))
+        else {
+            return Ok(());
+        };
+        for capture in &queue.captures {
+            if matches!(capture.state, CaptureState::Copying)
+                && image.size.as_uvec2() == capture.size
+            {
+                copy_to_buffer(&mut render_context.command_encoder, image, &capture.buffer);
+                capture.copied.store(true, Ordering::SeqCst);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Maps the buffers copied to this frame, and hands the ones that are mapped to a thread that
+/// writes them out.
+fn read_screenshots(mut queue: ResMut<ScreenshotQueue>, render_device: Res<RenderDevice>) {
+    if queue.captures.is_empty() {
+        return;
+    }
+    // Runs the callbacks of mappings that are done without waiting for the others.
+    render_device.poll(Maintain::Poll);
+
+    for mut capture in std::mem::take(&mut queue.captures) {
+        match &capture.state {
+            CaptureState::Copying if !capture.copied.load(Ordering::SeqCst) => queue.finish(
+                capture.request,
+                Err("the canvas changed size before the frame was copied".to_string()),
+            ),
+            CaptureState::Copying => {
+                let (sender, receiver) = crossbeam_channel::bounded(1);
+                capture
+                    .buffer
+                    .slice(..)
+                    .map_async(MapMode::Read, move |result| {
+                        let _ = sender.send(result);
+                    });
+                capture.state = CaptureState::Mapping(receiver);
+                queue.captures.push(capture);
+            }
+            CaptureState::Mapping(receiver) => match receiver.try_recv() {
+                Ok(Ok(())) => {
+                    let frame = read_buffer(&capture.buffer, capture.size, capture.format);
+                    save(&queue, capture.request, frame);
+                }
+                Ok(Err(err)) => queue.finish(
+                    capture.request,
+                    Err(format!("can't read the frame back: {err}")),
+                ),
+                Err(_) => queue.captures.push(capture),
+            },
+        }
+    }
+}
+
+fn save(queue: &ScreenshotQueue, request: ScreenshotRequest, frame: Option<RgbaImage>) {
+    let name = format!("screenshot {}", request.path.display());
+    // Shared with the thread so the request can still be finished here if it doesn't start.
+    let request = Arc::new(Mutex::new(Some(request)));
+    let pending = queue.pending.clone();
+    let spawned = std::thread::Builder::new().name(name).spawn({
+        let request = request.clone();
+        move || {
+            let Some(ScreenshotRequest { path, done }) = request.lock().unwrap().take() else {
+                return;
+            };
+            let result = match frame {
+                Some(frame) => frame
+                    .save(&path)
+                    .map_err(|err| format!("can't write {}: {err}", path.display())),
+                None => Err("the frame read back has the wrong size".to_string()),
+            };
+            match &result {
+                Ok(()) => info!("Saved a screenshot to {}", path.display()),
+                Err(err) => error!("Screenshot failed: {}", err),
+            }
+            pending.fetch_sub(1, Ordering::SeqCst);
+            done(result);
+        }
+    });
+    if let Err(err) = spawned {
+        error!("Can't start writing a screenshot: {}", err);
+        if let Some(request) = request.lock().unwrap().take() {
+            queue.finish(
+                request,
+                Err(format!("can't start writing the screenshot: {err}")),
+            );
+        }
+    }
+}
+
+/// Makes a buffer an image of `size` can be copied into by [`copy_to_buffer`] and then read on
+/// the CPU.
+pub fn readback_buffer(render_device: &RenderDevice, size: UVec2) -> Buffer {
+    render_device.create_buffer(&BufferDescriptor {
+        label: Some("readback_buffer"),
+        size: padded_bytes_per_row(size.x) as u64 * size.y as u64,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Records copying `image`, with 4 bytes per pixel, into a buffer made by [`readback_buffer`].
+pub fn copy_to_buffer(encoder: &mut CommandEncoder, image: &GpuImage, buffer: &Buffer) {
+    let size = image.size.as_uvec2();
+    encoder.copy_texture_to_buffer(
+        image.texture.as_image_copy(),
+        ImageCopyBuffer {
+            buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded_bytes_per_row(size.x)),
+                rows_per_image: None,
+            },
+        },
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+/// Reads an image copied by [`copy_to_buffer`] out of its buffer once it's been mapped, as RGBA,
+/// and unmaps the buffer again.
+pub fn read_buffer(buffer: &Buffer, size: UVec2, format: TextureFormat) -> Option<RgbaImage> {
+    let padded_row = padded_bytes_per_row(size.x) as usize;
+    let row = size.x as usize * 4;
+    let mut data: Vec<u8> = {
+        let mapped = buffer.slice(..).get_mapped_range();
+        mapped
+            .chunks(padded_row)
+            .flat_map(|padded| &padded[..row])
+            .copied()
+            .collect()
+    };
+    buffer.unmap();
+    if matches!(
+        format,
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+    ) {
+        for pixel in data.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+    RgbaImage::from_raw(size.x, size.y, data)
+}
+
+/// Rows of a copy into a buffer have to start at multiples of 256 bytes.
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let row = width * 4;
+    (row + COPY_BYTES_PER_ROW_ALIGNMENT - 1) / COPY_BYTES_PER_ROW_ALIGNMENT
+        * COPY_BYTES_PER_ROW_ALIGNMENT
+}
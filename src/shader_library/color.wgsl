@@ -0,0 +1,82 @@
+#define_import_path desktop::color
+
+// Conversions between color spaces. Colors are linear sRGB unless the name says otherwise, which
+// is also what wallpaper parameters of the `Color` kind are written as.
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    let low = c / 12.92;
+    let high = pow((c + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, c <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    let low = c * 12.92;
+    let high = 1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, c <= vec3<f32>(0.0031308));
+}
+
+// Relative luminance, as in Rec. 709.
+fn luminance(c: vec3<f32>) -> f32 {
+    return dot(c, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// https://bottosson.github.io/posts/oklab/
+fn linear_srgb_to_oklab(c: vec3<f32>) -> vec3<f32> {
+    let l = 0.4122214708 * c.r + 0.5363325363 * c.g + 0.0514459929 * c.b;
+    let m = 0.2119034982 * c.r + 0.6806995451 * c.g + 0.1073969566 * c.b;
+    let s = 0.0883024619 * c.r + 0.2817188376 * c.g + 0.6299787005 * c.b;
+
+    // Cube roots that keep the sign, for colors outside of the sRGB gamut.
+    let lms = sign(vec3<f32>(l, m, s)) * pow(abs(vec3<f32>(l, m, s)), vec3<f32>(1.0 / 3.0));
+
+    return vec3<f32>(
+        0.2104542553 * lms.x + 0.7936177850 * lms.y - 0.0040720468 * lms.z,
+        1.9779984951 * lms.x - 2.4285922050 * lms.y + 0.4505937099 * lms.z,
+        0.0259040371 * lms.x + 0.7827717662 * lms.y - 0.8086757660 * lms.z,
+    );
+}
+
+fn oklab_to_linear_srgb(c: vec3<f32>) -> vec3<f32> {
+    let l_ = c.x + 0.3963377774 * c.y + 0.2158037573 * c.z;
+    let m_ = c.x - 0.1055613458 * c.y - 0.0638541728 * c.z;
+    let s_ = c.x - 0.0894841775 * c.y - 1.2914855480 * c.z;
+
+    let l = l_ * l_ * l_;
+    let m = m_ * m_ * m_;
+    let s = s_ * s_ * s_;
+
+    return vec3<f32>(
+        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+    );
+}
+
+// Lightness, chroma and hue in radians.
+fn oklab_to_oklch(c: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(c.x, length(c.yz), atan2(c.z, c.y));
+}
+
+fn oklch_to_oklab(c: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(c.x, c.y * cos(c.z), c.y * sin(c.z));
+}
+
+fn linear_srgb_to_oklch(c: vec3<f32>) -> vec3<f32> {
+    return oklab_to_oklch(linear_srgb_to_oklab(c));
+}
+
+fn oklch_to_linear_srgb(c: vec3<f32>) -> vec3<f32> {
+    return oklab_to_linear_srgb(oklch_to_oklab(c));
+}
+
+// Blends two colors in Oklab, which keeps the colors in between from going muddy.
+fn mix_oklab(a: vec3<f32>, b: vec3<f32>, t: f32) -> vec3<f32> {
+    return oklab_to_linear_srgb(mix(linear_srgb_to_oklab(a), linear_srgb_to_oklab(b), t));
+}
+
+// Hue, saturation and value, all in 0..1, to a color in the same space the components are in.
+fn hsv_to_rgb(c: vec3<f32>) -> vec3<f32> {
+    let k = fract(c.x + vec3<f32>(1.0, 2.0 / 3.0, 1.0 / 3.0)) * 6.0;
+    let rgb = clamp(abs(k - 3.0) - 1.0, vec3<f32>(0.0), vec3<f32>(1.0));
+    return c.z * mix(vec3<f32>(1.0), rgb, c.y);
+}
@@ -0,0 +1,73 @@
+#define_import_path desktop::hash
+
+// Pseudo random values that only depend on their input, for noise and for scattering things
+// around without a random number generator.
+
+// PCG, for integers: https://www.jcgt.org/published/0009/03/02/
+fn pcg(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn pcg2d(v: vec2<u32>) -> vec2<u32> {
+    var h = v * 1664525u + 1013904223u;
+    h.x += h.y * 1664525u;
+    h.y += h.x * 1664525u;
+    h = h ^ (h >> vec2<u32>(16u));
+    h.x += h.y * 1664525u;
+    h.y += h.x * 1664525u;
+    return h ^ (h >> vec2<u32>(16u));
+}
+
+fn pcg3d(v: vec3<u32>) -> vec3<u32> {
+    var h = v * 1664525u + 1013904223u;
+    h.x += h.y * h.z;
+    h.y += h.z * h.x;
+    h.z += h.x * h.y;
+    h = h ^ (h >> vec3<u32>(16u));
+    h.x += h.y * h.z;
+    h.y += h.z * h.x;
+    h.z += h.x * h.y;
+    return h;
+}
+
+// Dave Hoskins' hashes without sine, for floats: https://www.shadertoy.com/view/4djSRW
+// The digits are how many values go in and come out, all of them in 0..1.
+
+fn hash11(p: f32) -> f32 {
+    var x = fract(p * 0.1031);
+    x *= x + 33.33;
+    x *= x + x;
+    return fract(x);
+}
+
+fn hash12(p: vec2<f32>) -> f32 {
+    var p3 = fract(p.xyx * 0.1031);
+    p3 += dot(p3, p3.yzx + 33.33);
+    return fract((p3.x + p3.y) * p3.z);
+}
+
+fn hash13(p: vec3<f32>) -> f32 {
+    var p3 = fract(p * 0.1031);
+    p3 += dot(p3, p3.zyx + 31.32);
+    return fract((p3.x + p3.y) * p3.z);
+}
+
+fn hash21(p: f32) -> vec2<f32> {
+    var p3 = fract(vec3<f32>(p) * vec3<f32>(0.1031, 0.1030, 0.0973));
+    p3 += dot(p3, p3.yzx + 33.33);
+    return fract((p3.xx + p3.yz) * p3.zy);
+}
+
+fn hash22(p: vec2<f32>) -> vec2<f32> {
+    var p3 = fract(p.xyx * vec3<f32>(0.1031, 0.1030, 0.0973));
+    p3 += dot(p3, p3.yzx + 33.33);
+    return fract((p3.xx + p3.yz) * p3.zy);
+}
+
+fn hash33(p: vec3<f32>) -> vec3<f32> {
+    var p3 = fract(p * vec3<f32>(0.1031, 0.1030, 0.0973));
+    p3 += dot(p3, p3.yxz + 33.33);
+    return fract((p3.xxy + p3.yxx) * p3.zyx);
+}
@@ -0,0 +1,34 @@
+use bevy::app::{App, Plugin};
+use bevy::asset::{load_internal_asset, HandleUntyped};
+use bevy::reflect::TypeUuid;
+use bevy::render::render_resource::Shader;
+
+pub const COLOR_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x6d1e_42b9_c07a_3f58);
+pub const HASH_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x93a4_0f6c_5e27_d1b8);
+pub const NOISE_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x1bc8_7d53_a2e9_604f);
+pub const SDF_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0xe57f_b316_08d4_9ac2);
+
+/// Bundles WGSL modules any shader can `#import`:
+///
+/// - `desktop::color`: sRGB, Oklab, Oklch and HSV conversions.
+/// - `desktop::hash`: pseudo random values from integers and floats.
+/// - `desktop::noise`: value, simplex and Worley noise, and fbm.
+/// - `desktop::sdf`: signed distance functions of 2D and 3D shapes, and operators on them.
+///
+/// Bevy pastes imports in without checking what's been imported already, so the modules don't
+/// import each other, and none of their names clash.
+#[derive(Default)]
+pub struct ShaderLibraryPlugin;
+
+impl Plugin for ShaderLibraryPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(app, COLOR_SHADER, "color.wgsl", Shader::from_wgsl);
+        load_internal_asset!(app, HASH_SHADER, "hash.wgsl", Shader::from_wgsl);
+        load_internal_asset!(app, NOISE_SHADER, "noise.wgsl", Shader::from_wgsl);
+        load_internal_asset!(app, SDF_SHADER, "sdf.wgsl", Shader::from_wgsl);
+    }
+}
@@ -0,0 +1,161 @@
+#define_import_path desktop::noise
+
+// Gradient, value and cellular noise, and fractal sums of them. Everything here is self-contained
+// so that it can be imported along with desktop::hash.
+
+fn noise_hash12(p: vec2<f32>) -> f32 {
+    var p3 = fract(p.xyx * 0.1031);
+    p3 += dot(p3, p3.yzx + 33.33);
+    return fract((p3.x + p3.y) * p3.z);
+}
+
+fn noise_hash13(p: vec3<f32>) -> f32 {
+    var p3 = fract(p * 0.1031);
+    p3 += dot(p3, p3.zyx + 31.32);
+    return fract((p3.x + p3.y) * p3.z);
+}
+
+fn noise_hash22(p: vec2<f32>) -> vec2<f32> {
+    var p3 = fract(p.xyx * vec3<f32>(0.1031, 0.1030, 0.0973));
+    p3 += dot(p3, p3.yzx + 33.33);
+    return fract((p3.xx + p3.yz) * p3.zy);
+}
+
+// Smoothly interpolated random values at integer coordinates, in 0..1.
+fn value_noise2(p: vec2<f32>) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    return mix(
+        mix(noise_hash12(i), noise_hash12(i + vec2<f32>(1.0, 0.0)), u.x),
+        mix(noise_hash12(i + vec2<f32>(0.0, 1.0)), noise_hash12(i + vec2<f32>(1.0, 1.0)), u.x),
+        u.y,
+    );
+}
+
+fn value_noise3(p: vec3<f32>) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    let bottom = mix(
+        mix(noise_hash13(i), noise_hash13(i + vec3<f32>(1.0, 0.0, 0.0)), u.x),
+        mix(noise_hash13(i + vec3<f32>(0.0, 1.0, 0.0)), noise_hash13(i + vec3<f32>(1.0, 1.0, 0.0)), u.x),
+        u.y,
+    );
+    let top = mix(
+        mix(noise_hash13(i + vec3<f32>(0.0, 0.0, 1.0)), noise_hash13(i + vec3<f32>(1.0, 0.0, 1.0)), u.x),
+        mix(noise_hash13(i + vec3<f32>(0.0, 1.0, 1.0)), noise_hash13(i + vec3<f32>(1.0, 1.0, 1.0)), u.x),
+        u.y,
+    );
+    return mix(bottom, top, u.z);
+}
+
+fn noise_mod289_2(x: vec2<f32>) -> vec2<f32> {
+    return x - floor(x * (1.0 / 289.0)) * 289.0;
+}
+
+fn noise_mod289_3(x: vec3<f32>) -> vec3<f32> {
+    return x - floor(x * (1.0 / 289.0)) * 289.0;
+}
+
+fn noise_permute3(x: vec3<f32>) -> vec3<f32> {
+    return noise_mod289_3((x * 34.0 + 10.0) * x);
+}
+
+// Simplex noise in -1..1, by Ian McEwan and Stefan Gustavson:
+// https://github.com/stegu/webgl-noise
+fn simplex_noise2(v: vec2<f32>) -> f32 {
+    let c = vec4<f32>(0.211324865405187, 0.366025403784439, -0.577350269189626, 0.024390243902439);
+    var i = floor(v + dot(v, c.yy));
+    let x0 = v - i + dot(i, c.xx);
+    var i1 = vec2<f32>(0.0, 1.0);
+    if (x0.x > x0.y) {
+        i1 = vec2<f32>(1.0, 0.0);
+    }
+    let x12 = x0.xyxy + c.xxzz - vec4<f32>(i1, 0.0, 0.0);
+    i = noise_mod289_2(i);
+    let p = noise_permute3(
+        noise_permute3(i.y + vec3<f32>(0.0, i1.y, 1.0)) + i.x + vec3<f32>(0.0, i1.x, 1.0)
+    );
+    var m = max(
+        0.5 - vec3<f32>(dot(x0, x0), dot(x12.xy, x12.xy), dot(x12.zw, x12.zw)),
+        vec3<f32>(0.0),
+    );
+    m = m * m;
+    m = m * m;
+    let x = 2.0 * fract(p * c.www) - 1.0;
+    let h = abs(x) - 0.5;
+    let a0 = x - floor(x + 0.5);
+    m = m * (1.79284291400159 - 0.85373472095314 * (a0 * a0 + h * h));
+    let g = vec3<f32>(a0.x * x0.x + h.x * x0.y, a0.yz * x12.xz + h.yz * x12.yw);
+    return 130.0 * dot(m, g);
+}
+
+// Cellular noise: the distances to the nearest and second nearest of points scattered one to a
+// cell.
+fn worley_noise2(p: vec2<f32>) -> vec2<f32> {
+    let cell = floor(p);
+    let f = fract(p);
+    var distances = vec2<f32>(8.0);
+    for (var y = -1; y <= 1; y += 1) {
+        for (var x = -1; x <= 1; x += 1) {
+            let offset = vec2<f32>(f32(x), f32(y));
+            let neighbor = offset + noise_hash22(cell + offset) - f;
+            let d = dot(neighbor, neighbor);
+            if (d < distances.x) {
+                distances = vec2<f32>(d, distances.x);
+            } else if (d < distances.y) {
+                distances = vec2<f32>(distances.x, d);
+            }
+        }
+    }
+    return sqrt(distances);
+}
+
+// Fractal sums of octaves at twice the frequency and half the amplitude of the one before,
+// scaled back into the range of the noise they're made of. The 2D octaves are rotated against
+// each other, which hides the grid the noise is built on.
+
+fn fbm_value2(p: vec2<f32>, octaves: i32) -> f32 {
+    let rotation = mat2x2<f32>(vec2<f32>(0.8, 0.6), vec2<f32>(-0.6, 0.8));
+    var q = p;
+    var sum = 0.0;
+    var amplitude = 0.5;
+    var total = 0.0;
+    for (var octave = 0; octave < octaves; octave += 1) {
+        sum += amplitude * value_noise2(q);
+        total += amplitude;
+        q = rotation * q * 2.0;
+        amplitude *= 0.5;
+    }
+    return sum / max(total, 0.0001);
+}
+
+fn fbm_value3(p: vec3<f32>, octaves: i32) -> f32 {
+    var q = p;
+    var sum = 0.0;
+    var amplitude = 0.5;
+    var total = 0.0;
+    for (var octave = 0; octave < octaves; octave += 1) {
+        sum += amplitude * value_noise3(q);
+        total += amplitude;
+        q = q * 2.0 + vec3<f32>(17.0, 31.0, 47.0);
+        amplitude *= 0.5;
+    }
+    return sum / max(total, 0.0001);
+}
+
+fn fbm_simplex2(p: vec2<f32>, octaves: i32) -> f32 {
+    let rotation = mat2x2<f32>(vec2<f32>(0.8, 0.6), vec2<f32>(-0.6, 0.8));
+    var q = p;
+    var sum = 0.0;
+    var amplitude = 0.5;
+    var total = 0.0;
+    for (var octave = 0; octave < octaves; octave += 1) {
+        sum += amplitude * simplex_noise2(q);
+        total += amplitude;
+        q = rotation * q * 2.0;
+        amplitude *= 0.5;
+    }
+    return sum / max(total, 0.0001);
+}
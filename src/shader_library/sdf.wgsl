@@ -0,0 +1,135 @@
+#define_import_path desktop::sdf
+
+// Signed distances to shapes centered on the origin, negative inside, and operators combining
+// them. Mostly after Inigo Quilez: https://iquilezles.org/articles/distfunctions/
+
+fn sd_circle(p: vec2<f32>, radius: f32) -> f32 {
+    return length(p) - radius;
+}
+
+// `half_size` is the distance from the center to the edges.
+fn sd_box2(p: vec2<f32>, half_size: vec2<f32>) -> f32 {
+    let d = abs(p) - half_size;
+    return length(max(d, vec2<f32>(0.0))) + min(max(d.x, d.y), 0.0);
+}
+
+fn sd_rounded_box2(p: vec2<f32>, half_size: vec2<f32>, radius: f32) -> f32 {
+    return sd_box2(p, half_size - radius) - radius;
+}
+
+// The line from `a` to `b`, which `op_round` gives a thickness.
+fn sd_segment2(p: vec2<f32>, a: vec2<f32>, b: vec2<f32>) -> f32 {
+    let pa = p - a;
+    let ba = b - a;
+    let h = clamp(dot(pa, ba) / dot(ba, ba), 0.0, 1.0);
+    return length(pa - ba * h);
+}
+
+// Pointing up, with its corners `radius` away from the center.
+fn sd_equilateral_triangle(p: vec2<f32>, radius: f32) -> f32 {
+    let k = sqrt(3.0);
+    let r = radius * k / 2.0;
+    var q = vec2<f32>(abs(p.x) - r, p.y + r / k);
+    if (q.x + k * q.y > 0.0) {
+        q = vec2<f32>(q.x - k * q.y, -k * q.x - q.y) / 2.0;
+    }
+    q = vec2<f32>(q.x - clamp(q.x, -2.0 * r, 0.0), q.y);
+    return -length(q) * sign(q.y);
+}
+
+// With flat sides at the top and bottom, `radius` away from the center.
+fn sd_hexagon(p: vec2<f32>, radius: f32) -> f32 {
+    let k = vec3<f32>(-0.866025404, 0.5, 0.577350269);
+    var q = abs(p);
+    q = q - 2.0 * min(dot(k.xy, q), 0.0) * k.xy;
+    q = q - vec2<f32>(clamp(q.x, -k.z * radius, k.z * radius), radius);
+    return length(q) * sign(q.y);
+}
+
+fn sd_sphere(p: vec3<f32>, radius: f32) -> f32 {
+    return length(p) - radius;
+}
+
+fn sd_box3(p: vec3<f32>, half_size: vec3<f32>) -> f32 {
+    let d = abs(p) - half_size;
+    return length(max(d, vec3<f32>(0.0))) + min(max(d.x, max(d.y, d.z)), 0.0);
+}
+
+fn sd_rounded_box3(p: vec3<f32>, half_size: vec3<f32>, radius: f32) -> f32 {
+    return sd_box3(p, half_size - radius) - radius;
+}
+
+// Lying in the XZ plane, with `radii.x` to the middle of the tube and `radii.y` its thickness.
+fn sd_torus(p: vec3<f32>, radii: vec2<f32>) -> f32 {
+    let q = vec2<f32>(length(p.xz) - radii.x, p.y);
+    return length(q) - radii.y;
+}
+
+fn sd_capsule(p: vec3<f32>, a: vec3<f32>, b: vec3<f32>, radius: f32) -> f32 {
+    let pa = p - a;
+    let ba = b - a;
+    let h = clamp(dot(pa, ba) / dot(ba, ba), 0.0, 1.0);
+    return length(pa - ba * h) - radius;
+}
+
+// Standing on the Y axis, `half_height` up and down from the center.
+fn sd_cylinder(p: vec3<f32>, half_height: f32, radius: f32) -> f32 {
+    let d = abs(vec2<f32>(length(p.xz), p.y)) - vec2<f32>(radius, half_height);
+    return min(max(d.x, d.y), 0.0) + length(max(d, vec2<f32>(0.0)));
+}
+
+// The plane through `normal * -offset`, facing the way `normal`, which has to be normalized,
+// points.
+fn sd_plane(p: vec3<f32>, normal: vec3<f32>, offset: f32) -> f32 {
+    return dot(p, normal) + offset;
+}
+
+fn op_union(a: f32, b: f32) -> f32 {
+    return min(a, b);
+}
+
+// `a` with `b` cut out of it.
+fn op_subtract(a: f32, b: f32) -> f32 {
+    return max(a, -b);
+}
+
+fn op_intersect(a: f32, b: f32) -> f32 {
+    return max(a, b);
+}
+
+// The smooth operators blend the shapes together over a distance of about `k`.
+
+fn op_smooth_union(a: f32, b: f32, k: f32) -> f32 {
+    let h = clamp(0.5 + 0.5 * (b - a) / k, 0.0, 1.0);
+    return mix(b, a, h) - k * h * (1.0 - h);
+}
+
+fn op_smooth_subtract(a: f32, b: f32, k: f32) -> f32 {
+    let h = clamp(0.5 - 0.5 * (a + b) / k, 0.0, 1.0);
+    return mix(a, -b, h) + k * h * (1.0 - h);
+}
+
+fn op_smooth_intersect(a: f32, b: f32, k: f32) -> f32 {
+    let h = clamp(0.5 - 0.5 * (b - a) / k, 0.0, 1.0);
+    return mix(b, a, h) + k * h * (1.0 - h);
+}
+
+// Grows a shape by `radius`, rounding off its corners.
+fn op_round(d: f32, radius: f32) -> f32 {
+    return d - radius;
+}
+
+// Hollows a shape out into a shell `thickness` thick.
+fn op_onion(d: f32, thickness: f32) -> f32 {
+    return abs(d) - thickness;
+}
+
+// Repeats space every `spacing`, so that a shape at the origin shows up in every cell. Evaluate
+// the shape at the returned point.
+fn op_repeat2(p: vec2<f32>, spacing: vec2<f32>) -> vec2<f32> {
+    return p - spacing * round(p / spacing);
+}
+
+fn op_repeat3(p: vec3<f32>, spacing: vec3<f32>) -> vec3<f32> {
+    return p - spacing * round(p / spacing);
+}
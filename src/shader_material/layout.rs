@@ -0,0 +1,164 @@
+use bevy::math::{Vec2, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+/// The type of a uniform buffer field that can be filled in from Rust.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UniformType {
+    F32,
+    U32,
+    I32,
+    Vec2,
+    Vec3,
+    Vec4,
+}
+
+impl UniformType {
+    /// Size and alignment in bytes, following WGSL's layout rules.
+    fn size_and_align(self) -> (u32, u32) {
+        match self {
+            UniformType::F32 | UniformType::U32 | UniformType::I32 => (4, 4),
+            UniformType::Vec2 => (8, 8),
+            UniformType::Vec3 => (12, 16),
+            UniformType::Vec4 => (16, 16),
+        }
+    }
+
+    /// How the type is written in WGSL.
+    pub fn wgsl_type(self) -> &'static str {
+        match self {
+            UniformType::F32 => "f32",
+            UniformType::U32 => "u32",
+            UniformType::I32 => "i32",
+            UniformType::Vec2 => "vec2<f32>",
+            UniformType::Vec3 => "vec3<f32>",
+            UniformType::Vec4 => "vec4<f32>",
+        }
+    }
+}
+
+/// A value to write into a uniform buffer field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UniformValue {
+    F32(f32),
+    U32(u32),
+    I32(i32),
+    Vec2(Vec2),
+    Vec3(Vec3),
+    Vec4(Vec4),
+}
+
+impl UniformValue {
+    pub fn uniform_type(&self) -> UniformType {
+        match self {
+            UniformValue::F32(_) => UniformType::F32,
+            UniformValue::U32(_) => UniformType::U32,
+            UniformValue::I32(_) => UniformType::I32,
+            UniformValue::Vec2(_) => UniformType::Vec2,
+            UniformValue::Vec3(_) => UniformType::Vec3,
+            UniformValue::Vec4(_) => UniformType::Vec4,
+        }
+    }
+
+    /// The value as a field of type `ty`, for the conversions a shader author would expect to
+    /// just work, like a color going into a `vec3<f32>` or a boolean into an `i32`.
+    fn convert(self, ty: UniformType) -> Option<UniformValue> {
+        let converted = match (self, ty) {
+            (value, ty) if value.uniform_type() == ty => value,
+            (UniformValue::U32(value), UniformType::I32) => {
+                UniformValue::I32(i32::try_from(value).ok()?)
+            }
+            (UniformValue::I32(value), UniformType::U32) => {
+                UniformValue::U32(u32::try_from(value).ok()?)
+            }
+            (UniformValue::U32(value), UniformType::F32) => UniformValue::F32(value as f32),
+            (UniformValue::I32(value), UniformType::F32) => UniformValue::F32(value as f32),
+            (UniformValue::Vec4(value), UniformType::Vec3) => UniformValue::Vec3(value.truncate()),
+            (UniformValue::Vec3(value), UniformType::Vec4) => UniformValue::Vec4(value.extend(1.0)),
+            _ => return None,
+        };
+        Some(converted)
+    }
+
+    fn words(&self) -> Vec<u32> {
+        match self {
+            UniformValue::F32(value) => vec![value.to_bits()],
+            UniformValue::U32(value) => vec![*value],
+            UniformValue::I32(value) => vec![*value as u32],
+            UniformValue::Vec2(value) => value.to_array().map(f32::to_bits).to_vec(),
+            UniformValue::Vec3(value) => value.to_array().map(f32::to_bits).to_vec(),
+            UniformValue::Vec4(value) => value.to_array().map(f32::to_bits).to_vec(),
+        }
+    }
+}
+
+/// Where the fields of a uniform buffer are, worked out at runtime rather than from a Rust type.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UniformLayout {
+    /// Size of the whole buffer in bytes.
+    pub size: u32,
+    /// The fields that can be filled in. Anything else in the buffer is left zeroed.
+    pub fields: Vec<UniformField>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UniformField {
+    pub name: String,
+    /// Offset from the start of the buffer in bytes.
+    pub offset: u32,
+    pub ty: UniformType,
+}
+
+impl UniformLayout {
+    /// Lays fields out one after the other, the way WGSL lays out a struct with the same members
+    /// in the uniform address space.
+    pub fn sequential(fields: impl IntoIterator<Item = (String, UniformType)>) -> Self {
+        let mut end = 0;
+        let fields = fields
+            .into_iter()
+            .map(|(name, ty)| {
+                let (size, align) = ty.size_and_align();
+                let offset = round_up(end, align);
+                end = offset + size;
+                UniformField { name, offset, ty }
+            })
+            .collect();
+        UniformLayout {
+            // Structs in the uniform address space are aligned to 16 bytes.
+            size: round_up(end, 16),
+            fields,
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&UniformField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// A zeroed buffer to write the fields into.
+    pub fn buffer(&self) -> Vec<u8> {
+        vec![0; self.size as usize]
+    }
+
+    /// Writes `value` into the field called `name`. Returns `false` without writing anything if
+    /// there is no such field or the value can't be converted to its type.
+    pub fn write(&self, buffer: &mut [u8], name: &str, value: UniformValue) -> bool {
+        let Some(field) = self.field(name) else {
+            return false;
+        };
+        let Some(value) = value.convert(field.ty) else {
+            return false;
+        };
+        let start = field.offset as usize;
+        let words = value.words();
+        let Some(bytes) = buffer.get_mut(start..start + words.len() * 4) else {
+            return false;
+        };
+        for (bytes, word) in bytes.chunks_exact_mut(4).zip(words) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        true
+    }
+}
+
+fn round_up(value: u32, align: u32) -> u32 {
+    (value + align - 1) / align * align
+}
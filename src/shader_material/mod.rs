@@ -0,0 +1,374 @@
+mod layout;
+mod reflect;
+
+pub use layout::*;
+pub use reflect::*;
+
+use std::sync::{Arc, Mutex};
+
+use bevy::app::{App, Plugin};
+use bevy::asset::{Assets, Handle, HandleUntyped};
+use bevy::ecs::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::mesh::MeshVertexBufferLayout;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_resource::{
+    AddressMode, AsBindGroup, AsBindGroupError, BindGroupDescriptor, BindGroupEntry,
+    BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer,
+    BufferBindingType, BufferInitDescriptor, BufferUsages, Extent3d, FilterMode,
+    OwnedBindingResource, PreparedBindGroup, RenderPipelineDescriptor, SamplerBindingType,
+    SamplerDescriptor, Shader, ShaderStages, SpecializedMeshPipelineError, TextureDimension,
+    TextureFormat, TextureSampleType, TextureViewDescriptor, TextureViewDimension,
+};
+use bevy::render::renderer::{RenderDevice, RenderQueue};
+use bevy::render::texture::{FallbackImage, Image};
+use bevy::render::{Extract, RenderApp, RenderStage};
+use bevy::sprite::{Material2d, Material2dKey, Material2dPlugin};
+use bevy::utils::tracing::warn;
+
+/// How many textures a [`ShaderMaterial`] can bind.
+pub const SHADER_MATERIAL_TEXTURES: usize = 4;
+
+/// A white cube, bound in place of the cubemaps of texture slots that don't hold one.
+pub const FALLBACK_CUBEMAP: HandleUntyped =
+    HandleUntyped::weak_from_u64(Image::TYPE_UUID, 0x51c3_7e0a_94d2_b68f);
+
+#[derive(Default)]
+pub struct ShaderMaterialPlugin;
+
+impl Plugin for ShaderMaterialPlugin {
+    fn build(&self, app: &mut App) {
+        let mut cubemap = Image::new_fill(
+            Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 6,
+            },
+            TextureDimension::D2,
+            &[255; 4],
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        cubemap.texture_view_descriptor = Some(cube_view());
+        app.world
+            .resource_mut::<Assets<Image>>()
+            .set_untracked(FALLBACK_CUBEMAP, cubemap);
+        app.add_plugin(Material2dPlugin::<ShaderMaterial>::default());
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<ExtractedUniforms>()
+            .add_system_to_stage(RenderStage::Extract, extract_uniforms)
+            .add_system_to_stage(RenderStage::Prepare, write_uniforms);
+    }
+}
+
+/// How an image with six layers is viewed to be bound as a cubemap.
+pub fn cube_view() -> TextureViewDescriptor<'static> {
+    TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::Cube),
+        ..Default::default()
+    }
+}
+
+/// A material drawn with any WGSL fragment shader, with uniforms and textures described at
+/// runtime instead of by a Rust type of their own.
+///
+/// The shader sees [`uniforms`](Self::uniforms) as a uniform buffer at `@group(1) @binding(0)`,
+/// and each of the [`SHADER_MATERIAL_TEXTURES`] textures at the odd bindings after it with its
+/// sampler at the following even one. Every slot also has a `texture_cube<f32>` binding after all
+/// of those, from `@binding(9)` on, sampled with the same sampler. Whichever of the two the
+/// slot's texture isn't bound to, and textures that aren't set, are bound to white.
+#[derive(TypeUuid, Debug, Clone)]
+#[uuid = "9d3c6f0e-2b7a-4c51-8e4f-6a1d0b9e7c35"]
+pub struct ShaderMaterial {
+    /// The fragment shader, with its entry point called `fragment`.
+    pub shader: Handle<Shader>,
+    /// Contents of the uniform buffer, usually written through a [`UniformLayout`].
+    pub uniforms: ShaderUniforms,
+    /// Up to [`SHADER_MATERIAL_TEXTURES`] textures, in binding order.
+    pub textures: Vec<ShaderTexture>,
+    /// Defines the shader is compiled with.
+    pub shader_defs: Vec<String>,
+}
+
+impl ShaderMaterial {
+    pub fn new(shader: Handle<Shader>) -> Self {
+        ShaderMaterial {
+            shader,
+            uniforms: ShaderUniforms::default(),
+            textures: Vec::new(),
+            shader_defs: Vec::new(),
+        }
+    }
+}
+
+/// The contents of the uniform buffer of a [`ShaderMaterial`], shared by its clones and so with
+/// the render world. Setting them writes them to the buffer the material is bound with, where
+/// changing anything else about the material has it prepared again, with a new bind group.
+#[derive(Debug, Clone, Default)]
+pub struct ShaderUniforms(Arc<Mutex<Uniforms>>);
+
+#[derive(Debug, Default)]
+struct Uniforms {
+    contents: Vec<u8>,
+    /// Whether the contents changed since they were written to the buffer.
+    changed: bool,
+    /// The buffer, once the material has been prepared.
+    buffer: Option<Buffer>,
+}
+
+impl ShaderUniforms {
+    /// How many bytes the contents take, before padding.
+    pub fn size(&self) -> usize {
+        self.0.lock().unwrap().contents.len()
+    }
+
+    pub fn set(&self, contents: Vec<u8>) {
+        let mut uniforms = self.0.lock().unwrap();
+        if uniforms.contents != contents {
+            uniforms.contents = contents;
+            uniforms.changed = true;
+        }
+    }
+
+    /// The buffer to bind, which is the one from the last time the material was prepared unless
+    /// the contents no longer fit it.
+    fn buffer(&self, render_device: &RenderDevice) -> Buffer {
+        let mut uniforms = self.0.lock().unwrap();
+        let contents = padded(&uniforms.contents);
+        match &uniforms.buffer {
+            Some(buffer) if buffer.size() == contents.len() as u64 => buffer.clone(),
+            _ => {
+                let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+                    label: Some("shader_material_uniforms"),
+                    contents: &contents,
+                    usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                });
+                uniforms.buffer = Some(buffer.clone());
+                uniforms.changed = false;
+                buffer
+            }
+        }
+    }
+}
+
+/// Bindings can't be empty, and uniform buffers are sized in multiples of 16 bytes.
+fn padded(contents: &[u8]) -> Vec<u8> {
+    let mut padded = contents.to_vec();
+    padded.resize((padded.len().max(1) + 15) / 16 * 16, 0);
+    padded
+}
+
+/// The uniforms of every [`ShaderMaterial`], which are only extracted as part of the material
+/// when it's prepared again.
+#[derive(Default, Resource)]
+struct ExtractedUniforms(Vec<ShaderUniforms>);
+
+fn extract_uniforms(
+    mut extracted: ResMut<ExtractedUniforms>,
+    materials: Extract<Res<Assets<ShaderMaterial>>>,
+) {
+    extracted.0.clear();
+    extracted.0.extend(
+        materials
+            .iter()
+            .map(|(_, material)| material.uniforms.clone()),
+    );
+}
+
+fn write_uniforms(extracted: Res<ExtractedUniforms>, render_queue: Res<RenderQueue>) {
+    for uniforms in &extracted.0 {
+        let mut uniforms = uniforms.0.lock().unwrap();
+        let (true, Some(buffer)) = (uniforms.changed, &uniforms.buffer) else {
+            continue;
+        };
+        // Contents that don't fit get a new buffer once the material is prepared again.
+        let contents = padded(&uniforms.contents);
+        if buffer.size() == contents.len() as u64 {
+            render_queue.write_buffer(buffer, 0, &contents);
+            uniforms.changed = false;
+        }
+    }
+}
+
+/// A texture bound to a [`ShaderMaterial`], with how it's sampled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderTexture {
+    pub image: Option<Handle<Image>>,
+    pub filter: FilterMode,
+    pub address_mode: AddressMode,
+    /// Bind the image as a cubemap, which it has to be viewed as with [`cube_view`].
+    pub cubemap: bool,
+}
+
+impl Default for ShaderTexture {
+    fn default() -> Self {
+        ShaderTexture {
+            image: None,
+            filter: FilterMode::Linear,
+            address_mode: AddressMode::ClampToEdge,
+            cubemap: false,
+        }
+    }
+}
+
+impl ShaderTexture {
+    fn sampler_descriptor(&self) -> SamplerDescriptor<'static> {
+        SamplerDescriptor {
+            label: Some("shader_material_sampler"),
+            address_mode_u: self.address_mode,
+            address_mode_v: self.address_mode,
+            address_mode_w: self.address_mode,
+            mag_filter: self.filter,
+            min_filter: self.filter,
+            mipmap_filter: self.filter,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ShaderMaterialKey {
+    shader: Handle<Shader>,
+    shader_defs: Vec<String>,
+}
+
+/// Written by hand rather than derived, as the size of the uniform buffer and the samplers are
+/// only known at runtime. The layout is the same for every shader: one buffer of any size and a
+/// fixed number of texture slots.
+impl AsBindGroup for ShaderMaterial {
+    type Data = ShaderMaterialKey;
+
+    fn as_bind_group(
+        &self,
+        layout: &BindGroupLayout,
+        render_device: &RenderDevice,
+        images: &RenderAssets<Image>,
+        fallback_image: &FallbackImage,
+    ) -> Result<PreparedBindGroup<Self>, AsBindGroupError> {
+        if self.textures.len() > SHADER_MATERIAL_TEXTURES {
+            warn!(
+                "Only the first {} of {} shader material textures are bound",
+                SHADER_MATERIAL_TEXTURES,
+                self.textures.len()
+            );
+        }
+
+        let mut bindings = vec![OwnedBindingResource::Buffer(
+            self.uniforms.buffer(render_device),
+        )];
+        let fallback_cubemap = &images
+            .get(&FALLBACK_CUBEMAP.typed())
+            .ok_or(AsBindGroupError::RetryNextUpdate)?
+            .texture_view;
+        let mut cubemaps = Vec::new();
+        for slot in 0..SHADER_MATERIAL_TEXTURES {
+            let texture = self.textures.get(slot).cloned().unwrap_or_default();
+            let view = match &texture.image {
+                Some(handle) => {
+                    &images
+                        .get(handle)
+                        .ok_or(AsBindGroupError::RetryNextUpdate)?
+                        .texture_view
+                }
+                None if texture.cubemap => fallback_cubemap,
+                None => &fallback_image.texture_view,
+            };
+            let (view, cubemap) = if texture.cubemap {
+                (&fallback_image.texture_view, view)
+            } else {
+                (view, fallback_cubemap)
+            };
+            bindings.push(OwnedBindingResource::TextureView(view.clone()));
+            bindings.push(OwnedBindingResource::Sampler(
+                render_device.create_sampler(&texture.sampler_descriptor()),
+            ));
+            cubemaps.push(OwnedBindingResource::TextureView(cubemap.clone()));
+        }
+        bindings.extend(cubemaps);
+
+        let entries: Vec<BindGroupEntry> = bindings
+            .iter()
+            .enumerate()
+            .map(|(binding, resource)| BindGroupEntry {
+                binding: binding as u32,
+                resource: resource.get_binding(),
+            })
+            .collect();
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("shader_material_bind_group"),
+            layout,
+            entries: &entries,
+        });
+        Ok(PreparedBindGroup {
+            bindings,
+            bind_group,
+            data: ShaderMaterialKey {
+                shader: self.shader.clone(),
+                shader_defs: self.shader_defs.clone(),
+            },
+        })
+    }
+
+    fn bind_group_layout(render_device: &RenderDevice) -> BindGroupLayout {
+        let mut entries = vec![BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::VERTEX_FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+        for slot in 0..SHADER_MATERIAL_TEXTURES as u32 {
+            entries.push(BindGroupLayoutEntry {
+                binding: 1 + slot * 2,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+            entries.push(BindGroupLayoutEntry {
+                binding: 2 + slot * 2,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
+            });
+        }
+        for slot in 0..SHADER_MATERIAL_TEXTURES as u32 {
+            entries.push(BindGroupLayoutEntry {
+                binding: 1 + SHADER_MATERIAL_TEXTURES as u32 * 2 + slot,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::Cube,
+                    multisampled: false,
+                },
+                count: None,
+            });
+        }
+        render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("shader_material_layout"),
+            entries: &entries,
+        })
+    }
+}
+
+impl Material2d for ShaderMaterial {
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let fragment = descriptor.fragment.as_mut().unwrap();
+        fragment.shader = key.bind_group_data.shader;
+        fragment.shader_defs.extend(key.bind_group_data.shader_defs);
+        Ok(())
+    }
+}
@@ -0,0 +1,266 @@
+use std::path::{Path, PathBuf};
+
+use bevy::utils::tracing::warn;
+use naga::{AddressSpace, ResourceBinding, ScalarKind, TypeInner, VectorSize};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::layout::{UniformField, UniformLayout, UniformType};
+use crate::content_hash::ContentHash;
+
+/// Works out the layout of the struct a WGSL shader declares its uniform buffer as, at
+/// `@group(group) @binding(binding)`.
+///
+/// Only the struct and constant declarations and the buffer itself are handed to naga, since the
+/// rest of the shader usually relies on `#import`s that only Bevy can resolve. Fields of types
+/// that can't be filled in from Rust, like arrays and matrices, are left out of the layout.
+///
+/// Layouts are kept on disk under a hash of the shader, so a shader is only parsed again once it
+/// changes.
+pub fn reflect_uniform_layout(
+    source: &str,
+    group: u32,
+    binding: u32,
+) -> Result<UniformLayout, String> {
+    reflect_once(source, &format!("uniforms {group} {binding}"), || {
+        uniform_layout(source, group, binding)
+    })
+}
+
+/// The `@workgroup_size` of the compute shader entry point called `entry_point`, with the
+/// dimensions that are left out set to 1. Kept on disk like uniform layouts.
+pub fn reflect_workgroup_size(source: &str, entry_point: &str) -> Result<[u32; 3], String> {
+    reflect_once(source, &format!("workgroup size {entry_point}"), || {
+        workgroup_size(source, entry_point)
+    })
+}
+
+/// Where reflected layouts are kept, in the local app data folder since they can always be worked
+/// out again. Only what's reflected from the WGSL source is kept, which doesn't depend on the
+/// adapter or driver, and not the shaders bevy compiles from it.
+fn reflections_dir() -> Option<PathBuf> {
+    let local_app_data = std::env::var_os("LOCALAPPDATA")?;
+    Some(
+        Path::new(&local_app_data)
+            .join("desktop")
+            .join("reflections"),
+    )
+}
+
+/// Looks up what was reflected about `source` last time, or reflects it and keeps the result.
+/// `what` tells apart the different things reflected from the same shader.
+fn reflect_once<T: Serialize + DeserializeOwned>(
+    source: &str,
+    what: &str,
+    reflect: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    let path = reflections_dir().map(|folder| {
+        let mut hash = ContentHash::default();
+        // A new version may reflect differently.
+        hash.write(env!("CARGO_PKG_VERSION").as_bytes());
+        hash.write(what.as_bytes());
+        hash.write(source.as_bytes());
+        folder.join(format!("{}.ron", hash.key()))
+    });
+    let kept = path
+        .as_ref()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|kept| ron::from_str(&kept).ok());
+    if let Some(kept) = kept {
+        return Ok(kept);
+    }
+    let reflected = reflect()?;
+    if let Some(path) = &path {
+        if let Err(err) = write_reflection(path, &reflected) {
+            warn!("Can't keep what was reflected from a shader: {}", err);
+        }
+    }
+    Ok(reflected)
+}
+
+fn write_reflection(path: &Path, reflected: &impl Serialize) -> Result<(), String> {
+    if let Some(folder) = path.parent() {
+        std::fs::create_dir_all(folder)
+            .map_err(|err| format!("can't create {}: {err}", folder.display()))?;
+    }
+    let reflected = ron::to_string(reflected).map_err(|err| err.to_string())?;
+    std::fs::write(path, reflected).map_err(|err| format!("can't write {}: {err}", path.display()))
+}
+
+fn uniform_layout(source: &str, group: u32, binding: u32) -> Result<UniformLayout, String> {
+    let declarations = uniform_declarations(&strip_comments(source), group, binding);
+    let module = naga::front::wgsl::parse_str(&declarations)
+        .map_err(|err| err.emit_to_string(&declarations))?;
+    let (_, buffer) = module
+        .global_variables
+        .iter()
+        .find(|(_, variable)| {
+            variable.space == AddressSpace::Uniform
+                && variable.binding == Some(ResourceBinding { group, binding })
+        })
+        .ok_or_else(|| format!("no uniform buffer at @group({group}) @binding({binding})"))?;
+    let TypeInner::Struct { members, span } = &module.types[buffer.ty].inner else {
+        return Err(format!(
+            "the uniform buffer at @group({group}) @binding({binding}) isn't a struct"
+        ));
+    };
+    let fields = members
+        .iter()
+        .filter_map(|member| {
+            Some(UniformField {
+                name: member.name.clone()?,
+                offset: member.offset,
+                ty: uniform_type(&module.types[member.ty].inner)?,
+            })
+        })
+        .collect();
+    Ok(UniformLayout {
+        size: *span,
+        fields,
+    })
+}
+
+fn workgroup_size(source: &str, entry_point: &str) -> Result<[u32; 3], String> {
+    let source = strip_comments(source);
+    let (attributes, _) = top_level_items(&source)
+        .into_iter()
+        .map(split_attributes)
+        .find(|(_, declaration)| {
+            declaration
+                .strip_prefix("fn ")
+                .and_then(|rest| rest.trim_start().strip_prefix(entry_point))
+                .map_or(false, |rest| rest.trim_start().starts_with('('))
+        })
+        .ok_or_else(|| format!("no entry point called {entry_point:?}"))?;
+    let compact: String = attributes.split_whitespace().collect();
+    let sizes = compact
+        .split_once("@workgroup_size(")
+        .and_then(|(_, rest)| rest.split_once(')'))
+        .map(|(sizes, _)| sizes)
+        .ok_or_else(|| format!("{entry_point:?} has no @workgroup_size"))?;
+    let sizes: Vec<u32> = sizes
+        .split(',')
+        .filter(|size| !size.is_empty())
+        .map(|size| {
+            let size = size.trim_end_matches(|c| c == 'u' || c == 'i');
+            size.parse().ok().filter(|size| *size > 0)
+        })
+        .collect::<Option<_>>()
+        .filter(|sizes: &Vec<u32>| (1..=3).contains(&sizes.len()))
+        .ok_or_else(|| format!("can't read the @workgroup_size of {entry_point:?}"))?;
+    let mut workgroup_size = [1; 3];
+    workgroup_size[..sizes.len()].copy_from_slice(&sizes);
+    Ok(workgroup_size)
+}
+
+fn uniform_type(inner: &TypeInner) -> Option<UniformType> {
+    match *inner {
+        TypeInner::Scalar { kind, width: 4 } => match kind {
+            ScalarKind::Float => Some(UniformType::F32),
+            ScalarKind::Uint => Some(UniformType::U32),
+            ScalarKind::Sint => Some(UniformType::I32),
+            ScalarKind::Bool => None,
+        },
+        TypeInner::Vector {
+            size,
+            kind: ScalarKind::Float,
+            width: 4,
+        } => Some(match size {
+            VectorSize::Bi => UniformType::Vec2,
+            VectorSize::Tri => UniformType::Vec3,
+            VectorSize::Quad => UniformType::Vec4,
+        }),
+        _ => None,
+    }
+}
+
+/// Blanks out comments and preprocessor lines like `#import`, keeping everything else in place.
+fn strip_comments(source: &str) -> String {
+    let mut stripped = String::with_capacity(source.len());
+    let mut rest = source;
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("//") {
+            rest = comment.find('\n').map_or("", |end| &comment[end..]);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.find("*/").map_or("", |end| &comment[end + 2..]);
+            stripped.push(' ');
+        } else {
+            let c = rest.chars().next().unwrap();
+            stripped.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    stripped
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The struct and constant declarations of `source`, and the declaration of the uniform buffer.
+fn uniform_declarations(source: &str, group: u32, binding: u32) -> String {
+    let mut declarations = String::new();
+    for item in top_level_items(source) {
+        let (attributes, declaration) = split_attributes(item);
+        // Structs made up of `#import`ed members are empty by now, and aren't valid WGSL.
+        let wanted = (declaration.starts_with("struct ")
+            && declaration
+                .split_once('{')
+                .map_or(false, |(_, body)| body.contains(':')))
+            || declaration.starts_with("let ")
+            || declaration.starts_with("const ")
+            || (declaration.starts_with("var") && {
+                let compact: String = attributes.split_whitespace().collect();
+                compact.contains(&format!("@group({group})"))
+                    && compact.contains(&format!("@binding({binding})"))
+            });
+        if wanted {
+            declarations.push_str(item.trim());
+            declarations.push('\n');
+        }
+    }
+    declarations
+}
+
+/// Splits `source` into the declarations at the top level: everything up to a `;` or a closing
+/// brace outside of any braces.
+fn top_level_items(source: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (index, c) in source.char_indices() {
+        let end = match c {
+            '{' => {
+                depth += 1;
+                false
+            }
+            '}' => {
+                depth = depth.saturating_sub(1);
+                depth == 0
+            }
+            ';' => depth == 0,
+            _ => false,
+        };
+        if end {
+            items.push(&source[start..=index]);
+            start = index + 1;
+        }
+    }
+    items
+}
+
+/// Splits the leading `@attribute(...)`s off a declaration.
+fn split_attributes(item: &str) -> (&str, &str) {
+    let item = item.trim_start();
+    let mut rest = item;
+    while let Some(attribute) = rest.strip_prefix('@') {
+        let name_end = attribute
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(attribute.len());
+        let mut after = attribute[name_end..].trim_start();
+        if after.starts_with('(') {
+            after = after.find(')').map_or("", |end| &after[end + 1..]);
+        }
+        rest = after.trim_start();
+    }
+    (&item[..item.len() - rest.len()], rest)
+}
@@ -0,0 +1,294 @@
+use std::ffi::c_void;
+use std::os::windows::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use bevy::app::{App, Plugin};
+use bevy::asset::Assets;
+use bevy::ecs::prelude::*;
+use bevy::render::render_resource::Extent3d;
+use bevy::render::texture::Image;
+use bevy::render::view::WindowSurfaces;
+use bevy::render::{Extract, RenderApp, RenderStage};
+use bevy::utils::{
+    default,
+    tracing::{info, warn},
+};
+use bevy::window::Windows;
+use crossbeam_channel::{Receiver, TryRecvError};
+use windows::Win32::Foundation::MAX_PATH;
+use windows::Win32::UI::WindowsAndMessaging::{
+    SystemParametersInfoW, SPIF_SENDCHANGE, SPI_GETDESKWALLPAPER, SPI_SETDESKWALLPAPER,
+    SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS,
+};
+
+use crate::canvas::WallpaperCanvas;
+use crate::clock::WallpaperClock;
+use crate::playlist::Playlist;
+use crate::power::{Power, PowerState};
+use crate::quality::FullQuality;
+use crate::screenshot::Screenshots;
+use crate::wallpaper::{despawn_wallpaper, SwitchWallpaper};
+use crate::wallpaper_render_plugin::{Dormant, WinitWindows};
+use crate::warm_up::WarmUp;
+
+/// Stops the wallpaper in low power states, which takes more than pausing its clock: a paused
+/// wallpaper still holds on to its textures, pipelines and window surface, and keeps the GPU out
+/// of its lowest power state.
+///
+/// Instead a frame, rendered at the high preset and full resolution, is saved and handed to
+/// Windows as a plain wallpaper, the window is hidden and everything it took to draw is let go,
+/// and the event loop stops updating until the power state changes back. If Windows doesn't take
+/// the frame, the window stays up showing it and only the updates stop.
+///
+/// Compiled pipelines stay in bevy's pipeline cache, which can't drop them.
+#[derive(Default)]
+pub struct StaticFallbackPlugin;
+
+impl Plugin for StaticFallbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StaticFallback>()
+            .init_resource::<ReleaseSurfaces>()
+            .add_system(run_static_fallback);
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<ReleaseSurfaces>()
+            .add_system_to_stage(RenderStage::Extract, extract_release_surfaces)
+            .add_system_to_stage(RenderStage::Cleanup, release_surfaces);
+    }
+}
+
+/// When the wallpaper stops animating.
+#[derive(Debug, Resource)]
+pub struct StaticFallback {
+    /// The power state from which on the wallpaper is static, or `None` to keep it animating.
+    pub from: Option<PowerState>,
+    phase: Phase,
+}
+
+impl Default for StaticFallback {
+    fn default() -> Self {
+        StaticFallback {
+            from: Some(PowerState::Saver),
+            phase: Phase::Animating,
+        }
+    }
+}
+
+impl StaticFallback {
+    pub fn new(from: Option<PowerState>) -> Self {
+        StaticFallback { from, ..default() }
+    }
+
+    /// Whether the wallpaper has stopped, and nothing gets rendered until it animates again.
+    pub fn is_static(&self) -> bool {
+        matches!(self.phase, Phase::Static { .. })
+    }
+}
+
+#[derive(Debug)]
+enum Phase {
+    Animating,
+    /// Waiting for the frame to be saved.
+    Capturing(Receiver<Result<(), String>>),
+    Static {
+        /// The wallpaper Windows showed before it got the frame, if it took it.
+        previous: Option<Vec<u16>>,
+    },
+}
+
+/// Drops the window surfaces at the end of the frame, for the render world to recreate once the
+/// window is shown again.
+#[derive(Debug, Default, Resource)]
+struct ReleaseSurfaces(bool);
+
+/// Where the frame handed to Windows is saved.
+fn frame_path() -> Option<PathBuf> {
+    let local_app_data = std::env::var_os("LOCALAPPDATA")?;
+    Some(
+        Path::new(&local_app_data)
+            .join("desktop")
+            .join("static.png"),
+    )
+}
+
+fn run_static_fallback(world: &mut World) {
+    // The frame Windows gets should be one of the wallpaper, not of it loading.
+    if !world
+        .get_resource::<WarmUp>()
+        .map_or(true, WarmUp::finished)
+    {
+        return;
+    }
+    let Some(power) = world.get_resource::<Power>().map(Power::state) else {
+        return;
+    };
+    let mut fallback = world.resource_mut::<StaticFallback>();
+    let wanted = fallback.from.map_or(false, |from| power >= from);
+    match std::mem::replace(&mut fallback.phase, Phase::Animating) {
+        Phase::Animating if wanted => {
+            info!("Stopping the wallpaper to save power");
+            let Some(path) = frame_path().filter(|_| world.contains_resource::<Screenshots>())
+            else {
+                go_static(world, None);
+                return;
+            };
+            if let Some(folder) = path.parent() {
+                let _ = std::fs::create_dir_all(folder);
+            }
+            // The frame may be shown for hours, so it's taken at the high preset, and at full
+            // resolution like every screenshot, rather than at what the power state allows.
+            world.resource_mut::<FullQuality>().0 = true;
+            let (sender, receiver) = crossbeam_channel::bounded(1);
+            world.resource_mut::<Screenshots>().take(
+                path,
+                Box::new(move |result| {
+                    let _ = sender.send(result);
+                }),
+            );
+            world.resource_mut::<StaticFallback>().phase = Phase::Capturing(receiver);
+        }
+        Phase::Animating => {}
+        // Keep animating if the power came back before the frame was saved.
+        Phase::Capturing(_) if !wanted => world.resource_mut::<FullQuality>().0 = false,
+        Phase::Capturing(receiver) => match receiver.try_recv() {
+            Ok(Ok(())) => go_static(world, frame_path()),
+            Ok(Err(err)) => {
+                warn!("Can't save the frame for Windows to show: {}", err);
+                go_static(world, None);
+            }
+            Err(TryRecvError::Disconnected) => go_static(world, None),
+            Err(TryRecvError::Empty) => {
+                world.resource_mut::<StaticFallback>().phase = Phase::Capturing(receiver);
+            }
+        },
+        Phase::Static { previous } if !wanted => {
+            info!("Animating the wallpaper again");
+            resume(world, previous);
+        }
+        phase @ Phase::Static { .. } => world.resource_mut::<StaticFallback>().phase = phase,
+    }
+}
+
+/// Hands `frame` to Windows and lets go of everything the wallpaper draws with, or if there's no
+/// frame or Windows won't take it, keeps the last one presented.
+fn go_static(world: &mut World, frame: Option<PathBuf>) {
+    world.resource_mut::<WallpaperClock>().pause();
+    world.resource_mut::<Dormant>().0 = true;
+    world.resource_mut::<FullQuality>().0 = false;
+    let previous = frame.and_then(|frame| {
+        let previous = desktop_wallpaper();
+        match set_desktop_wallpaper(&frame) {
+            // An empty path takes the image away again if there wasn't one.
+            Ok(()) => Some(previous.unwrap_or_else(|| vec![0])),
+            Err(err) => {
+                warn!("Windows won't show {}: {}", frame.display(), err);
+                None
+            }
+        }
+    });
+    world.resource_mut::<StaticFallback>().phase = Phase::Static {
+        previous: previous.clone(),
+    };
+    // Drawing anything else would replace the frame that stays presented.
+    if previous.is_none() {
+        return;
+    }
+
+    for window in world.non_send_resource::<WinitWindows>().windows.values() {
+        window.set_visible(false);
+    }
+    despawn_wallpaper(world);
+    world.resource_scope(|world, canvas: Mut<WallpaperCanvas>| {
+        let size = Extent3d {
+            width: 1,
+            height: 1,
+            ..default()
+        };
+        canvas.resize(&mut world.resource_mut::<Assets<Image>>(), size);
+    });
+    world.resource_mut::<ReleaseSurfaces>().0 = true;
+}
+
+fn resume(world: &mut World, previous: Option<Vec<u16>>) {
+    world.resource_mut::<WallpaperClock>().resume();
+    world.resource_mut::<Dormant>().0 = false;
+    let Some(previous) = previous else {
+        return;
+    };
+
+    if let Err(err) = set_desktop_wallpaper_wide(previous) {
+        warn!("Can't give Windows its wallpaper back: {}", err);
+    }
+    world.resource_mut::<ReleaseSurfaces>().0 = false;
+    for window in world.non_send_resource::<WinitWindows>().windows.values() {
+        window.set_visible(true);
+    }
+    world.resource_scope(|world, canvas: Mut<WallpaperCanvas>| {
+        world.resource_scope(|world, mut images: Mut<Assets<Image>>| {
+            if let Some(window) = world.resource::<Windows>().get_primary() {
+                canvas.fit(&mut images, window);
+            }
+        });
+    });
+    let current = world
+        .resource::<Playlist>()
+        .current()
+        .map(|entry| entry.wallpaper.clone());
+    if let Some(name) = current {
+        world.send_event(SwitchWallpaper {
+            name,
+            transition: None,
+        });
+    }
+}
+
+fn extract_release_surfaces(
+    mut release: ResMut<ReleaseSurfaces>,
+    extracted: Extract<Res<ReleaseSurfaces>>,
+) {
+    release.0 = extracted.0;
+}
+
+fn release_surfaces(release: Res<ReleaseSurfaces>, mut surfaces: ResMut<WindowSurfaces>) {
+    if release.0 {
+        *surfaces = WindowSurfaces::default();
+    }
+}
+
+/// The path of the image Windows shows as the desktop wallpaper, as a null-terminated wide string.
+fn desktop_wallpaper() -> Option<Vec<u16>> {
+    let mut path = vec![0u16; MAX_PATH as usize];
+    unsafe {
+        SystemParametersInfoW(
+            SPI_GETDESKWALLPAPER,
+            MAX_PATH,
+            path.as_mut_ptr() as *mut c_void,
+            SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+        )
+    }
+    .as_bool()
+    .then_some(path)
+}
+
+fn set_desktop_wallpaper(path: &Path) -> Result<(), String> {
+    let path = path.as_os_str().encode_wide().chain([0]).collect();
+    set_desktop_wallpaper_wide(path)
+}
+
+/// Shows the image at the null-terminated `path` on the desktop until the user logs off, without
+/// touching the wallpaper saved in their profile. An empty path shows no image.
+fn set_desktop_wallpaper_wide(mut path: Vec<u16>) -> Result<(), String> {
+    unsafe {
+        SystemParametersInfoW(
+            SPI_SETDESKWALLPAPER,
+            0,
+            path.as_mut_ptr() as *mut c_void,
+            SPIF_SENDCHANGE,
+        )
+    }
+    .ok()
+    .map_err(|err| err.to_string())
+}
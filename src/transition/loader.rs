@@ -0,0 +1,104 @@
+use bevy::asset::{AssetLoader, Error, LoadContext, LoadedAsset};
+use bevy::render::render_resource::{Shader, ShaderStage};
+use bevy::utils::BoxedFuture;
+
+/// Loads transitions written against the [gl-transitions](https://gl-transitions.com)
+/// conventions: a `vec4 transition(vec2 uv)` function that reads `progress` and `ratio` and
+/// samples the two wallpapers through `getFromColor` and `getToColor`.
+///
+/// Extra `uniform` parameters are turned into constants using the default value from their
+/// trailing `// = value` comment, as the gl-transitions editor does.
+#[derive(Default)]
+pub struct TransitionLoader;
+
+impl AssetLoader for TransitionLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let source = std::str::from_utf8(bytes)?;
+            let shader = Shader::from_glsl(wrap_transition(source)?, ShaderStage::Fragment);
+            load_context.set_default_asset(LoadedAsset::new(shader));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["glsl"]
+    }
+}
+
+const PRELUDE: &str = r#"#version 450
+
+layout(location = 2) in vec2 v_Uv;
+layout(location = 0) out vec4 o_Target;
+
+layout(set = 1, binding = 0) uniform TransitionMaterial {
+    float progress;
+    float ratio;
+};
+layout(set = 1, binding = 1) uniform texture2D from_texture;
+layout(set = 1, binding = 2) uniform sampler from_sampler;
+layout(set = 1, binding = 3) uniform texture2D to_texture;
+layout(set = 1, binding = 4) uniform sampler to_sampler;
+
+// gl-transitions expect GL's bottom left texture origin.
+vec4 getFromColor(vec2 uv) {
+    return texture(sampler2D(from_texture, from_sampler), vec2(uv.x, 1.0 - uv.y));
+}
+
+vec4 getToColor(vec2 uv) {
+    return texture(sampler2D(to_texture, to_sampler), vec2(uv.x, 1.0 - uv.y));
+}
+"#;
+
+const MAIN: &str = r#"
+void main() {
+    o_Target = transition(vec2(v_Uv.x, 1.0 - v_Uv.y));
+}
+"#;
+
+fn wrap_transition(source: &str) -> Result<String, Error> {
+    let mut wrapped = String::from(PRELUDE);
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("precision ") {
+            continue;
+        }
+        if let Some(declaration) = trimmed.strip_prefix("uniform ") {
+            wrapped.push_str(&uniform_to_const(declaration)?);
+        } else {
+            wrapped.push_str(line);
+        }
+        wrapped.push('\n');
+    }
+    wrapped.push_str(MAIN);
+    Ok(wrapped)
+}
+
+/// Turns `float smoothness; // = 0.5` into `const float smoothness = 0.5;`.
+fn uniform_to_const(declaration: &str) -> Result<String, Error> {
+    let (declaration, comment) = declaration
+        .split_once(';')
+        .ok_or_else(|| Error::msg(format!("unterminated uniform declaration: {declaration}")))?;
+    let mut tokens = declaration.split_whitespace();
+    let (Some(ty), Some(name), None) = (tokens.next(), tokens.next(), tokens.next()) else {
+        return Err(Error::msg(format!(
+            "unsupported uniform declaration: {declaration}"
+        )));
+    };
+    if ty.starts_with("sampler") {
+        return Err(Error::msg(format!(
+            "texture uniform {name} is not supported by transitions"
+        )));
+    }
+    let default = comment
+        .trim()
+        .strip_prefix("//")
+        .and_then(|comment| comment.trim().strip_prefix('='))
+        .map(|value| value.trim().trim_end_matches(';').to_string())
+        .unwrap_or_else(|| format!("{ty}(0)"));
+    Ok(format!("const {ty} {name} = {default};"))
+}
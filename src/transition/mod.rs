@@ -0,0 +1,331 @@
+mod loader;
+
+pub use loader::*;
+
+use bevy::app::{App, CoreStage, Plugin, StartupStage};
+use bevy::asset::{AddAsset, AssetServer, Assets, Handle};
+use bevy::ecs::prelude::*;
+use bevy::hierarchy::{DespawnRecursiveExt, Parent};
+use bevy::math::{Vec2, Vec3};
+use bevy::prelude::{shape, Camera2dBundle, Mesh, Transform};
+use bevy::reflect::TypeUuid;
+use bevy::render::camera::{Camera, RenderTarget};
+use bevy::render::mesh::MeshVertexBufferLayout;
+use bevy::render::render_resource::{
+    AsBindGroup, RenderPipelineDescriptor, Shader, SpecializedMeshPipelineError,
+};
+use bevy::render::texture::Image;
+use bevy::render::view::RenderLayers;
+use bevy::sprite::{Material2d, Material2dKey, Material2dPlugin, MaterialMesh2dBundle};
+use bevy::time::{Timer, TimerMode};
+use bevy::utils::{default, tracing::warn, Duration, HashMap};
+use bevy::window::{
+    RequestRedraw, WindowCreated, WindowResized, WindowScaleFactorChanged, Windows,
+};
+
+use crate::canvas::{
+    PresentationCamera, TargetWallpaperCameras, WallpaperCanvas, PRESENTATION_LAYER,
+};
+use crate::clock::WallpaperClock;
+use crate::wallpaper::{OutgoingWallpaperEntity, WallpaperSwitched};
+
+/// Draws the canvas to every window, blending from the previous wallpaper to the new one with
+/// a transition shader whenever the wallpaper is switched. The previous wallpaper keeps rendering
+/// until the transition is over.
+#[derive(Default)]
+pub struct TransitionPlugin;
+
+impl Plugin for TransitionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(Material2dPlugin::<TransitionMaterial>::default())
+            .init_asset_loader::<TransitionLoader>()
+            .add_startup_system_to_stage(StartupStage::PreStartup, setup_presentation)
+            .add_system(advance_transition)
+            .add_system_to_stage(CoreStage::PostUpdate, spawn_presentation)
+            .add_system_to_stage(CoreStage::PostUpdate, fit_presentation)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                begin_transition.before(TargetWallpaperCameras),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                despawn_outgoing_wallpaper.after(begin_transition),
+            );
+    }
+}
+
+/// The transition played when the wallpaper changes when no other one is requested.
+pub const DEFAULT_TRANSITION: &str = "crossfade";
+
+/// An animated switch between two wallpapers.
+#[derive(Debug, Clone)]
+pub struct Transition {
+    /// The file stem of a transition in `assets/transitions`, e.g. `"wipe"`.
+    pub name: String,
+    pub duration: Duration,
+}
+
+impl Transition {
+    pub fn new(name: impl Into<String>, duration: Duration) -> Self {
+        Transition {
+            name: name.into(),
+            duration,
+        }
+    }
+}
+
+/// Every transition shader in `assets/transitions`, by file stem. Dropping a gl-transitions
+/// `.glsl` file in there makes it available under its name.
+#[derive(Resource)]
+pub struct TransitionLibrary {
+    transitions: HashMap<String, Handle<Shader>>,
+}
+
+impl TransitionLibrary {
+    fn load(asset_server: &AssetServer) -> Self {
+        let handles = asset_server
+            .load_folder("transitions")
+            .unwrap_or_else(|err| {
+                warn!("Couldn't load transitions: {}", err);
+                Vec::new()
+            });
+        let transitions = handles
+            .into_iter()
+            .filter_map(|handle| {
+                let path = asset_server.get_handle_path(&handle)?;
+                let name = path.path().file_stem()?.to_str()?.to_string();
+                Some((name, handle.typed()))
+            })
+            .collect();
+        TransitionLibrary { transitions }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Handle<Shader>> {
+        self.transitions.get(name)
+    }
+}
+
+/// Blends the `from` and `to` canvases with a transition shader loaded by [`TransitionLoader`].
+#[derive(AsBindGroup, TypeUuid, Debug, Clone)]
+#[uuid = "4f1b1d53-0a9d-4d64-a1a5-3c1c8f1f7a2e"]
+#[bind_group_data(TransitionMaterialKey)]
+pub struct TransitionMaterial {
+    #[uniform(0)]
+    progress: f32,
+    /// Width over height of the canvas.
+    #[uniform(0)]
+    ratio: f32,
+    #[texture(1)]
+    #[sampler(2)]
+    from: Handle<Image>,
+    #[texture(3)]
+    #[sampler(4)]
+    to: Handle<Image>,
+    shader: Handle<Shader>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct TransitionMaterialKey {
+    shader: Handle<Shader>,
+}
+
+impl From<&TransitionMaterial> for TransitionMaterialKey {
+    fn from(material: &TransitionMaterial) -> Self {
+        TransitionMaterialKey {
+            shader: material.shader.clone(),
+        }
+    }
+}
+
+impl Material2d for TransitionMaterial {
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let fragment = descriptor.fragment.as_mut().unwrap();
+        fragment.shader = key.bind_group_data.shader;
+        fragment.entry_point = "main".into();
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+struct Presentation {
+    mesh: Handle<Mesh>,
+    material: Handle<TransitionMaterial>,
+    timer: Option<Timer>,
+}
+
+#[derive(Component)]
+struct PresentationQuad;
+
+fn setup_presentation(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    canvas: Res<WallpaperCanvas>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<TransitionMaterial>>,
+) {
+    let library = TransitionLibrary::load(&asset_server);
+    let shader = library.get(DEFAULT_TRANSITION).cloned().unwrap_or_else(|| {
+        warn!("Missing the {:?} transition", DEFAULT_TRANSITION);
+        Handle::default()
+    });
+    commands.insert_resource(Presentation {
+        mesh: meshes.add(Mesh::from(shape::Quad::new(Vec2::ONE))),
+        material: materials.add(TransitionMaterial {
+            progress: 1.0,
+            ratio: 1.0,
+            from: canvas.outgoing().clone(),
+            to: canvas.live().clone(),
+            shader,
+        }),
+        timer: None,
+    });
+    commands.insert_resource(library);
+}
+
+fn spawn_presentation(
+    mut commands: Commands,
+    presentation: Res<Presentation>,
+    mut created: EventReader<WindowCreated>,
+) {
+    let layer = RenderLayers::layer(PRESENTATION_LAYER);
+    for event in created.iter() {
+        commands.spawn((
+            Camera2dBundle {
+                camera: Camera {
+                    target: RenderTarget::Window(event.id),
+                    // after every wallpaper camera has drawn to the canvas
+                    priority: isize::MAX,
+                    ..default()
+                },
+                ..default()
+            },
+            PresentationCamera,
+            layer,
+        ));
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: presentation.mesh.clone().into(),
+                material: presentation.material.clone(),
+                ..default()
+            },
+            PresentationQuad,
+            layer,
+        ));
+    }
+}
+
+/// Stretches the presentation quads over the primary window. At a render scale of 1 the canvas has
+/// the primary window's resolution, so this is a 1:1 copy there, and otherwise it's filtered up.
+fn fit_presentation(
+    windows: Res<Windows>,
+    presentation: Res<Presentation>,
+    mut materials: ResMut<Assets<TransitionMaterial>>,
+    mut quads: Query<&mut Transform, With<PresentationQuad>>,
+    added: Query<(), Added<PresentationQuad>>,
+    mut resized: EventReader<WindowResized>,
+    mut scale_factor_changed: EventReader<WindowScaleFactorChanged>,
+) {
+    let primary_changed = !added.is_empty()
+        | resized.iter().any(|event| event.id.is_primary())
+        | scale_factor_changed
+            .iter()
+            .any(|event| event.id.is_primary());
+    if !primary_changed {
+        return;
+    }
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+
+    for mut transform in &mut quads {
+        transform.scale = Vec3::new(window.width(), window.height(), 1.0);
+    }
+    if let Some(material) = materials.get_mut(&presentation.material) {
+        material.ratio = window.width() / window.height().max(1.0);
+    }
+}
+
+fn begin_transition(
+    library: Res<TransitionLibrary>,
+    mut canvas: ResMut<WallpaperCanvas>,
+    mut presentation: ResMut<Presentation>,
+    mut materials: ResMut<Assets<TransitionMaterial>>,
+    mut switched: EventReader<WallpaperSwitched>,
+    mut redraw: EventWriter<RequestRedraw>,
+) {
+    let Some(event) = switched.iter().last() else {
+        return;
+    };
+    canvas.swap();
+
+    let shader = event.transition.as_ref().and_then(|transition| {
+        let shader = library.get(&transition.name);
+        if shader.is_none() {
+            warn!(
+                "Unknown transition {:?} switching to {:?}, cutting instead",
+                transition.name, event.name
+            );
+        }
+        Some((shader?.clone(), transition.duration))
+    });
+    presentation.timer = shader
+        .as_ref()
+        .map(|(_, duration)| Timer::new(*duration, TimerMode::Once));
+    if presentation.timer.is_some() {
+        redraw.send(RequestRedraw);
+    }
+
+    let Some(material) = materials.get_mut(&presentation.material) else {
+        return;
+    };
+    material.from = canvas.outgoing().clone();
+    material.to = canvas.live().clone();
+    material.progress = if presentation.timer.is_some() {
+        0.0
+    } else {
+        1.0
+    };
+    if let Some((shader, _)) = shader {
+        material.shader = shader;
+    }
+}
+
+fn advance_transition(
+    clock: Res<WallpaperClock>,
+    mut presentation: ResMut<Presentation>,
+    mut materials: ResMut<Assets<TransitionMaterial>>,
+    mut redraw: EventWriter<RequestRedraw>,
+) {
+    let presentation = &mut *presentation;
+    let Some(timer) = presentation.timer.as_mut() else {
+        return;
+    };
+    timer.tick(clock.frame_delta());
+    if let Some(material) = materials.get_mut(&presentation.material) {
+        material.progress = timer.percent();
+    }
+    if timer.finished() {
+        presentation.timer = None;
+    } else {
+        // Keep a reactive event loop going until the transition is over.
+        redraw.send(RequestRedraw);
+    }
+}
+
+/// Despawns the wallpaper that was switched away from once there's no transition showing it.
+fn despawn_outgoing_wallpaper(
+    mut commands: Commands,
+    presentation: Res<Presentation>,
+    outgoing: Query<Entity, (With<OutgoingWallpaperEntity>, Without<Parent>)>,
+) {
+    if presentation.timer.is_some() {
+        return;
+    }
+    for entity in &outgoing {
+        commands.entity(entity).despawn_recursive();
+    }
+}
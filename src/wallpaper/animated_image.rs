@@ -0,0 +1,335 @@
+use std::io::Cursor;
+
+use bevy::asset::FileAssetIo;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::render_resource::{Extent3d, TextureFormat};
+use bevy::utils::{tracing::warn, Duration};
+use crossbeam_channel::{Receiver, Sender};
+use image::codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder};
+use image::{AnimationDecoder, ImageResult};
+
+use crate::clock::WallpaperClock;
+use crate::wallpaper_render_plugin::{EventLoopWaker, NextUpdate};
+
+/// Label of the [`AnimatedImage`] loaded alongside every GIF, APNG and WebP image.
+pub(super) const ANIMATION_LABEL: &str = "animation";
+
+/// Browsers play frames with a delay this short at [`DEFAULT_FRAME_DELAY`] instead, and GIFs on
+/// the web have been authored with that in mind ever since.
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(10);
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+#[derive(Default)]
+pub struct AnimatedImagePlugin;
+
+impl Plugin for AnimatedImagePlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let waker = app.world.get_resource::<EventLoopWaker>().cloned();
+        app.add_asset::<AnimatedImage>()
+            .insert_resource(PngAnimations {
+                sender,
+                receiver,
+                waker,
+            })
+            .add_system(receive_png_animations)
+            .add_system(animate_images.after(receive_png_animations));
+    }
+}
+
+/// Whether the image at `path` is a PNG, which Bevy's own loader loads.
+pub(super) fn is_png(path: &str) -> bool {
+    let extension = path.rsplit_once('.').map_or("", |(_, extension)| extension);
+    extension.eq_ignore_ascii_case("png")
+}
+
+/// Decodes the animations of PNG files in the background. PNGs are loaded by Bevy's image loader,
+/// which knows nothing of animations, so they don't come with one labeled [`ANIMATION_LABEL`].
+#[derive(Resource)]
+pub(super) struct PngAnimations {
+    sender: Sender<(Entity, AnimatedImage)>,
+    receiver: Receiver<(Entity, AnimatedImage)>,
+    /// Wakes a reactive event loop to start playing an animation once it's decoded.
+    waker: Option<EventLoopWaker>,
+}
+
+impl PngAnimations {
+    /// Plays the PNG at asset path `path` into the image of `entity` once it's decoded, if it's
+    /// animated.
+    pub(super) fn load(&self, path: &str, entity: Entity) {
+        // Absolute paths are kept as they are by `join`.
+        let file = FileAssetIo::get_base_path().join("assets").join(path);
+        let sender = self.sender.clone();
+        let waker = self.waker.clone();
+        let spawned = std::thread::Builder::new()
+            .name(format!("decode {path}"))
+            .spawn(move || {
+                let animation = std::fs::read(&file)
+                    .map_err(|err| err.to_string())
+                    .and_then(|bytes| {
+                        AnimatedImage::decode(&bytes, "png").map_err(|err| err.to_string())
+                    });
+                match animation {
+                    Ok(animation) if animation.is_animated() => {
+                        let _ = sender.send((entity, animation));
+                        if let Some(waker) = waker {
+                            waker.wake();
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(err) => warn!("Can't decode the frames of {}: {}", file.display(), err),
+                }
+            });
+        if let Err(err) = spawned {
+            warn!("Can't start decoding the frames of {}: {}", path, err);
+        }
+    }
+}
+
+/// Starts playing the PNG animations that have been decoded, on the images they were decoded for
+/// unless those have gone since.
+fn receive_png_animations(
+    mut commands: Commands,
+    png_animations: Res<PngAnimations>,
+    mut animations: ResMut<Assets<AnimatedImage>>,
+) {
+    for (entity, animation) in png_animations.receiver.try_iter() {
+        if let Some(mut entity) = commands.get_entity(entity) {
+            entity.insert(ImageAnimation::new(animations.add(animation)));
+        }
+    }
+}
+
+/// The decoded frames of an animated GIF, APNG or WebP file.
+///
+/// Stills in those formats load as an animation without frames, so that there's always something
+/// behind the label.
+#[derive(TypeUuid)]
+#[uuid = "0a3e5f0c-9d0e-4c52-b8de-6b1a4e3f7d21"]
+pub struct AnimatedImage {
+    frames: Vec<AnimationFrame>,
+    size: Extent3d,
+    /// How many times the animation plays before stopping on its last frame, or `None` to repeat
+    /// forever.
+    plays: Option<u32>,
+    duration: Duration,
+}
+
+struct AnimationFrame {
+    /// Rgba8 pixels covering the whole image.
+    data: Vec<u8>,
+    delay: Duration,
+}
+
+impl AnimatedImage {
+    /// Decodes every frame of an image with the given file extension up front, which keeps
+    /// playback cheap at the cost of memory.
+    pub(super) fn decode(bytes: &[u8], extension: &str) -> ImageResult<Self> {
+        let frames = match extension {
+            "gif" => GifDecoder::new(Cursor::new(bytes))?.into_frames(),
+            "png" | "apng" => {
+                let decoder = PngDecoder::new(Cursor::new(bytes))?;
+                if !decoder.is_apng() {
+                    return Ok(AnimatedImage::still());
+                }
+                decoder.apng().into_frames()
+            }
+            // Still WebPs decode to no frames at all.
+            "webp" => WebPDecoder::new(Cursor::new(bytes))?.into_frames(),
+            _ => return Ok(AnimatedImage::still()),
+        }
+        .collect_frames()?;
+
+        let Some(first) = frames.first() else {
+            return Ok(AnimatedImage::still());
+        };
+        let (width, height) = first.buffer().dimensions();
+        let frames: Vec<AnimationFrame> = frames
+            .into_iter()
+            .filter(|frame| frame.buffer().dimensions() == (width, height))
+            .map(|frame| {
+                let delay = Duration::from(frame.delay());
+                AnimationFrame {
+                    delay: if delay <= MIN_FRAME_DELAY {
+                        DEFAULT_FRAME_DELAY
+                    } else {
+                        delay
+                    },
+                    data: frame.into_buffer().into_raw(),
+                }
+            })
+            .collect();
+        Ok(AnimatedImage {
+            duration: frames.iter().map(|frame| frame.delay).sum(),
+            frames,
+            size: Extent3d {
+                width,
+                height,
+                ..default()
+            },
+            plays: play_count(bytes, extension),
+        })
+    }
+
+    fn still() -> Self {
+        AnimatedImage {
+            frames: Vec::new(),
+            size: Extent3d::default(),
+            plays: None,
+            duration: Duration::ZERO,
+        }
+    }
+
+    fn is_animated(&self) -> bool {
+        self.frames.len() > 1
+    }
+
+    /// The frame showing `time` into the animation, and how much longer it shows for if another
+    /// one follows.
+    fn frame_at(&self, time: Duration) -> (usize, Option<Duration>) {
+        let last = self.frames.len() - 1;
+        let plays = time.as_nanos() / self.duration.as_nanos();
+        if self.plays.map_or(false, |limit| plays >= u128::from(limit)) {
+            return (last, None);
+        }
+
+        let mut time = Duration::from_nanos((time.as_nanos() % self.duration.as_nanos()) as u64);
+        for (index, frame) in self.frames.iter().enumerate() {
+            if time < frame.delay {
+                return (index, Some(frame.delay - time));
+            }
+            time -= frame.delay;
+        }
+        (last, Some(Duration::ZERO))
+    }
+
+    fn write_frame(&self, index: usize, image: &mut Image) {
+        image.data.clone_from(&self.frames[index].data);
+        image.texture_descriptor.size = self.size;
+        image.texture_descriptor.format = TextureFormat::Rgba8UnormSrgb;
+    }
+}
+
+/// How many times an animation plays, or `None` for forever. The `image` crate doesn't expose
+/// this, so it's read straight from the file.
+fn play_count(bytes: &[u8], extension: &str) -> Option<u32> {
+    let count = match extension {
+        "gif" => {
+            // The NETSCAPE2.0 application extension holds the number of times to repeat after
+            // the first play. GIFs without it play once.
+            const NETSCAPE: &[u8] = b"NETSCAPE2.0";
+            let Some(start) = bytes
+                .windows(NETSCAPE.len())
+                .position(|window| window == NETSCAPE)
+            else {
+                return Some(1);
+            };
+            match bytes.get(start + NETSCAPE.len()..start + NETSCAPE.len() + 4) {
+                Some(&[3, 1, low, high]) => match u16::from_le_bytes([low, high]) {
+                    0 => 0,
+                    repeats => u32::from(repeats) + 1,
+                },
+                _ => 1,
+            }
+        }
+        "png" | "apng" => {
+            let actl = png_chunk(bytes, b"acTL")?;
+            u32::from_be_bytes(actl.get(4..8)?.try_into().ok()?)
+        }
+        "webp" => {
+            let anim = webp_chunk(bytes, b"ANIM")?;
+            u32::from(u16::from_le_bytes(anim.get(4..6)?.try_into().ok()?))
+        }
+        _ => return None,
+    };
+    // All three formats use zero for looping forever.
+    (count > 0).then_some(count)
+}
+
+/// Finds the data of a PNG chunk that comes before the image data.
+fn png_chunk<'a>(bytes: &'a [u8], id: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut rest = bytes.get(8..)?;
+    while rest.len() >= 8 {
+        let length = u32::from_be_bytes(rest[..4].try_into().ok()?) as usize;
+        let chunk_id = &rest[4..8];
+        if chunk_id == b"IDAT" {
+            return None;
+        }
+        let data = rest.get(8..8 + length)?;
+        if chunk_id == id {
+            return Some(data);
+        }
+        // Skip the data and its CRC.
+        rest = rest.get(12 + length..)?;
+    }
+    None
+}
+
+/// Finds the data of a top level chunk in a WebP file.
+fn webp_chunk<'a>(bytes: &'a [u8], id: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut rest = bytes.get(12..)?;
+    while rest.len() >= 8 {
+        let chunk_id = &rest[..4];
+        let length = u32::from_le_bytes(rest[4..8].try_into().ok()?) as usize;
+        let data = rest.get(8..8 + length)?;
+        if chunk_id == id {
+            return Some(data);
+        }
+        // Chunks are padded to an even length.
+        rest = rest.get(8 + length + length % 2..)?;
+    }
+    None
+}
+
+/// Plays an [`AnimatedImage`] into the sprite's image.
+#[derive(Component)]
+pub(super) struct ImageAnimation {
+    animation: Handle<AnimatedImage>,
+    /// Clock time at which the first frame showed.
+    started: Option<Duration>,
+    frame: Option<usize>,
+}
+
+impl ImageAnimation {
+    pub(super) fn new(animation: Handle<AnimatedImage>) -> Self {
+        ImageAnimation {
+            animation,
+            started: None,
+            frame: None,
+        }
+    }
+}
+
+fn animate_images(
+    clock: Res<WallpaperClock>,
+    animations: Res<Assets<AnimatedImage>>,
+    mut images: ResMut<Assets<Image>>,
+    mut next_update: ResMut<NextUpdate>,
+    mut sprites: Query<(&mut ImageAnimation, &Handle<Image>)>,
+) {
+    for (mut state, handle) in &mut sprites {
+        let Some(animation) = animations.get(&state.animation) else {
+            continue;
+        };
+        if !animation.is_animated() {
+            continue;
+        }
+
+        let started = *state.started.get_or_insert(clock.elapsed());
+        let (frame, remaining) = animation.frame_at(clock.elapsed().saturating_sub(started));
+        if state.frame != Some(frame) {
+            let Some(image) = images.get_mut(handle) else {
+                continue;
+            };
+            animation.write_frame(frame, image);
+            state.frame = Some(frame);
+        }
+
+        // Sleep until the next frame is due rather than updating continuously. A paused clock
+        // doesn't need waking up for at all.
+        if let Some(wait) = remaining.and_then(|remaining| clock.real_duration(remaining)) {
+            next_update.request_in(wait);
+        }
+    }
+}
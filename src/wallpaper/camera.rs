@@ -0,0 +1,368 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+use windows::Win32::Foundation::POINT;
+use windows::Win32::UI::WindowsAndMessaging::GetCursorPos;
+
+use super::{RaymarchCamera, WallpaperManifest};
+use crate::clock::WallpaperClock;
+use crate::parameters::{Parameters, ResolveParameters};
+
+/// How far ahead of a camera that stays still its parallax pivots around.
+const STILL_FOCUS_DISTANCE: f32 = 5.0;
+
+#[derive(Default)]
+pub struct CameraRigPlugin;
+
+impl Plugin for CameraRigPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(attach_camera_rigs.after(ResolveParameters))
+            .add_system(
+                move_camera_rigs
+                    .label(MoveCameraRigs)
+                    .after(attach_camera_rigs),
+            );
+    }
+}
+
+/// Label for the system that moves rigged cameras. Systems reading camera transforms for the
+/// frame run after it.
+#[derive(SystemLabel)]
+pub struct MoveCameraRigs;
+
+/// Moves the camera a wallpaper looks through, on the wallpaper clock:
+///
+/// ```ron
+/// camera: Some((
+///     motion: Path(
+///         keyframes: [
+///             (time: 0.0, position: (-2.0, 2.5, 5.0), target: (0.0, 0.0, 0.0)),
+///             (time: 12.0, position: (3.0, 1.5, 4.0), target: (0.0, 0.5, 0.0)),
+///             (time: 24.0, position: (-2.0, 2.5, 5.0), target: (0.0, 0.0, 0.0)),
+///         ],
+///     ),
+///     breathing: Some((distance: 0.15, period: 8.0)),
+///     parallax: Some((distance: 0.3)),
+/// )),
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CameraRigDeclaration {
+    pub motion: CameraMotion,
+    /// Dollies the camera toward and away from what it looks at.
+    pub breathing: Option<Breathing>,
+    /// Shifts the camera with the mouse, as if looking into the screen from where it is.
+    pub parallax: Option<Parallax>,
+}
+
+impl CameraRigDeclaration {
+    pub(super) fn validate(&self) -> Result<(), String> {
+        if let CameraMotion::Path { keyframes, .. } = &self.motion {
+            if keyframes.len() < 2 {
+                return Err("a camera path needs at least two keyframes".to_string());
+            }
+            if keyframes[0].time != 0.0
+                || keyframes
+                    .windows(2)
+                    .any(|pair| pair[1].time <= pair[0].time)
+            {
+                return Err(
+                    "camera keyframes have to start at 0 seconds and go forward in time"
+                        .to_string(),
+                );
+            }
+        }
+        if let Some(breathing) = &self.breathing {
+            if !(breathing.period.is_finite() && breathing.period > 0.0) {
+                return Err("the period of the camera's breathing isn't positive".to_string());
+            }
+        }
+        if let Some(parallax) = &self.parallax {
+            if !(parallax.smoothing >= 0.0) {
+                return Err("the smoothing of the camera's parallax is negative".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub enum CameraMotion {
+    /// Stays where the scene put it.
+    #[default]
+    Still,
+    /// Circles around the target at the distance and height the scene put the camera at.
+    Orbit {
+        target: (f32, f32, f32),
+        /// Radians per second, counterclockwise seen from above.
+        #[serde(default = "orbit_speed")]
+        speed: f32,
+    },
+    /// Follows a smooth curve through the keyframes, which start the first one at 0 seconds. A
+    /// looping path that ends on the keyframe it starts with goes around without a jump.
+    Path {
+        keyframes: Vec<CameraKeyframe>,
+        /// How the camera speeds up and slows down between keyframes.
+        #[serde(default)]
+        easing: Easing,
+        #[serde(default = "looping")]
+        looping: bool,
+    },
+}
+
+fn orbit_speed() -> f32 {
+    0.1
+}
+
+fn looping() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CameraKeyframe {
+    /// Seconds of wallpaper time into the path.
+    pub time: f32,
+    pub position: (f32, f32, f32),
+    /// What the camera looks at.
+    pub target: (f32, f32, f32),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum Easing {
+    Linear,
+    /// Starts slow.
+    In,
+    /// Ends slow.
+    Out,
+    /// Starts and ends slow, stopping briefly at each keyframe.
+    #[default]
+    InOut,
+}
+
+impl Easing {
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::In => t * t,
+            Easing::Out => t * (2.0 - t),
+            Easing::InOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Breathing {
+    /// How far the camera moves either way.
+    pub distance: f32,
+    /// Seconds from one breath to the next.
+    pub period: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Parallax {
+    /// How far the camera moves with the mouse at the edge of the screen.
+    pub distance: f32,
+    /// Seconds the camera takes to mostly catch up with the mouse.
+    #[serde(default = "parallax_smoothing")]
+    pub smoothing: f32,
+}
+
+fn parallax_smoothing() -> f32 {
+    0.5
+}
+
+/// Moves a camera the way a [`CameraRigDeclaration`] says, on the wallpaper clock. The active
+/// wallpaper's manifest puts one on its 3D and raymarch cameras.
+#[derive(Component, Debug, Clone)]
+pub struct CameraRig {
+    pub declaration: CameraRigDeclaration,
+    /// Where the camera was before the rig took it over.
+    rest: Transform,
+    /// Seconds of wallpaper time since the rig took the camera over.
+    elapsed: f32,
+    /// The mouse position the camera has caught up with, from -1 to 1 across the window.
+    parallax: Vec2,
+}
+
+impl CameraRig {
+    pub fn new(declaration: CameraRigDeclaration, rest: Transform) -> Self {
+        CameraRig {
+            declaration,
+            rest,
+            elapsed: 0.0,
+            parallax: Vec2::ZERO,
+        }
+    }
+
+    pub(super) fn transform(&self) -> Transform {
+        let (mut position, target) = match &self.declaration.motion {
+            CameraMotion::Still => (
+                self.rest.translation,
+                self.rest.translation + self.rest.forward() * STILL_FOCUS_DISTANCE,
+            ),
+            CameraMotion::Orbit { target, speed } => {
+                let target = Vec3::from(*target);
+                let offset = self.rest.translation - target;
+                let distance = offset.x.hypot(offset.z);
+                let angle = offset.x.atan2(offset.z) + speed * self.elapsed;
+                let position =
+                    target + Vec3::new(angle.sin() * distance, offset.y, angle.cos() * distance);
+                (position, target)
+            }
+            CameraMotion::Path {
+                keyframes,
+                easing,
+                looping,
+            } => {
+                let duration = keyframes.last().map_or(0.0, |keyframe| keyframe.time);
+                let time = if *looping && duration > 0.0 {
+                    self.elapsed % duration
+                } else {
+                    self.elapsed.min(duration)
+                };
+                sample_path(keyframes, time, |t| easing.apply(t))
+            }
+        };
+        let forward = (target - position).normalize_or_zero();
+
+        if let Some(breathing) = &self.declaration.breathing {
+            let phase = self.elapsed / breathing.period * TAU;
+            position += forward * phase.sin() * breathing.distance;
+        }
+        if let Some(parallax) = &self.declaration.parallax {
+            let right = forward.cross(Vec3::Y).normalize_or_zero();
+            let up = right.cross(forward);
+            // Moving the mouse right looks at the scene from further right.
+            position += (right * self.parallax.x + up * self.parallax.y) * parallax.distance;
+        }
+
+        if forward == Vec3::ZERO {
+            return Transform::from_translation(position).with_rotation(self.rest.rotation);
+        }
+        Transform::from_translation(position)
+            .looking_at(target, Vec3::Y)
+            .with_scale(self.rest.scale)
+    }
+}
+
+/// The position and target at `time` along a Catmull-Rom spline through the keyframes.
+fn sample_path(keyframes: &[CameraKeyframe], time: f32, ease: impl Fn(f32) -> f32) -> (Vec3, Vec3) {
+    let Some(last) = keyframes.len().checked_sub(1) else {
+        return (Vec3::ZERO, Vec3::NEG_Z);
+    };
+    let segment = keyframes
+        .windows(2)
+        .position(|pair| time < pair[1].time)
+        .unwrap_or(last.saturating_sub(1));
+    let at = |index: isize| &keyframes[index.clamp(0, last as isize) as usize];
+    let i = segment as isize;
+    let (k0, k1, k2, k3) = (at(i - 1), at(i), at(i + 1), at(i + 2));
+    let span = k2.time - k1.time;
+    let t = if span > 0.0 {
+        ease(((time - k1.time) / span).clamp(0.0, 1.0))
+    } else {
+        0.0
+    };
+    let spline = |p: fn(&CameraKeyframe) -> (f32, f32, f32)| {
+        catmull_rom(p(k0).into(), p(k1).into(), p(k2).into(), p(k3).into(), t)
+    };
+    (
+        spline(|keyframe| keyframe.position),
+        spline(|keyframe| keyframe.target),
+    )
+}
+
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+/// Puts the active wallpaper manifest's camera rig on its cameras, and keeps their rigs up to
+/// date when the manifest changes.
+fn attach_camera_rigs(
+    mut commands: Commands,
+    parameters: Res<Parameters>,
+    manifests: Res<Assets<WallpaperManifest>>,
+    mut cameras: Query<
+        (Entity, &Transform, Option<&mut CameraRig>),
+        Or<(With<Camera3d>, With<RaymarchCamera>)>,
+    >,
+) {
+    let Some(declaration) = parameters
+        .manifest()
+        .and_then(|manifest| manifests.get(manifest))
+        .and_then(|manifest| manifest.camera.as_ref())
+    else {
+        return;
+    };
+    for (entity, transform, rig) in &mut cameras {
+        match rig {
+            Some(mut rig) => {
+                if rig.declaration != *declaration {
+                    rig.declaration = declaration.clone();
+                }
+            }
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(CameraRig::new(declaration.clone(), *transform));
+            }
+        }
+    }
+}
+
+fn move_camera_rigs(
+    clock: Res<WallpaperClock>,
+    windows: Res<Windows>,
+    mut rigs: Query<(&mut Transform, &mut CameraRig)>,
+) {
+    let cursor = windows.get_primary().and_then(cursor_position);
+    let delta = clock.delta_seconds();
+    for (mut transform, mut rig) in &mut rigs {
+        let rig = &mut *rig;
+        rig.elapsed += delta;
+        // Following the mouse stops with the clock too, so a paused wallpaper holds still.
+        if let (Some(parallax), Some(cursor)) = (&rig.declaration.parallax, cursor) {
+            if delta > 0.0 {
+                let catch_up = if parallax.smoothing > 0.0 {
+                    1.0 - (-3.0 * delta / parallax.smoothing).exp()
+                } else {
+                    1.0
+                };
+                rig.parallax = rig.parallax.lerp(cursor, catch_up);
+            }
+        }
+        let moved = rig.transform();
+        if *transform != moved {
+            *transform = moved;
+        }
+    }
+}
+
+/// Where the mouse is over `window`, from -1 to 1 with +Y up. The wallpaper sits behind the
+/// desktop icons and never gets mouse events, so this asks Windows rather than the window.
+fn cursor_position(window: &Window) -> Option<Vec2> {
+    let mut point = POINT::default();
+    let position = if unsafe { GetCursorPos(&mut point) }.as_bool() {
+        let origin = window.position()?;
+        Vec2::new(
+            (point.x - origin.x) as f32,
+            window.physical_height() as f32 - (point.y - origin.y) as f32,
+        )
+    } else {
+        window.physical_cursor_position()?.as_vec2()
+    };
+    let size = Vec2::new(
+        window.physical_width() as f32,
+        window.physical_height() as f32,
+    );
+    if size.min_element() <= 0.0 {
+        return None;
+    }
+    Some((position / size * 2.0 - 1.0).clamp(Vec2::NEG_ONE, Vec2::ONE))
+}
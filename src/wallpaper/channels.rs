@@ -0,0 +1,156 @@
+use bevy::asset::HandleUntyped;
+use bevy::reflect::TypeUuid;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::Image;
+
+use crate::shader_material::cube_view;
+
+/// The texture bound to [`ChannelSource::Noise`](super::ChannelSource::Noise) channels.
+pub const NOISE_TEXTURE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Image::TYPE_UUID, 0x2f6a_d0b3_7c18_e945);
+
+const NOISE_SIZE: u32 = 256;
+
+/// The same noise every time, so that wallpapers look the same from one run to the next.
+pub(super) fn noise_image() -> Image {
+    let data = (0..NOISE_SIZE * NOISE_SIZE * 4)
+        .map(|index| hash(index) as u8)
+        .collect();
+    Image::new(
+        Extent3d {
+            width: NOISE_SIZE,
+            height: NOISE_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8Unorm,
+    )
+}
+
+/// Chris Wellons' lowbias32.
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^ (x >> 16)
+}
+
+pub(super) fn is_cubemap(image: &Image) -> bool {
+    image.texture_descriptor.size.depth_or_array_layers == 6
+        && image.texture_view_descriptor.is_some()
+}
+
+/// Turns an image with six square faces stacked on top of each other into a cubemap. Anything
+/// else is replaced with a black cube, so that it can still be bound as one, and `false`
+/// returned.
+pub(super) fn stack_cubemap(image: &mut Image) -> bool {
+    let Extent3d {
+        width,
+        height,
+        depth_or_array_layers,
+    } = image.texture_descriptor.size;
+    let stacked = depth_or_array_layers == 1 && height == width * 6;
+    if stacked {
+        image.reinterpret_stacked_2d_as_array(6);
+    } else {
+        *image = Image::new_fill(
+            Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 6,
+            },
+            TextureDimension::D2,
+            &[0, 0, 0, 255],
+            TextureFormat::Rgba8UnormSrgb,
+        );
+    }
+    image.texture_view_descriptor = Some(cube_view());
+    stacked
+}
+
+/// How many mip levels a full chain for a texture of this size has.
+fn mip_levels(size: Extent3d) -> u32 {
+    32 - size.width.max(size.height).leading_zeros()
+}
+
+pub(super) fn needs_mipmaps(image: &Image) -> bool {
+    image.texture_descriptor.mip_level_count < mip_levels(image.texture_descriptor.size)
+}
+
+/// Replaces the image's data with a full chain of mipmaps for each of its layers, each level a
+/// box filtered half of the one before. Returns `false` without changing anything for formats
+/// other than 8 bit RGBA or BGRA.
+pub(super) fn generate_mipmaps(image: &mut Image) -> bool {
+    let srgb = match image.texture_descriptor.format {
+        TextureFormat::Rgba8Unorm | TextureFormat::Bgra8Unorm => false,
+        TextureFormat::Rgba8UnormSrgb | TextureFormat::Bgra8UnormSrgb => true,
+        _ => return false,
+    };
+    let size = image.texture_descriptor.size;
+    let levels = mip_levels(size);
+    let layer_size = (size.width * size.height * 4) as usize;
+
+    // wgpu expects every level of the first layer, then every level of the next one.
+    let mut data = Vec::with_capacity(image.data.len() * 2);
+    for layer in image.data.chunks_exact(layer_size) {
+        data.extend_from_slice(layer);
+        let mut level = layer.to_vec();
+        let (mut width, mut height) = (size.width, size.height);
+        for _ in 1..levels {
+            level = downsample(&level, width, height, srgb);
+            width = (width / 2).max(1);
+            height = (height / 2).max(1);
+            data.extend_from_slice(&level);
+        }
+    }
+    image.data = data;
+    image.texture_descriptor.mip_level_count = levels;
+    true
+}
+
+/// Averages each 2 by 2 block of texels, in linear light for sRGB textures.
+fn downsample(texels: &[u8], width: u32, height: u32, srgb: bool) -> Vec<u8> {
+    let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+    let mut downsampled = Vec::with_capacity((half_width * half_height * 4) as usize);
+    for y in 0..half_height {
+        for x in 0..half_width {
+            for channel in 0..4 {
+                let color = srgb && channel < 3;
+                let mut sum = 0.0;
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    // Odd sizes leave the last row or column without a neighbor to pair up with.
+                    let sx = (x * 2 + dx).min(width - 1);
+                    let sy = (y * 2 + dy).min(height - 1);
+                    let value = texels[((sy * width + sx) * 4 + channel) as usize] as f32 / 255.0;
+                    sum += if color { srgb_to_linear(value) } else { value };
+                }
+                let average = sum / 4.0;
+                let value = if color {
+                    linear_to_srgb(average)
+                } else {
+                    average
+                };
+                downsampled.push((value * 255.0).round() as u8);
+            }
+        }
+    }
+    downsampled
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
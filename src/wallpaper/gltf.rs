@@ -0,0 +1,304 @@
+use std::path::Path;
+
+use bevy::gltf::Gltf;
+use bevy::prelude::*;
+use bevy::scene::SceneInstance;
+use bevy::utils::tracing::warn;
+
+use super::{CameraMotion, CameraRig, CameraRigDeclaration, WallpaperEntity};
+use crate::clock::WallpaperClock;
+
+/// File extensions of the scenes shown with a [`GltfWallpaper`].
+const GLTF_EXTENSIONS: &[&str] = &["gltf", "glb"];
+
+#[derive(Default)]
+pub struct GltfWallpaperPlugin;
+
+impl Plugin for GltfWallpaperPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_gltf_scenes)
+            .add_system(set_up_gltf_scenes.after(spawn_gltf_scenes))
+            .add_system(play_gltf_animations.after(set_up_gltf_scenes));
+    }
+}
+
+/// Whether `path` is a scene to show with a [`GltfWallpaper`].
+pub fn is_gltf(path: &Path) -> bool {
+    path.extension().map_or(false, |extension| {
+        GLTF_EXTENSIONS
+            .iter()
+            .any(|gltf| extension.eq_ignore_ascii_case(gltf))
+    })
+}
+
+/// A scene exported to glTF, like from Blender, with its animations played on the wallpaper
+/// clock.
+#[derive(Debug, Clone)]
+pub struct GltfWallpaper {
+    /// Asset path of the `.gltf` or `.glb` file.
+    pub path: String,
+    /// Index of the scene to show, or the file's default scene if `None`.
+    pub scene: Option<usize>,
+    pub camera: GltfCamera,
+    pub animation: GltfAnimation,
+}
+
+impl GltfWallpaper {
+    pub fn new(path: impl Into<String>) -> Self {
+        GltfWallpaper {
+            path: path.into(),
+            scene: None,
+            camera: GltfCamera::default(),
+            animation: GltfAnimation::default(),
+        }
+    }
+
+    pub(super) fn spawn(&self, world: &mut World) {
+        let gltf = world.resource::<AssetServer>().load(self.path.as_str());
+        // The scene is spawned under this entity once the file has loaded.
+        world.spawn((
+            GltfScene {
+                gltf,
+                wallpaper: self.clone(),
+                spawned: false,
+                set_up: false,
+                players: Vec::new(),
+                clips: Vec::new(),
+                clip: 0,
+                elapsed: 0.0,
+            },
+            SpatialBundle::default(),
+        ));
+    }
+}
+
+/// What a [`GltfWallpaper`] is looked at through.
+#[derive(Debug, Clone, Default)]
+pub enum GltfCamera {
+    /// The scene's first camera, or an [`OrbitCamera`] with its defaults if it has none.
+    #[default]
+    Scene,
+    /// Ignores the scene's cameras.
+    Orbit(OrbitCamera),
+}
+
+/// Which of a [`GltfWallpaper`]'s animation clips play.
+#[derive(Debug, Clone, Default)]
+pub enum GltfAnimation {
+    /// Keeps the scene in its rest pose.
+    None,
+    /// Loops the clip with this name.
+    Loop(String),
+    /// Plays every clip in the order they are in the file, starting over after the last one.
+    #[default]
+    Playlist,
+}
+
+/// Circles a camera around a target, once every `2π / speed` seconds of wallpaper time. A camera
+/// rig in the wallpaper's manifest takes over from it.
+#[derive(Debug, Clone)]
+pub struct OrbitCamera {
+    pub target: Vec3,
+    /// Horizontal distance from the target.
+    pub distance: f32,
+    /// Height above the target.
+    pub height: f32,
+    /// Radians per second, counterclockwise seen from above.
+    pub speed: f32,
+    /// Where around the target the camera starts, in radians from +Z.
+    pub angle: f32,
+}
+
+impl Default for OrbitCamera {
+    fn default() -> Self {
+        OrbitCamera {
+            target: Vec3::ZERO,
+            distance: 5.0,
+            height: 2.0,
+            speed: 0.1,
+            angle: 0.0,
+        }
+    }
+}
+
+impl OrbitCamera {
+    fn rig(&self) -> CameraRig {
+        let offset = Vec3::new(
+            self.angle.sin() * self.distance,
+            self.height,
+            self.angle.cos() * self.distance,
+        );
+        let rest =
+            Transform::from_translation(self.target + offset).looking_at(self.target, Vec3::Y);
+        let declaration = CameraRigDeclaration {
+            motion: CameraMotion::Orbit {
+                target: self.target.into(),
+                speed: self.speed,
+            },
+            ..default()
+        };
+        CameraRig::new(declaration, rest)
+    }
+}
+
+#[derive(Component)]
+struct GltfScene {
+    gltf: Handle<Gltf>,
+    wallpaper: GltfWallpaper,
+    spawned: bool,
+    /// Whether the scene's camera, lights and animations have been sorted out.
+    set_up: bool,
+    /// The entities with an [`AnimationPlayer`], which bevy puts at the root of each animated
+    /// hierarchy.
+    players: Vec<Entity>,
+    clips: Vec<Handle<AnimationClip>>,
+    /// Index into `clips` of the one playing.
+    clip: usize,
+    /// Seconds of wallpaper time into the playing clip.
+    elapsed: f32,
+}
+
+fn spawn_gltf_scenes(
+    mut commands: Commands,
+    gltfs: Res<Assets<Gltf>>,
+    mut scenes: Query<(Entity, &mut GltfScene)>,
+) {
+    for (entity, mut scene) in &mut scenes {
+        if scene.spawned {
+            continue;
+        }
+        let Some(gltf) = gltfs.get(&scene.gltf) else {
+            continue;
+        };
+        let handle = match scene.wallpaper.scene {
+            Some(index) => gltf.scenes.get(index),
+            None => gltf.default_scene.as_ref().or_else(|| gltf.scenes.first()),
+        };
+        let Some(handle) = handle else {
+            warn!("{:?} has no scene to show", scene.wallpaper.path);
+            scene.spawned = true;
+            continue;
+        };
+        commands.entity(entity).insert(handle.clone());
+        scene.clips = match &scene.wallpaper.animation {
+            GltfAnimation::None => Vec::new(),
+            GltfAnimation::Loop(name) => match gltf.named_animations.get(name) {
+                Some(clip) => vec![clip.clone()],
+                None => {
+                    warn!(
+                        "{:?} has no animation called {:?}",
+                        scene.wallpaper.path, name
+                    );
+                    Vec::new()
+                }
+            },
+            GltfAnimation::Playlist => gltf.animations.clone(),
+        };
+        scene.spawned = true;
+    }
+}
+
+/// Once the scene has spawned, falls back to an orbit camera and a light if it needs them, and
+/// hands the first clip to its animation players.
+#[allow(clippy::too_many_arguments)]
+fn set_up_gltf_scenes(
+    mut commands: Commands,
+    scene_spawner: Res<SceneSpawner>,
+    mut scenes: Query<(&mut GltfScene, &SceneInstance)>,
+    mut cameras: Query<&mut Camera>,
+    lights: Query<(), Or<(With<PointLight>, With<DirectionalLight>, With<SpotLight>)>>,
+    mut players: Query<&mut AnimationPlayer>,
+) {
+    for (mut scene, instance) in &mut scenes {
+        if scene.set_up || !scene_spawner.instance_is_ready(**instance) {
+            continue;
+        }
+        let entities: Vec<Entity> = scene_spawner.iter_instance_entities(**instance).collect();
+
+        let scene_cameras: Vec<Entity> = entities
+            .iter()
+            .copied()
+            .filter(|entity| cameras.contains(*entity))
+            .collect();
+        let orbit = match &scene.wallpaper.camera {
+            GltfCamera::Scene if !scene_cameras.is_empty() => None,
+            GltfCamera::Scene => Some(OrbitCamera::default()),
+            GltfCamera::Orbit(orbit) => Some(orbit.clone()),
+        };
+        if let Some(orbit) = orbit {
+            for entity in scene_cameras {
+                cameras.get_mut(entity).unwrap().is_active = false;
+            }
+            let rig = orbit.rig();
+            commands.spawn((
+                Camera3dBundle {
+                    transform: rig.transform(),
+                    ..default()
+                },
+                rig,
+                WallpaperEntity,
+            ));
+        }
+
+        if !entities.iter().any(|entity| lights.contains(*entity)) {
+            commands.spawn((
+                DirectionalLightBundle {
+                    transform: Transform::from_xyz(1.0, 2.0, 1.5).looking_at(Vec3::ZERO, Vec3::Y),
+                    ..default()
+                },
+                WallpaperEntity,
+            ));
+        }
+
+        scene.players = entities
+            .into_iter()
+            .filter(|entity| players.contains(*entity))
+            .collect();
+        if let Some(clip) = scene.clips.first() {
+            for entity in &scene.players {
+                let mut player = players.get_mut(*entity).unwrap();
+                player.start(clip.clone()).pause();
+            }
+        }
+        scene.set_up = true;
+    }
+}
+
+/// Advances the animation players by the wallpaper clock rather than real time. They're kept
+/// paused, which makes bevy sample them only when they change.
+fn play_gltf_animations(
+    clock: Res<WallpaperClock>,
+    clips: Res<Assets<AnimationClip>>,
+    mut scenes: Query<&mut GltfScene>,
+    mut players: Query<&mut AnimationPlayer>,
+) {
+    for mut scene in &mut scenes {
+        let scene = &mut *scene;
+        if !scene.set_up || scene.clips.is_empty() {
+            continue;
+        }
+        let Some(clip) = clips.get(&scene.clips[scene.clip]) else {
+            continue;
+        };
+        if clock.delta().is_zero() {
+            continue;
+        }
+        scene.elapsed += clock.delta_seconds();
+        let mut switched = false;
+        if scene.elapsed >= clip.duration() && clip.duration() > 0.0 {
+            scene.elapsed %= clip.duration();
+            if scene.clips.len() > 1 {
+                scene.clip = (scene.clip + 1) % scene.clips.len();
+                switched = true;
+            }
+        }
+        for entity in &scene.players {
+            if let Ok(mut player) = players.get_mut(*entity) {
+                if switched {
+                    player.start(scene.clips[scene.clip].clone()).pause();
+                }
+                player.set_elapsed(scene.elapsed);
+            }
+        }
+    }
+}
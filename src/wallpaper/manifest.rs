@@ -0,0 +1,533 @@
+use std::path::{Component, Path, PathBuf};
+
+use bevy::asset::{AssetLoader, AssetPath, Error, LoadContext, LoadedAsset};
+use bevy::reflect::TypeUuid;
+use bevy::render::color::Color;
+use bevy::render::render_resource::{AddressMode, FilterMode, Shader, TextureFormat};
+use bevy::render::texture::BevyDefault;
+use bevy::utils::{BoxedFuture, HashSet};
+use serde::{Deserialize, Deserializer};
+
+use super::raymarch::{raymarch_layout, raymarch_shader, RAYMARCH_LABEL, RAYMARCH_UNIFORMS};
+use super::shader::{BUILTIN_UNIFORMS, MAX_PASSES};
+use super::{CameraRigDeclaration, RaymarchDeclaration, SimulationDeclaration};
+use crate::post_process::PostProcessDeclaration;
+use crate::shader_material::{
+    reflect_uniform_layout, reflect_workgroup_size, UniformLayout, UniformType,
+    SHADER_MATERIAL_TEXTURES,
+};
+
+/// Describes a wallpaper beyond the code or files that draw it, loaded from a `.wallpaper.ron`
+/// file:
+///
+/// ```ron
+/// (
+///     parameters: [
+///         (name: "speed", kind: Float(min: 0.0, max: 10.0, default: 2.0)),
+///         (name: "tint", kind: Color(default: "#ff8000")),
+///     ],
+///     shader: Some("plasma.wgsl"),
+/// )
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, TypeUuid)]
+#[uuid = "5b0c2a9e-3f0b-4b83-9f0e-8f1f3c7a6d42"]
+pub struct WallpaperManifest {
+    /// Knobs users can turn, in the order their values are laid out in the uniform buffer.
+    #[serde(default)]
+    pub parameters: Vec<ParameterDeclaration>,
+    /// The WGSL shader that draws a [`ShaderWallpaper`](super::ShaderWallpaper), relative to the
+    /// manifest. Once loaded this is an asset path.
+    #[serde(default)]
+    pub shader: Option<String>,
+    /// Where the layout of the shader's uniform buffer comes from.
+    #[serde(default)]
+    pub uniforms: UniformSource,
+    /// The layout of the shader's uniform buffer, filled in by the loader.
+    #[serde(skip)]
+    pub layout: Option<UniformLayout>,
+    /// What the shader's textures are bound to, in binding order.
+    #[serde(default)]
+    pub channels: Vec<ChannelDeclaration>,
+    /// Drawn into offscreen buffers before the shader each frame, in order.
+    #[serde(default)]
+    pub passes: Vec<PassDeclaration>,
+    /// Compute shaders run before the passes.
+    #[serde(default)]
+    pub simulation: Option<SimulationDeclaration>,
+    /// Makes the shader the scene of a raymarched wallpaper.
+    #[serde(default)]
+    pub raymarch: Option<RaymarchDeclaration>,
+    /// How the camera of a scene or raymarched wallpaper moves.
+    #[serde(default)]
+    pub camera: Option<CameraRigDeclaration>,
+    /// Effects applied to what the wallpaper draws.
+    #[serde(default)]
+    pub post: Option<PostProcessDeclaration>,
+    /// An image showing what the wallpaper looks like, relative to the manifest. Wallpapers
+    /// without one get previews rendered for them.
+    #[serde(default)]
+    pub preview: Option<String>,
+}
+
+/// A shader drawn into a buffer of its own, which the shaders after it can read from:
+///
+/// ```ron
+/// (
+///     name: "trail",
+///     shader: "trail.wgsl",
+///     channels: [(source: Pass("trail"))],
+///     format: Rgba16Float,
+/// )
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct PassDeclaration {
+    pub name: String,
+    /// Relative to the manifest like the main shader, and an asset path once loaded.
+    pub shader: String,
+    #[serde(default)]
+    pub channels: Vec<ChannelDeclaration>,
+    #[serde(default)]
+    pub format: BufferFormat,
+    /// Size of the buffer relative to the canvas.
+    #[serde(default = "full_scale")]
+    pub scale: f32,
+    /// The layout of the shader's uniform buffer, filled in by the loader.
+    #[serde(skip)]
+    pub layout: UniformLayout,
+}
+
+pub(super) fn full_scale() -> f32 {
+    1.0
+}
+
+/// A texture bound to one of a shader's channels, and how it's sampled:
+///
+/// ```ron
+/// (source: Image("rock.png"), filter: Linear, wrap: Repeat, mipmaps: true)
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChannelDeclaration {
+    pub source: ChannelSource,
+    #[serde(default)]
+    pub filter: ChannelFilter,
+    #[serde(default)]
+    pub wrap: ChannelWrap,
+    /// Generates mipmaps for an image or cubemap when it loads. Other sources don't have any.
+    #[serde(default)]
+    pub mipmaps: bool,
+}
+
+/// Something bound to one of a shader's textures. Paths are relative to the manifest, and asset
+/// paths once loaded.
+#[derive(Debug, Clone, Deserialize)]
+pub enum ChannelSource {
+    /// The buffer of the named pass. Passes drawn earlier in the frame are read as they were just
+    /// drawn, and the others, including the reading pass itself, as they were the frame before.
+    Pass(String),
+    /// The named texture of the simulation, as of its latest step.
+    Simulation(String),
+    Image(String),
+    /// An image with the six faces of a cube stacked on top of each other, in the order +X, -X,
+    /// +Y, -Y, +Z, -Z. Shaders bind it as a `texture_cube<f32>` instead of the channel's
+    /// `texture_2d<f32>`.
+    Cubemap(String),
+    /// A GIF, APNG or WebP, played on the wallpaper clock.
+    Animated(String),
+    /// 256 by 256 texels of uniformly distributed random values, independent in each channel.
+    Noise,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum ChannelFilter {
+    Nearest,
+    #[default]
+    Linear,
+}
+
+impl ChannelFilter {
+    pub fn filter_mode(self) -> FilterMode {
+        match self {
+            ChannelFilter::Nearest => FilterMode::Nearest,
+            ChannelFilter::Linear => FilterMode::Linear,
+        }
+    }
+}
+
+/// What a channel reads outside of its texture.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum ChannelWrap {
+    #[default]
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+impl ChannelWrap {
+    pub fn address_mode(self) -> AddressMode {
+        match self {
+            ChannelWrap::Clamp => AddressMode::ClampToEdge,
+            ChannelWrap::Repeat => AddressMode::Repeat,
+            ChannelWrap::Mirror => AddressMode::MirrorRepeat,
+        }
+    }
+}
+
+/// The texture format of a pass's buffer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum BufferFormat {
+    /// 8 bits per channel, like the canvas.
+    #[default]
+    Rgba8,
+    /// 16 bit floats per channel, for values outside `0..=1` or that build up over many frames.
+    Rgba16Float,
+}
+
+impl BufferFormat {
+    pub fn texture_format(self) -> TextureFormat {
+        match self {
+            BufferFormat::Rgba8 => TextureFormat::bevy_default(),
+            BufferFormat::Rgba16Float => TextureFormat::Rgba16Float,
+        }
+    }
+}
+
+/// How the fields of a shader wallpaper's uniform buffer are laid out.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum UniformSource {
+    /// Whatever the struct the shader declares says, read from the shader source.
+    #[default]
+    Reflect,
+    /// The built in uniforms, `time: f32`, `frame: u32` and `resolution: vec2<f32>`, followed by
+    /// the parameters in the order they're declared. For shaders that can't be reflected.
+    Declared,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ParameterDeclaration {
+    pub name: String,
+    pub kind: ParameterKind,
+}
+
+/// The type of a parameter, with its default value.
+///
+/// In the uniform buffer floats are `f32`, colors are linear RGBA `vec4<f32>`, and booleans and
+/// enums are `u32`, the latter holding the index of the option. Textures are bound separately.
+#[derive(Debug, Clone, Deserialize)]
+pub enum ParameterKind {
+    Float {
+        min: f32,
+        max: f32,
+        default: f32,
+    },
+    Color {
+        /// A hex color like `"#ff8000"`.
+        #[serde(deserialize_with = "hex_color")]
+        default: Color,
+    },
+    Bool {
+        default: bool,
+    },
+    Enum {
+        options: Vec<String>,
+        default: String,
+    },
+    Texture {
+        /// Asset path of the image to use when none is set.
+        #[serde(default)]
+        default: Option<String>,
+    },
+}
+
+impl ParameterKind {
+    /// The type of the parameter's field in a uniform buffer, if it has one.
+    pub fn uniform_type(&self) -> Option<UniformType> {
+        match self {
+            ParameterKind::Float { .. } => Some(UniformType::F32),
+            ParameterKind::Color { .. } => Some(UniformType::Vec4),
+            ParameterKind::Bool { .. } | ParameterKind::Enum { .. } => Some(UniformType::U32),
+            ParameterKind::Texture { .. } => None,
+        }
+    }
+}
+
+pub fn parse_hex_color(value: &str) -> Result<Color, String> {
+    Color::hex(value.trim_start_matches('#')).map_err(|_| format!("invalid color {value:?}"))
+}
+
+pub(super) fn hex_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_hex_color(&value).map_err(serde::de::Error::custom)
+}
+
+impl WallpaperManifest {
+    /// The files the manifest refers to, as written in it, relative to the manifest.
+    pub fn files(&self) -> Vec<&str> {
+        let shaders = self
+            .shader
+            .iter()
+            .map(String::as_str)
+            .chain(self.passes.iter().map(|pass| pass.shader.as_str()))
+            .chain(
+                self.simulation
+                    .iter()
+                    .map(|simulation| simulation.shader.as_str()),
+            );
+        let channels = self
+            .passes
+            .iter()
+            .flat_map(|pass| &pass.channels)
+            .chain(&self.channels)
+            .filter_map(|channel| match &channel.source {
+                ChannelSource::Image(path)
+                | ChannelSource::Cubemap(path)
+                | ChannelSource::Animated(path) => Some(path.as_str()),
+                _ => None,
+            });
+        shaders.chain(channels).collect()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let mut names = HashSet::new();
+        for parameter in &self.parameters {
+            if !names.insert(parameter.name.as_str()) {
+                return Err(format!("parameter {:?} is declared twice", parameter.name));
+            }
+            match &parameter.kind {
+                ParameterKind::Float { min, max, default } if !(*min..=*max).contains(default) => {
+                    return Err(format!(
+                        "default of {:?} is outside of {min}..={max}",
+                        parameter.name
+                    ));
+                }
+                ParameterKind::Enum { options, default } if !options.contains(default) => {
+                    return Err(format!(
+                        "default of {:?} is not one of its options",
+                        parameter.name
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        if !self.passes.is_empty() && self.shader.is_none() {
+            return Err("passes need a shader to draw their buffers to the canvas".to_string());
+        }
+        if self.passes.len() > MAX_PASSES {
+            return Err(format!("there can be at most {MAX_PASSES} passes"));
+        }
+        let mut names = HashSet::new();
+        for pass in &self.passes {
+            if !names.insert(pass.name.as_str()) {
+                return Err(format!("pass {:?} is declared twice", pass.name));
+            }
+            if !(pass.scale.is_finite() && pass.scale > 0.0) {
+                return Err(format!("scale of pass {:?} isn't positive", pass.name));
+            }
+        }
+        let mut textures = HashSet::new();
+        if let Some(simulation) = &self.simulation {
+            if self.shader.is_none() {
+                return Err("a simulation needs a shader to draw its textures".to_string());
+            }
+            simulation.validate()?;
+            textures.extend(
+                simulation
+                    .textures
+                    .iter()
+                    .map(|texture| texture.name.as_str()),
+            );
+        }
+        if let Some(raymarch) = &self.raymarch {
+            if self.shader.is_none() {
+                return Err("raymarching needs a shader with the scene to draw".to_string());
+            }
+            if let Some(parameter) = self.parameters.iter().find(|parameter| {
+                BUILTIN_UNIFORMS
+                    .iter()
+                    .chain(RAYMARCH_UNIFORMS)
+                    .any(|(name, _)| parameter.name == *name)
+            }) {
+                return Err(format!(
+                    "parameter {:?} has the name of a raymarching uniform",
+                    parameter.name
+                ));
+            }
+            raymarch.validate()?;
+        }
+        if let Some(camera) = &self.camera {
+            camera.validate()?;
+        }
+        if let Some(post) = &self.post {
+            post.validate()?;
+        }
+        let channel_lists = self
+            .passes
+            .iter()
+            .map(|pass| &pass.channels)
+            .chain([&self.channels]);
+        for channels in channel_lists {
+            if channels.len() > SHADER_MATERIAL_TEXTURES {
+                return Err(format!(
+                    "shaders can have at most {SHADER_MATERIAL_TEXTURES} channels"
+                ));
+            }
+            for channel in channels {
+                match &channel.source {
+                    ChannelSource::Pass(name) if !names.contains(name.as_str()) => {
+                        return Err(format!("there is no pass called {name:?}"));
+                    }
+                    ChannelSource::Simulation(name) if !textures.contains(name.as_str()) => {
+                        return Err(format!("the simulation has no texture called {name:?}"));
+                    }
+                    ChannelSource::Animated(path) if channel.mipmaps => {
+                        return Err(format!("animated image {path:?} can't have mipmaps"));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The channels of every pass and of the shader.
+    pub fn channels(&self) -> impl Iterator<Item = &ChannelDeclaration> {
+        self.passes
+            .iter()
+            .flat_map(|pass| &pass.channels)
+            .chain(&self.channels)
+    }
+
+    fn declared_layout(&self) -> UniformLayout {
+        let builtins = BUILTIN_UNIFORMS
+            .iter()
+            .map(|(name, ty)| (name.to_string(), *ty));
+        let parameters = self.parameters.iter().filter_map(|parameter| {
+            let ty = parameter.kind.uniform_type()?;
+            Some((parameter.name.clone(), ty))
+        });
+        UniformLayout::sequential(builtins.chain(parameters))
+    }
+}
+
+/// The path of `path`, relative to the folder `manifest` is in.
+fn relative_to(manifest: &Path, path: &str) -> PathBuf {
+    let mut resolved = manifest.parent().map(Path::to_owned).unwrap_or_default();
+    for component in Path::new(path).components() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::CurDir => {}
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+/// The layout of the uniforms `source` declares in `group`, according to the manifest's
+/// [`UniformSource`].
+fn shader_layout(
+    manifest: &WallpaperManifest,
+    path: &Path,
+    source: &str,
+    group: u32,
+) -> Result<UniformLayout, Error> {
+    match manifest.uniforms {
+        UniformSource::Declared => Ok(manifest.declared_layout()),
+        UniformSource::Reflect => reflect_uniform_layout(source, group, 0).map_err(|err| {
+            Error::msg(format!(
+                "can't reflect the uniforms of {}: {err}",
+                path.display()
+            ))
+        }),
+    }
+}
+
+#[derive(Default)]
+pub struct WallpaperManifestLoader;
+
+impl AssetLoader for WallpaperManifestLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let mut manifest: WallpaperManifest = ron::de::from_bytes(bytes)?;
+            manifest.validate().map_err(Error::msg)?;
+            let mut dependencies = Vec::new();
+            if let Some(simulation) = &manifest.simulation {
+                let shader = relative_to(load_context.path(), &simulation.shader);
+                let source = String::from_utf8(load_context.read_asset_bytes(&shader).await?)?;
+                let layout = shader_layout(&manifest, &shader, &source, 0)?;
+                let simulation = manifest.simulation.as_mut().unwrap();
+                for step in &mut simulation.steps {
+                    step.workgroup_size = reflect_workgroup_size(&source, &step.entry_point)
+                        .map_err(|err| Error::msg(format!("{}: {err}", shader.display())))?;
+                }
+                simulation.shader = shader.to_string_lossy().into_owned();
+                simulation.layout = layout;
+                dependencies.push(AssetPath::new(shader, None));
+            }
+            for index in 0..manifest.passes.len() {
+                let shader = relative_to(load_context.path(), &manifest.passes[index].shader);
+                let source = String::from_utf8(load_context.read_asset_bytes(&shader).await?)?;
+                let layout = shader_layout(&manifest, &shader, &source, 1)?;
+                let pass = &mut manifest.passes[index];
+                pass.shader = shader.to_string_lossy().into_owned();
+                pass.layout = layout;
+                dependencies.push(AssetPath::new(shader, None));
+            }
+            let channels = manifest
+                .passes
+                .iter_mut()
+                .flat_map(|pass| &mut pass.channels)
+                .chain(&mut manifest.channels);
+            for channel in channels {
+                if let ChannelSource::Image(path)
+                | ChannelSource::Cubemap(path)
+                | ChannelSource::Animated(path) = &mut channel.source
+                {
+                    let resolved = relative_to(load_context.path(), path);
+                    *path = resolved.to_string_lossy().into_owned();
+                    dependencies.push(AssetPath::new(resolved, None));
+                }
+            }
+            if let Some(shader) = &manifest.shader {
+                let shader = relative_to(load_context.path(), shader);
+                let source = String::from_utf8(load_context.read_asset_bytes(&shader).await?)?;
+                if manifest.raymarch.is_some() {
+                    // The scene isn't a shader by itself, so the one it's wrapped into comes
+                    // with the manifest.
+                    let source = raymarch_shader(&manifest, &source);
+                    load_context.set_labeled_asset(
+                        RAYMARCH_LABEL,
+                        LoadedAsset::new(Shader::from_wgsl(source)),
+                    );
+                    manifest.layout = Some(raymarch_layout(&manifest));
+                    manifest.shader = Some(format!(
+                        "{}#{RAYMARCH_LABEL}",
+                        load_context.path().to_string_lossy()
+                    ));
+                } else {
+                    manifest.layout = Some(shader_layout(&manifest, &shader, &source, 1)?);
+                    manifest.shader = Some(shader.to_string_lossy().into_owned());
+                    dependencies.push(AssetPath::new(shader, None));
+                }
+            }
+            if let Some(preview) = &mut manifest.preview {
+                *preview = relative_to(load_context.path(), preview)
+                    .to_string_lossy()
+                    .into_owned();
+            }
+            // Starts loading the shaders along with the manifest.
+            load_context
+                .set_default_asset(LoadedAsset::new(manifest).with_dependencies(dependencies));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["wallpaper.ron"]
+    }
+}
@@ -0,0 +1,297 @@
+mod animated_image;
+mod camera;
+mod channels;
+mod gltf;
+mod manifest;
+mod raymarch;
+mod shader;
+mod simulation;
+mod static_image;
+mod video;
+mod wic;
+
+pub use animated_image::AnimatedImagePlugin;
+pub use camera::{
+    Breathing, CameraKeyframe, CameraMotion, CameraRig, CameraRigDeclaration, CameraRigPlugin,
+    Easing, MoveCameraRigs, Parallax,
+};
+pub use gltf::*;
+pub use manifest::*;
+pub use raymarch::{
+    CameraDeclaration, FogDeclaration, LightDeclaration, RaymarchCamera, RaymarchDeclaration,
+    RAYMARCH_UNIFORMS,
+};
+pub use shader::*;
+pub use simulation::{
+    AdvanceSimulations, SimulationDeclaration, SimulationPlugin, SimulationStep,
+    StorageBufferDeclaration, StorageFormat, StorageTextureDeclaration, SIMULATION_NODE,
+};
+pub use static_image::*;
+pub use video::*;
+
+use bevy::app::{App, CoreStage, Plugin};
+use bevy::asset::{AddAsset, AssetServer, Handle};
+use bevy::ecs::event::ManualEventReader;
+use bevy::ecs::prelude::*;
+use bevy::ecs::system::BoxedSystem;
+use bevy::hierarchy::despawn_with_children_recursive;
+use bevy::render::view::RenderLayers;
+use bevy::utils::{tracing::warn, Duration, HashMap, HashSet};
+
+use crate::transition::Transition;
+use crate::wallpaper_render_plugin::{UpdateMode, WinitSettings};
+
+#[derive(Default)]
+pub struct WallpaperPlugin;
+
+impl Plugin for WallpaperPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WallpaperRegistry>()
+            .add_asset::<WallpaperManifest>()
+            .init_asset_loader::<WallpaperManifestLoader>()
+            .add_event::<SwitchWallpaper>()
+            .add_event::<WallpaperSwitched>()
+            .add_plugin(StaticImagePlugin)
+            .add_plugin(AnimatedImagePlugin)
+            .add_plugin(VideoPlugin)
+            .add_plugin(ShaderWallpaperPlugin)
+            .add_plugin(GltfWallpaperPlugin)
+            .add_plugin(CameraRigPlugin)
+            .add_plugin(SimulationPlugin)
+            .add_system_to_stage(CoreStage::PreUpdate, apply_wallpaper_switch.at_end());
+    }
+}
+
+/// Marks every entity spawned by the active wallpaper so that it can be torn down when another
+/// wallpaper is switched in.
+#[derive(Component)]
+pub struct WallpaperEntity;
+
+/// Marks the entities of the wallpaper being switched away from, which keep rendering to the
+/// outgoing canvas until the transition to the new one is over, and are despawned then.
+///
+/// They're moved to render layers of their own so that neither wallpaper's cameras see the other's
+/// entities. Lights aren't kept apart by render layers, so for the length of a transition between
+/// two scenes each is also lit by the other's lights.
+#[derive(Component)]
+pub struct OutgoingWallpaperEntity;
+
+/// Something that can be spawned into the world as the active wallpaper.
+pub enum Wallpaper {
+    /// A scene spawned by a system, the same way a startup system would set it up.
+    Scene(BoxedSystem),
+    /// A still or animated image laid out on the canvas.
+    Image(ImageWallpaper),
+    /// A video laid out on the canvas like an image.
+    Video(VideoWallpaper),
+    /// A fragment shader covering the canvas, described by a manifest.
+    Shader(ShaderWallpaper),
+    /// A glTF scene, with its camera and animations.
+    Gltf(GltfWallpaper),
+}
+
+impl Wallpaper {
+    pub fn scene<Params>(system: impl IntoSystem<(), (), Params>) -> Self {
+        Wallpaper::Scene(Box::new(IntoSystem::into_system(system)))
+    }
+
+    fn spawn(&mut self, world: &mut World) {
+        match self {
+            Wallpaper::Scene(system) => {
+                system.initialize(world);
+                system.run((), world);
+                system.apply_buffers(world);
+            }
+            Wallpaper::Image(image) => image.spawn(world),
+            Wallpaper::Video(video) => video.spawn(world),
+            Wallpaper::Shader(shader) => shader.spawn(world),
+            Wallpaper::Gltf(gltf) => gltf.spawn(world),
+        }
+    }
+
+    /// How often the event loop needs to update while this wallpaper is showing. Images and
+    /// videos only change when something asks for an update, like their next frame being due.
+    fn update_mode(&self) -> UpdateMode {
+        match self {
+            Wallpaper::Scene(_) | Wallpaper::Shader(_) | Wallpaper::Gltf(_) => {
+                UpdateMode::Continuous
+            }
+            Wallpaper::Image(_) | Wallpaper::Video(_) => UpdateMode::Reactive {
+                max_wait: Duration::from_secs(1),
+            },
+        }
+    }
+}
+
+/// All wallpapers known to the app, by name.
+#[derive(Default, Resource)]
+pub struct WallpaperRegistry {
+    wallpapers: HashMap<String, Wallpaper>,
+    /// Asset paths of the wallpapers' manifests, for the ones that have one.
+    manifests: HashMap<String, String>,
+}
+
+impl WallpaperRegistry {
+    pub fn insert(&mut self, name: impl Into<String>, wallpaper: Wallpaper) {
+        let name = name.into();
+        if let Wallpaper::Shader(shader) = &wallpaper {
+            self.manifests.insert(name.clone(), shader.manifest.clone());
+        }
+        self.wallpapers.insert(name, wallpaper);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.wallpapers.contains_key(name)
+    }
+
+    /// The names of the wallpapers that have a manifest, with its asset path.
+    pub fn manifests(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.manifests
+            .iter()
+            .map(|(name, path)| (name.as_str(), path.as_str()))
+    }
+
+    pub fn set_manifest(&mut self, name: impl Into<String>, path: impl Into<String>) {
+        self.manifests.insert(name.into(), path.into());
+    }
+
+    /// Starts loading the manifest of the named wallpaper, if it has one.
+    pub fn load_manifest(
+        &self,
+        name: &str,
+        asset_server: &AssetServer,
+    ) -> Option<Handle<WallpaperManifest>> {
+        let path = self.manifests.get(name)?;
+        Some(asset_server.load(path.as_str()))
+    }
+}
+
+/// Tears down the active wallpaper and spawns the named one in its place.
+#[derive(Debug, Clone)]
+pub struct SwitchWallpaper {
+    pub name: String,
+    /// How to get from the outgoing wallpaper to this one, or a hard cut if `None`.
+    pub transition: Option<Transition>,
+}
+
+/// Sent once the wallpaper requested by a [`SwitchWallpaper`] has been spawned.
+#[derive(Debug, Clone)]
+pub struct WallpaperSwitched {
+    pub name: String,
+    pub transition: Option<Transition>,
+}
+
+pub trait WallpaperAppExt {
+    fn add_wallpaper(&mut self, name: impl Into<String>, wallpaper: Wallpaper) -> &mut Self;
+
+    /// Describes the named wallpaper with the [`WallpaperManifest`] at the given asset path.
+    fn add_wallpaper_manifest(
+        &mut self,
+        name: impl Into<String>,
+        path: impl Into<String>,
+    ) -> &mut Self;
+}
+
+impl WallpaperAppExt for App {
+    fn add_wallpaper(&mut self, name: impl Into<String>, wallpaper: Wallpaper) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(WallpaperRegistry::default)
+            .insert(name, wallpaper);
+        self
+    }
+
+    fn add_wallpaper_manifest(
+        &mut self,
+        name: impl Into<String>,
+        path: impl Into<String>,
+    ) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(WallpaperRegistry::default)
+            .set_manifest(name, path);
+        self
+    }
+}
+
+fn apply_wallpaper_switch(
+    world: &mut World,
+    mut reader: Local<ManualEventReader<SwitchWallpaper>>,
+) {
+    let events = world.resource::<Events<SwitchWallpaper>>();
+    let Some(SwitchWallpaper { name, transition }) = reader.iter(events).last().cloned() else {
+        return;
+    };
+
+    world.resource_scope(|world, mut registry: Mut<WallpaperRegistry>| {
+        let Some(wallpaper) = registry.wallpapers.get_mut(&name) else {
+            warn!("Tried to switch to unknown wallpaper {:?}", name);
+            return;
+        };
+
+        // Only one wallpaper is kept around, so this cuts short any transition still going.
+        despawn_tagged::<OutgoingWallpaperEntity>(world);
+        if transition.is_some() {
+            retire_wallpaper(world);
+        } else {
+            despawn_tagged::<WallpaperEntity>(world);
+        }
+
+        let existing: HashSet<Entity> = world.iter_entities().collect();
+        wallpaper.spawn(world);
+        let spawned: Vec<Entity> = world
+            .iter_entities()
+            .filter(|entity| !existing.contains(entity))
+            .collect();
+        for entity in spawned {
+            world.entity_mut(entity).insert(WallpaperEntity);
+        }
+
+        let update_mode = wallpaper.update_mode();
+        if let Some(mut settings) = world.get_resource_mut::<WinitSettings>() {
+            settings.focused_mode = update_mode;
+            settings.unfocused_mode = update_mode;
+        }
+
+        world.send_event(WallpaperSwitched { name, transition });
+    });
+}
+
+/// Despawns everything the active wallpaper spawned, and what's left of the one before it.
+pub fn despawn_wallpaper(world: &mut World) {
+    despawn_tagged::<WallpaperEntity>(world);
+    despawn_tagged::<OutgoingWallpaperEntity>(world);
+}
+
+fn despawn_tagged<T: Component>(world: &mut World) {
+    let entities: Vec<Entity> = world
+        .query_filtered::<Entity, With<T>>()
+        .iter(world)
+        .collect();
+    for entity in entities {
+        // Children are tagged as well, so they may already be gone by the time we get to them.
+        if world.get_entity(entity).is_some() {
+            despawn_with_children_recursive(world, entity);
+        }
+    }
+}
+
+/// Turns the active wallpaper's entities into [`OutgoingWallpaperEntity`]s. Its cameras keep the
+/// canvas image they draw to, which becomes the outgoing one once the transition begins.
+fn retire_wallpaper(world: &mut World) {
+    let entities: Vec<(Entity, Option<RenderLayers>)> = world
+        .query_filtered::<(Entity, Option<&RenderLayers>), With<WallpaperEntity>>()
+        .iter(world)
+        .map(|(entity, layers)| (entity, layers.copied()))
+        .collect();
+    for (entity, layers) in entities {
+        let layers = layers
+            .unwrap_or_default()
+            .iter()
+            .fold(RenderLayers::none(), |outgoing, layer| {
+                outgoing.with(outgoing_layer(layer))
+            });
+        world
+            .entity_mut(entity)
+            .remove::<WallpaperEntity>()
+            .insert((OutgoingWallpaperEntity, layers));
+    }
+}
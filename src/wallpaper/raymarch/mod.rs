@@ -0,0 +1,310 @@
+use std::fmt::Write;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::manifest::hex_color;
+use super::shader::BUILTIN_UNIFORMS;
+use super::WallpaperManifest;
+use crate::quality::Quality;
+use crate::shader_material::{UniformLayout, UniformType, UniformValue};
+
+/// Label of the shader a raymarched wallpaper's manifest is loaded with.
+pub(super) const RAYMARCH_LABEL: &str = "raymarch";
+
+/// Uniforms a raymarched wallpaper's shader gets between the built in ones and its parameters.
+pub const RAYMARCH_UNIFORMS: &[(&str, UniformType)] = &[
+    ("camera_position", UniformType::Vec3),
+    // How far the image plane is in front of the camera, with the canvas 2 units high.
+    ("camera_zoom", UniformType::F32),
+    ("camera_right", UniformType::Vec3),
+    ("camera_up", UniformType::Vec3),
+    ("camera_forward", UniformType::Vec3),
+    // The way the light travels.
+    ("light_direction", UniformType::Vec3),
+    ("light_color", UniformType::Vec4),
+    ("ambient_color", UniformType::Vec4),
+    ("fog_color", UniformType::Vec4),
+    ("fog_density", UniformType::F32),
+    ("max_distance", UniformType::F32),
+    ("max_steps", UniformType::U32),
+    ("shadow_steps", UniformType::U32),
+    ("shadow_hardness", UniformType::F32),
+    ("occlusion_samples", UniformType::U32),
+    // How many pixels off a hit can be.
+    ("pixel_precision", UniformType::F32),
+];
+
+const TEMPLATE: &str = include_str!("template.wgsl");
+
+/// Turns the shader into a scene raymarched from a camera, lit, shadowed and fogged by the
+/// wallpaper:
+///
+/// ```ron
+/// shader: Some("../shaders/blobs.wgsl"),
+/// raymarch: Some((
+///     camera: (position: (0.0, 1.0, 4.0), target: (0.0, 0.0, 0.0), fov: 50.0),
+///     light: (direction: (-1.0, -2.0, -1.0), color: "#fff4e0"),
+///     fog: (color: "#101420", density: 0.04),
+/// )),
+/// ```
+///
+/// The shader only defines the scene, as `fn map(p: vec3<f32>) -> f32`, the signed distance to
+/// it, and `fn material(p: vec3<f32>, normal: vec3<f32>) -> vec3<f32>`, its color where a ray
+/// hits it. It reads the built in uniforms and its parameters from `uniforms`, and declares the
+/// textures of its channels like any other shader. How many steps rays take, shadows and ambient
+/// occlusion follow the [`Quality`](crate::quality::Quality) preset.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RaymarchDeclaration {
+    pub camera: CameraDeclaration,
+    pub light: LightDeclaration,
+    #[serde(deserialize_with = "hex_color")]
+    pub ambient: Color,
+    pub fog: FogDeclaration,
+    /// How far rays go before they count as having missed.
+    pub max_distance: f32,
+    /// The most steps rays take, on high quality and up, with fewer on lower ones.
+    pub max_steps: u32,
+    /// How sharp the edges of shadows are.
+    pub shadow_hardness: f32,
+}
+
+impl Default for RaymarchDeclaration {
+    fn default() -> Self {
+        RaymarchDeclaration {
+            camera: CameraDeclaration::default(),
+            light: LightDeclaration::default(),
+            ambient: Color::rgb(0.1, 0.1, 0.12),
+            fog: FogDeclaration::default(),
+            max_distance: 50.0,
+            max_steps: 160,
+            shadow_hardness: 8.0,
+        }
+    }
+}
+
+impl RaymarchDeclaration {
+    pub(super) fn validate(&self) -> Result<(), String> {
+        let camera = &self.camera;
+        let forward = Vec3::from(camera.target) - Vec3::from(camera.position);
+        if forward.normalize_or_zero().cross(Vec3::Y).length_squared() < 1e-6 {
+            return Err("the camera can't look straight up or down, or at itself".to_string());
+        }
+        if !(camera.fov > 0.0 && camera.fov < 180.0) {
+            return Err("the camera's fov isn't between 0 and 180 degrees".to_string());
+        }
+        if Vec3::from(self.light.direction).length_squared() == 0.0 {
+            return Err("the light has no direction".to_string());
+        }
+        if !(self.max_distance.is_finite() && self.max_distance > 0.0) {
+            return Err("max_distance isn't positive".to_string());
+        }
+        if self.max_steps == 0 {
+            return Err("max_steps is 0".to_string());
+        }
+        if !(self.fog.density >= 0.0 && self.shadow_hardness > 0.0) {
+            return Err(
+                "fog density can't be negative, and shadow hardness has to be positive".to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CameraDeclaration {
+    pub position: (f32, f32, f32),
+    pub target: (f32, f32, f32),
+    /// Vertical field of view in degrees.
+    pub fov: f32,
+}
+
+impl Default for CameraDeclaration {
+    fn default() -> Self {
+        CameraDeclaration {
+            position: (0.0, 1.0, 5.0),
+            target: (0.0, 0.0, 0.0),
+            fov: 50.0,
+        }
+    }
+}
+
+impl CameraDeclaration {
+    pub fn transform(&self) -> Transform {
+        Transform::from_translation(self.position.into()).looking_at(self.target.into(), Vec3::Y)
+    }
+}
+
+/// A light infinitely far away, like the sun.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LightDeclaration {
+    /// The way the light travels.
+    pub direction: (f32, f32, f32),
+    #[serde(deserialize_with = "hex_color")]
+    pub color: Color,
+}
+
+impl Default for LightDeclaration {
+    fn default() -> Self {
+        LightDeclaration {
+            direction: (-1.0, -2.0, -1.0),
+            color: Color::WHITE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FogDeclaration {
+    /// Also what rays that miss the scene see.
+    #[serde(deserialize_with = "hex_color")]
+    pub color: Color,
+    /// How much of the light is lost to fog per unit travelled. No fog at 0.
+    pub density: f32,
+}
+
+impl Default for FogDeclaration {
+    fn default() -> Self {
+        FogDeclaration {
+            color: Color::BLACK,
+            density: 0.0,
+        }
+    }
+}
+
+/// The camera a raymarched wallpaper looks through. Its transform is read every frame, so
+/// anything moving it moves the view.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct RaymarchCamera {
+    /// Vertical field of view in radians.
+    pub fov: f32,
+}
+
+/// How much work rays do at a [`Quality`] preset.
+struct RaymarchQuality {
+    /// Of the steps the manifest allows.
+    steps: f32,
+    /// Leaves shadows out when 0.
+    shadow_steps: u32,
+    occlusion_samples: u32,
+    pixel_precision: f32,
+}
+
+impl RaymarchQuality {
+    fn new(quality: Quality) -> Self {
+        match quality {
+            Quality::Low => RaymarchQuality {
+                steps: 0.4,
+                shadow_steps: 0,
+                occlusion_samples: 0,
+                pixel_precision: 3.0,
+            },
+            Quality::Medium => RaymarchQuality {
+                steps: 0.7,
+                shadow_steps: 24,
+                occlusion_samples: 3,
+                pixel_precision: 1.5,
+            },
+            Quality::High => RaymarchQuality {
+                steps: 1.0,
+                shadow_steps: 64,
+                occlusion_samples: 5,
+                pixel_precision: 1.0,
+            },
+            Quality::Ultra => RaymarchQuality {
+                steps: 1.0,
+                shadow_steps: 128,
+                occlusion_samples: 8,
+                pixel_precision: 0.5,
+            },
+        }
+    }
+}
+
+fn uniform_fields(
+    manifest: &WallpaperManifest,
+) -> impl Iterator<Item = (String, UniformType)> + '_ {
+    let parameters = manifest.parameters.iter().filter_map(|parameter| {
+        let ty = parameter.kind.uniform_type()?;
+        Some((parameter.name.clone(), ty))
+    });
+    BUILTIN_UNIFORMS
+        .iter()
+        .chain(RAYMARCH_UNIFORMS)
+        .map(|(name, ty)| (name.to_string(), *ty))
+        .chain(parameters)
+}
+
+/// The layout of the uniforms [`raymarch_shader`] declares.
+pub(super) fn raymarch_layout(manifest: &WallpaperManifest) -> UniformLayout {
+    UniformLayout::sequential(uniform_fields(manifest))
+}
+
+/// Wraps the `map` and `material` functions of `scene` into a fragment shader, after a uniform
+/// struct holding the built in uniforms, the [`RAYMARCH_UNIFORMS`] and the parameters.
+pub(super) fn raymarch_shader(manifest: &WallpaperManifest, scene: &str) -> String {
+    let mut source = String::from("struct Uniforms {\n");
+    for (name, ty) in uniform_fields(manifest) {
+        writeln!(source, "    {name}: {},", ty.wgsl_type()).unwrap();
+    }
+    source.push_str("};\n\n@group(1) @binding(0)\nvar<uniform> uniforms: Uniforms;\n\n");
+    source.push_str(scene);
+    source.push('\n');
+    source.push_str(TEMPLATE);
+    source
+}
+
+/// Writes the [`RAYMARCH_UNIFORMS`] for a camera at `transform`.
+pub(super) fn write_raymarch_uniforms(
+    declaration: &RaymarchDeclaration,
+    layout: &UniformLayout,
+    uniforms: &mut [u8],
+    transform: &Transform,
+    camera: RaymarchCamera,
+    quality: Quality,
+) {
+    let quality = RaymarchQuality::new(quality);
+    let steps = (declaration.max_steps as f32 * quality.steps)
+        .round()
+        .max(1.0) as u32;
+    let color = |color: Color| UniformValue::Vec4(color.as_linear_rgba_f32().into());
+    let values = [
+        ("camera_position", UniformValue::Vec3(transform.translation)),
+        (
+            "camera_zoom",
+            UniformValue::F32((camera.fov / 2.0).tan().recip()),
+        ),
+        ("camera_right", UniformValue::Vec3(transform.right())),
+        ("camera_up", UniformValue::Vec3(transform.up())),
+        ("camera_forward", UniformValue::Vec3(transform.forward())),
+        (
+            "light_direction",
+            UniformValue::Vec3(Vec3::from(declaration.light.direction)),
+        ),
+        ("light_color", color(declaration.light.color)),
+        ("ambient_color", color(declaration.ambient)),
+        ("fog_color", color(declaration.fog.color)),
+        ("fog_density", UniformValue::F32(declaration.fog.density)),
+        ("max_distance", UniformValue::F32(declaration.max_distance)),
+        ("max_steps", UniformValue::U32(steps)),
+        ("shadow_steps", UniformValue::U32(quality.shadow_steps)),
+        (
+            "shadow_hardness",
+            UniformValue::F32(declaration.shadow_hardness),
+        ),
+        (
+            "occlusion_samples",
+            UniformValue::U32(quality.occlusion_samples),
+        ),
+        (
+            "pixel_precision",
+            UniformValue::F32(quality.pixel_precision),
+        ),
+    ];
+    for (name, value) in values {
+        layout.write(uniforms, name, value);
+    }
+}
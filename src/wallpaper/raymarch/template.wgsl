@@ -0,0 +1,95 @@
+// The rest of a raymarched wallpaper's shader, following the uniforms and the scene's `map` and
+// `material`.
+
+struct FragmentInput {
+    #import bevy_sprite::mesh2d_vertex_output
+};
+
+// How far along the ray the scene is hit, or -1 if it's missed. `pixel_size` is the size of a
+// pixel one unit away from the camera: the further the ray goes the bigger pixels get, and the
+// less precisely the surface needs to be found.
+fn raymarch_march(origin: vec3<f32>, direction: vec3<f32>, pixel_size: f32) -> f32 {
+    var t = 0.0;
+    for (var i = 0u; i < uniforms.max_steps; i += 1u) {
+        let d = map(origin + direction * t);
+        if (d < max(uniforms.pixel_precision * pixel_size * t, 0.0001)) {
+            return t;
+        }
+        t += d;
+        if (t > uniforms.max_distance) {
+            break;
+        }
+    }
+    return -1.0;
+}
+
+fn raymarch_normal(p: vec3<f32>, epsilon: f32) -> vec3<f32> {
+    let k = vec2<f32>(1.0, -1.0);
+    return normalize(
+        k.xyy * map(p + k.xyy * epsilon) + k.yyx * map(p + k.yyx * epsilon)
+            + k.yxy * map(p + k.yxy * epsilon) + k.xxx * map(p + k.xxx * epsilon)
+    );
+}
+
+// How much light gets from the light to `origin`, softened by how closely the shadow ray passes
+// by the scene: https://iquilezles.org/articles/rmshadows/
+fn raymarch_shadow(origin: vec3<f32>, to_light: vec3<f32>) -> f32 {
+    var light = 1.0;
+    var t = 0.02;
+    for (var i = 0u; i < uniforms.shadow_steps; i += 1u) {
+        let d = map(origin + to_light * t);
+        light = min(light, uniforms.shadow_hardness * d / t);
+        t += clamp(d, 0.02, 0.5);
+        if (light < 0.001 || t > uniforms.max_distance) {
+            break;
+        }
+    }
+    return clamp(light, 0.0, 1.0);
+}
+
+// How much of the ambient light reaches `p`, from how close the scene is along the normal.
+fn raymarch_occlusion(p: vec3<f32>, normal: vec3<f32>) -> f32 {
+    var occlusion = 0.0;
+    var weight = 1.0;
+    let samples = f32(uniforms.occlusion_samples);
+    for (var i = 0u; i < uniforms.occlusion_samples; i += 1u) {
+        let h = 0.01 + 0.15 * f32(i) / max(samples - 1.0, 1.0);
+        occlusion += (h - map(p + normal * h)) * weight;
+        weight *= 0.85;
+    }
+    return clamp(1.0 - 3.0 * occlusion / max(samples, 1.0), 0.0, 1.0);
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    let aspect = uniforms.resolution.x / max(uniforms.resolution.y, 1.0);
+    // UVs go down the canvas, the camera's up vector up.
+    let screen = vec2<f32>((in.uv.x * 2.0 - 1.0) * aspect, 1.0 - in.uv.y * 2.0);
+    let direction = normalize(
+        uniforms.camera_forward * uniforms.camera_zoom + uniforms.camera_right * screen.x
+            + uniforms.camera_up * screen.y
+    );
+    let pixel_size = 2.0 / (uniforms.camera_zoom * max(uniforms.resolution.y, 1.0));
+    let origin = uniforms.camera_position;
+
+    let fog = uniforms.fog_color.rgb;
+    let t = raymarch_march(origin, direction, pixel_size);
+    if (t < 0.0) {
+        return vec4<f32>(fog, 1.0);
+    }
+
+    let p = origin + direction * t;
+    let normal = raymarch_normal(p, max(pixel_size * t, 0.0005));
+    let albedo = material(p, normal);
+    let to_light = -normalize(uniforms.light_direction);
+    var lit = max(dot(normal, to_light), 0.0);
+    if (lit > 0.0 && uniforms.shadow_steps > 0u) {
+        lit *= raymarch_shadow(p + normal * 0.01, to_light);
+    }
+    let occlusion = raymarch_occlusion(p, normal);
+    let specular = pow(max(dot(normal, normalize(to_light - direction)), 0.0), 32.0) * lit;
+    let color = albedo * (uniforms.light_color.rgb * lit + uniforms.ambient_color.rgb * occlusion)
+        + uniforms.light_color.rgb * specular * 0.25;
+    let fogged = mix(color, fog, 1.0 - exp(-uniforms.fog_density * t));
+    return vec4<f32>(fogged, 1.0);
+}
@@ -0,0 +1,631 @@
+use std::path::Path;
+
+use bevy::core_pipeline::clear_color::ClearColorConfig;
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::render_resource::{Extent3d, TextureDescriptor, TextureDimension, TextureUsages};
+use bevy::render::view::RenderLayers;
+use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+use bevy::utils::{tracing::warn, HashMap, HashSet};
+
+use super::animated_image::{is_png, ImageAnimation, PngAnimations, ANIMATION_LABEL};
+use super::channels::{
+    generate_mipmaps, is_cubemap, needs_mipmaps, noise_image, stack_cubemap, NOISE_TEXTURE,
+};
+use super::raymarch::{write_raymarch_uniforms, RaymarchCamera};
+use super::simulation::{AdvanceSimulations, Simulation};
+use super::{
+    BufferFormat, ChannelDeclaration, ChannelSource, MoveCameraRigs, PassDeclaration,
+    WallpaperEntity, WallpaperManifest,
+};
+use crate::canvas::{WallpaperCanvas, OUTGOING_LAYER};
+use crate::clock::WallpaperClock;
+use crate::parameters::{ParameterValue, Parameters, ResolveParameters};
+use crate::quality::{ChooseQuality, Quality};
+use crate::shader_material::{
+    ShaderMaterial, ShaderTexture, UniformLayout, UniformType, UniformValue,
+};
+
+/// Uniforms every shader wallpaper gets when its uniform struct has a field of that name.
+pub const BUILTIN_UNIFORMS: &[(&str, UniformType)] = &[
+    // Seconds of wallpaper time.
+    ("time", UniformType::F32),
+    // Frames drawn since the wallpaper was switched in.
+    ("frame", UniformType::U32),
+    // Size of the canvas, or of the pass's buffer, in pixels.
+    ("resolution", UniformType::Vec2),
+];
+
+/// How many offscreen passes a shader wallpaper can have.
+pub const MAX_PASSES: usize = 8;
+
+/// Render layer of the first pass, with the others on the ones after it, so that each pass's
+/// camera only sees its own quad.
+const FIRST_PASS_LAYER: u8 = 20;
+
+/// Where the passes of an outgoing wallpaper are moved to, likewise.
+const FIRST_OUTGOING_PASS_LAYER: u8 = 10;
+
+/// The render layer an entity of an outgoing wallpaper is moved to from `layer`, one that the
+/// incoming wallpaper doesn't use.
+pub fn outgoing_layer(layer: u8) -> u8 {
+    let passes = FIRST_PASS_LAYER..FIRST_PASS_LAYER + MAX_PASSES as u8;
+    if layer == 0 {
+        OUTGOING_LAYER
+    } else if passes.contains(&layer) {
+        layer - FIRST_PASS_LAYER + FIRST_OUTGOING_PASS_LAYER
+    } else {
+        layer
+    }
+}
+
+#[derive(Default)]
+pub struct ShaderWallpaperPlugin;
+
+impl Plugin for ShaderWallpaperPlugin {
+    fn build(&self, app: &mut App) {
+        app.world
+            .resource_mut::<Assets<Image>>()
+            .set_untracked(NOISE_TEXTURE, noise_image());
+        app.add_system(spawn_shader_passes.before(prepare_channel_images))
+            .add_system(prepare_channel_images.before(update_shader_wallpapers))
+            .add_system(
+                update_shader_wallpapers
+                    .after(ResolveParameters)
+                    .after(ChooseQuality)
+                    .after(AdvanceSimulations)
+                    .after(MoveCameraRigs),
+            );
+    }
+}
+
+/// Whether `path` is a manifest to show with a [`ShaderWallpaper`].
+pub fn is_shader_wallpaper(path: &Path) -> bool {
+    path.file_name().map_or(false, |name| {
+        name.to_string_lossy().ends_with(".wallpaper.ron")
+    })
+}
+
+/// A WGSL fragment shader drawn over the whole canvas. The [`WallpaperManifest`] names the shader
+/// and declares its parameters, which are written into the shader's uniforms by name along with
+/// the [`BUILTIN_UNIFORMS`]. Its textures are bound to the manifest's channels, followed by the
+/// texture parameters in the order they're declared.
+///
+/// The manifest can add passes drawn into offscreen buffers before the shader, which read each
+/// other's buffers and their own from the frame before through their channels, and a simulation
+/// run by compute shaders before the passes, whose textures they can read too. A raymarched
+/// wallpaper's shader only describes the scene, which is looked at through a [`RaymarchCamera`].
+#[derive(Debug, Clone)]
+pub struct ShaderWallpaper {
+    /// Asset path of the manifest.
+    pub manifest: String,
+}
+
+impl ShaderWallpaper {
+    pub fn new(manifest: impl Into<String>) -> Self {
+        ShaderWallpaper {
+            manifest: manifest.into(),
+        }
+    }
+
+    pub(super) fn spawn(&self, world: &mut World) {
+        let manifest = world.resource::<AssetServer>().load(self.manifest.as_str());
+        let mesh = world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Mesh::from(shape::Quad::new(Vec2::ONE)));
+        world.spawn(Camera2dBundle::default());
+        // The materials are only added once the manifest says which shader to draw with.
+        world.spawn((
+            MaterialMesh2dBundle::<ShaderMaterial> {
+                mesh: Mesh2dHandle(mesh),
+                ..default()
+            },
+            ShaderCanvas {
+                manifest,
+                frame: 0,
+                passes: None,
+                materials: None,
+                images: HashMap::default(),
+                animations: Vec::new(),
+                camera: None,
+            },
+        ));
+    }
+}
+
+/// The quad covering the canvas that a shader wallpaper is drawn on.
+#[derive(Component)]
+struct ShaderCanvas {
+    manifest: Handle<WallpaperManifest>,
+    frame: u32,
+    /// The passes drawn before the canvas, once the manifest has loaded.
+    passes: Option<Vec<Pass>>,
+    /// The materials the canvas is drawn with on even and odd frames, once the manifest has
+    /// loaded.
+    materials: Option<[Handle<ShaderMaterial>; 2]>,
+    /// The images the channels of the canvas and its passes are bound to, by path.
+    images: HashMap<String, Handle<Image>>,
+    /// The entities playing animated images into their channels.
+    animations: Vec<Entity>,
+    /// The [`RaymarchCamera`] of a raymarched wallpaper.
+    camera: Option<Entity>,
+}
+
+/// An offscreen pass of a shader wallpaper, with the camera drawing its quad into its buffer.
+struct Pass {
+    camera: Entity,
+    quad: Entity,
+    /// Drawn with on even and odd frames respectively, each binding the buffers as they are on
+    /// that frame, so that neither has to be prepared again when the buffers swap.
+    materials: [Handle<ShaderMaterial>; 2],
+    /// Written on even and odd frames respectively, so that the other one still holds the
+    /// previous frame.
+    buffers: [Handle<Image>; 2],
+}
+
+fn pass_buffer(format: BufferFormat) -> Image {
+    let size = Extent3d {
+        width: 1,
+        height: 1,
+        ..default()
+    };
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("shader_pass_buffer"),
+            size,
+            dimension: TextureDimension::D2,
+            format: format.texture_format(),
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+        },
+        ..default()
+    };
+    image.resize(size);
+    image
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_shader_passes(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    png_animations: Res<PngAnimations>,
+    manifests: Res<Assets<WallpaperManifest>>,
+    mut manifest_events: EventReader<AssetEvent<WallpaperManifest>>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ShaderMaterial>>,
+    mut canvases: Query<(Entity, &mut ShaderCanvas)>,
+) {
+    let modified: Vec<Handle<WallpaperManifest>> = manifest_events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { handle } => Some(handle.clone_weak()),
+            _ => None,
+        })
+        .collect();
+
+    for (entity, mut canvas) in &mut canvases {
+        if modified.contains(&canvas.manifest) {
+            for pass in canvas.passes.take().into_iter().flatten() {
+                commands.entity(pass.camera).despawn();
+                commands.entity(pass.quad).despawn();
+            }
+            for animation in canvas.animations.drain(..) {
+                commands.entity(animation).despawn();
+            }
+            if let Some(camera) = canvas.camera.take() {
+                commands.entity(camera).despawn();
+            }
+            canvas.images.clear();
+            commands.entity(entity).remove::<Simulation>();
+        }
+        if canvas.passes.is_some() {
+            continue;
+        }
+        let Some(manifest) = manifests.get(&canvas.manifest) else {
+            continue;
+        };
+
+        if let Some(simulation) = &manifest.simulation {
+            commands
+                .entity(entity)
+                .insert(Simulation::new(simulation, &asset_server, &mut images));
+        }
+        if let Some(raymarch) = &manifest.raymarch {
+            let camera = commands
+                .spawn((
+                    RaymarchCamera {
+                        fov: raymarch.camera.fov.to_radians(),
+                    },
+                    TransformBundle::from_transform(raymarch.camera.transform()),
+                    WallpaperEntity,
+                ))
+                .id();
+            canvas.camera = Some(camera);
+        }
+        for channel in manifest.channels() {
+            let (ChannelSource::Image(path)
+            | ChannelSource::Cubemap(path)
+            | ChannelSource::Animated(path)) = &channel.source
+            else {
+                continue;
+            };
+            if canvas.images.contains_key(path) {
+                continue;
+            }
+            let image: Handle<Image> = asset_server.load(path.as_str());
+            if let ChannelSource::Animated(_) = channel.source {
+                let mut animator = commands.spawn((image.clone(), WallpaperEntity));
+                if is_png(path) {
+                    png_animations.load(path, animator.id());
+                } else {
+                    let animation = asset_server.load(format!("{path}#{ANIMATION_LABEL}"));
+                    animator.insert(ImageAnimation::new(animation));
+                }
+                canvas.animations.push(animator.id());
+            }
+            canvas.images.insert(path.clone(), image);
+        }
+        let mesh = meshes.add(Mesh::from(shape::Quad::new(Vec2::ONE)));
+        let count = manifest.passes.len() as isize;
+        let passes = manifest
+            .passes
+            .iter()
+            .enumerate()
+            .map(|(index, declaration)| {
+                let layer = RenderLayers::layer(FIRST_PASS_LAYER + index as u8);
+                let buffers = [(); 2].map(|_| images.add(pass_buffer(declaration.format)));
+                let shader = asset_server.load(declaration.shader.as_str());
+                let pass_materials =
+                    [(); 2].map(|_| materials.add(ShaderMaterial::new(shader.clone())));
+                let camera = commands
+                    .spawn((
+                        Camera2dBundle {
+                            camera: Camera {
+                                target: RenderTarget::Image(buffers[0].clone()),
+                                // before the canvas camera, and in order
+                                priority: index as isize - count,
+                                // so that float buffers aren't drawn through an 8 bit texture
+                                hdr: declaration.format == BufferFormat::Rgba16Float,
+                                ..default()
+                            },
+                            camera_2d: Camera2d {
+                                clear_color: ClearColorConfig::None,
+                            },
+                            ..default()
+                        },
+                        layer,
+                        WallpaperEntity,
+                    ))
+                    .id();
+                let quad = commands
+                    .spawn((
+                        MaterialMesh2dBundle {
+                            mesh: Mesh2dHandle(mesh.clone()),
+                            material: pass_materials[0].clone(),
+                            ..default()
+                        },
+                        layer,
+                        WallpaperEntity,
+                    ))
+                    .id();
+                Pass {
+                    camera,
+                    quad,
+                    materials: pass_materials,
+                    buffers,
+                }
+            })
+            .collect();
+        canvas.passes = Some(passes);
+    }
+}
+
+/// What the shaders of a wallpaper are drawn with in the current frame.
+struct Frame<'a> {
+    time: f32,
+    frame: u32,
+    parameters: &'a Parameters,
+    quality: Quality,
+    declarations: &'a [PassDeclaration],
+    passes: &'a [Pass],
+    simulation: Option<&'a Simulation>,
+    images: &'a HashMap<String, Handle<Image>>,
+}
+
+impl Frame<'_> {
+    /// The buffer pass `index` draws to this frame.
+    fn target(&self, index: usize) -> &Handle<Image> {
+        &self.passes[index].buffers[self.frame as usize % 2]
+    }
+
+    /// The uniforms and textures of the pass at `reader`, where the canvas comes after every pass.
+    fn bindings(
+        &self,
+        layout: &UniformLayout,
+        channels: &[ChannelDeclaration],
+        reader: usize,
+        resolution: Vec2,
+    ) -> (Vec<u8>, Vec<ShaderTexture>) {
+        let mut uniforms = layout.buffer();
+        layout.write(&mut uniforms, "time", UniformValue::F32(self.time));
+        layout.write(&mut uniforms, "frame", UniformValue::U32(self.frame));
+        layout.write(&mut uniforms, "resolution", UniformValue::Vec2(resolution));
+
+        let mut textures: Vec<ShaderTexture> = channels
+            .iter()
+            .map(|channel| self.channel(channel, reader))
+            .collect();
+        for (name, value) in self.parameters.values() {
+            match value {
+                ParameterValue::Texture(image) => textures.push(ShaderTexture {
+                    image: image.clone(),
+                    ..default()
+                }),
+                value => {
+                    if let Some(value) = value.uniform() {
+                        layout.write(&mut uniforms, name, value);
+                    }
+                }
+            }
+        }
+
+        (uniforms, textures)
+    }
+
+    fn channel(&self, channel: &ChannelDeclaration, reader: usize) -> ShaderTexture {
+        let image = match &channel.source {
+            ChannelSource::Pass(name) => self
+                .declarations
+                .iter()
+                .position(|declaration| declaration.name == *name)
+                .map(|index| {
+                    // Passes drawn earlier in the frame are read as they were just drawn, the
+                    // others as they were the frame before.
+                    let buffer = if index < reader {
+                        self.frame
+                    } else {
+                        self.frame.wrapping_add(1)
+                    };
+                    self.passes[index].buffers[buffer as usize % 2].clone()
+                }),
+            ChannelSource::Simulation(name) => self
+                .simulation
+                .and_then(|simulation| simulation.texture(name)),
+            ChannelSource::Image(path)
+            | ChannelSource::Cubemap(path)
+            | ChannelSource::Animated(path) => self.images.get(path).cloned(),
+            ChannelSource::Noise => Some(NOISE_TEXTURE.typed()),
+        };
+        ShaderTexture {
+            image,
+            filter: channel.filter.filter_mode(),
+            address_mode: channel.wrap.address_mode(),
+            cubemap: matches!(channel.source, ChannelSource::Cubemap(_)),
+        }
+    }
+}
+
+/// Gets images ready to be bound to channels as they load: cubemaps are stacked up into one, and
+/// channels that ask for mipmaps get them.
+fn prepare_channel_images(
+    manifests: Res<Assets<WallpaperManifest>>,
+    mut images: ResMut<Assets<Image>>,
+    mut unfilterable: Local<HashSet<Handle<Image>>>,
+    canvases: Query<&ShaderCanvas>,
+) {
+    for canvas in &canvases {
+        let Some(manifest) = manifests.get(&canvas.manifest) else {
+            continue;
+        };
+        for channel in manifest.channels() {
+            let (path, cubemap) = match &channel.source {
+                ChannelSource::Image(path) => (path, false),
+                ChannelSource::Cubemap(path) => (path, true),
+                _ => continue,
+            };
+            let Some(handle) = canvas.images.get(path) else {
+                continue;
+            };
+            // Only borrowed mutably when there's something to do, as that marks the image as
+            // modified and uploads it again.
+            let Some(image) = images.get(handle) else {
+                continue;
+            };
+            let stack = cubemap && !is_cubemap(image);
+            let mipmaps = channel.mipmaps
+                && (stack || needs_mipmaps(image))
+                && !unfilterable.contains(handle);
+            if !stack && !mipmaps {
+                continue;
+            }
+            let image = images.get_mut(handle).unwrap();
+            if stack && !stack_cubemap(image) {
+                warn!(
+                    "Cubemap {:?} isn't six square faces stacked on top of each other",
+                    path
+                );
+            }
+            if mipmaps && !generate_mipmaps(image) {
+                warn!(
+                    "Can't generate mipmaps for {:?}, which is {:?}",
+                    path, image.texture_descriptor.format
+                );
+                unfilterable.insert(handle.clone_weak());
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_shader_wallpapers(
+    clock: Res<WallpaperClock>,
+    windows: Res<Windows>,
+    wallpaper_canvas: Res<WallpaperCanvas>,
+    parameters: Res<Parameters>,
+    quality: Res<Quality>,
+    asset_server: Res<AssetServer>,
+    manifests: Res<Assets<WallpaperManifest>>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<ShaderMaterial>>,
+    mut canvases: Query<(
+        &mut ShaderCanvas,
+        &mut Handle<ShaderMaterial>,
+        &mut Transform,
+        Option<&Simulation>,
+    )>,
+    mut pass_cameras: Query<&mut Camera>,
+    mut pass_quads: Query<
+        (&mut Transform, &mut Handle<ShaderMaterial>),
+        (Without<ShaderCanvas>, Without<RaymarchCamera>),
+    >,
+    raymarch_cameras: Query<(&Transform, &RaymarchCamera), Without<ShaderCanvas>>,
+) {
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+    let resolution = wallpaper_canvas.resolution(window);
+
+    for (mut canvas, mut handle, mut transform, simulation) in &mut canvases {
+        let Some(manifest) = manifests.get(&canvas.manifest) else {
+            continue;
+        };
+        let (Some(shader), Some(layout)) = (&manifest.shader, &manifest.layout) else {
+            continue;
+        };
+        if canvas.passes.is_none() {
+            continue;
+        }
+        let shader = asset_server.load(shader.as_str());
+        if canvas.materials.is_none() {
+            let added = [(); 2].map(|_| materials.add(ShaderMaterial::new(shader.clone())));
+            canvas.materials = Some(added);
+        }
+        let (Some(passes), Some(canvas_materials)) = (&canvas.passes, &canvas.materials) else {
+            continue;
+        };
+        let parity = canvas.frame as usize % 2;
+        let frame = Frame {
+            time: clock.elapsed_seconds(),
+            frame: canvas.frame,
+            parameters: &parameters,
+            quality: *quality,
+            declarations: &manifest.passes,
+            passes,
+            simulation,
+            images: &canvas.images,
+        };
+
+        for (index, (pass, declaration)) in passes.iter().zip(&manifest.passes).enumerate() {
+            let size = (resolution * declaration.scale).round().max(Vec2::ONE);
+            let extent = Extent3d {
+                width: size.x as u32,
+                height: size.y as u32,
+                ..default()
+            };
+            for buffer in &pass.buffers {
+                if let Some(image) = images.get_mut(buffer) {
+                    if image.texture_descriptor.size != extent {
+                        image.resize(extent);
+                    }
+                }
+            }
+            if let Ok(mut camera) = pass_cameras.get_mut(pass.camera) {
+                camera.target = RenderTarget::Image(frame.target(index).clone());
+            }
+            if let Ok((mut quad, mut quad_material)) = pass_quads.get_mut(pass.quad) {
+                quad.scale = size.extend(1.0);
+                if *quad_material != pass.materials[parity] {
+                    *quad_material = pass.materials[parity].clone();
+                }
+            }
+            let (uniforms, textures) =
+                frame.bindings(&declaration.layout, &declaration.channels, index, size);
+            update_material(
+                &mut materials,
+                &pass.materials[parity],
+                None,
+                uniforms,
+                textures,
+                *quality,
+            );
+        }
+
+        if *handle != canvas_materials[parity] {
+            *handle = canvas_materials[parity].clone();
+        }
+        let (mut uniforms, textures) =
+            frame.bindings(layout, &manifest.channels, passes.len(), resolution);
+        if let Some(raymarch) = &manifest.raymarch {
+            // The camera is spawned by commands, so it only shows up the frame after the canvas.
+            let (transform, camera) = canvas
+                .camera
+                .and_then(|camera| raymarch_cameras.get(camera).ok())
+                .map_or_else(
+                    || {
+                        let fov = raymarch.camera.fov.to_radians();
+                        (raymarch.camera.transform(), RaymarchCamera { fov })
+                    },
+                    |(transform, camera)| (*transform, *camera),
+                );
+            write_raymarch_uniforms(
+                raymarch,
+                layout,
+                &mut uniforms,
+                &transform,
+                camera,
+                *quality,
+            );
+        }
+        update_material(
+            &mut materials,
+            &canvas_materials[parity],
+            Some(shader),
+            uniforms,
+            textures,
+            *quality,
+        );
+        transform.scale = resolution.extend(1.0);
+        // Passes feeding back into themselves hold still with the clock.
+        if !clock.delta().is_zero() {
+            canvas.frame = canvas.frame.wrapping_add(1);
+        }
+    }
+}
+
+/// Sets what the material at `handle` is drawn with. It's only borrowed mutably when its shader,
+/// textures or defines change, or its uniforms change size, as that has it prepared again with a
+/// new bind group; otherwise the uniforms are written to the buffer it's already bound with.
+fn update_material(
+    materials: &mut Assets<ShaderMaterial>,
+    handle: &Handle<ShaderMaterial>,
+    shader: Option<Handle<Shader>>,
+    uniforms: Vec<u8>,
+    textures: Vec<ShaderTexture>,
+    quality: Quality,
+) {
+    let Some(material) = materials.get(handle) else {
+        return;
+    };
+    let shader_defs = vec![quality.shader_def()];
+    let unchanged = shader
+        .as_ref()
+        .map_or(true, |shader| material.shader == *shader)
+        && material.textures == textures
+        && material.shader_defs == shader_defs
+        && material.uniforms.size() == uniforms.len();
+    if unchanged {
+        material.uniforms.set(uniforms);
+        return;
+    }
+    let material = materials.get_mut(handle).unwrap();
+    if let Some(shader) = shader {
+        material.shader = shader;
+    }
+    material.textures = textures;
+    material.shader_defs = shader_defs;
+    material.uniforms.set(uniforms);
+}
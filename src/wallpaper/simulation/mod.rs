@@ -0,0 +1,352 @@
+mod node;
+
+use bevy::prelude::*;
+use bevy::render::main_graph::node::CAMERA_DRIVER;
+use bevy::render::render_graph::RenderGraph;
+use bevy::render::render_resource::{
+    Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+};
+use bevy::render::{RenderApp, RenderStage};
+use bevy::utils::HashSet;
+use serde::Deserialize;
+
+use super::manifest::full_scale;
+use crate::canvas::WallpaperCanvas;
+use crate::clock::WallpaperClock;
+use crate::parameters::{Parameters, ResolveParameters};
+use crate::shader_material::{UniformLayout, UniformValue};
+use node::{
+    extract_simulations, queue_simulations, ExtractedSimulations, PreparedSimulations,
+    SimulationNode, SimulationPipelines, SimulationStates,
+};
+
+/// How many ticks a simulation with a fixed rate catches up on in one frame. Anything beyond that
+/// is dropped, so that a long frame doesn't make the next ones longer still.
+const MAX_TICKS_PER_FRAME: usize = 4;
+
+pub const SIMULATION_NODE: &str = "wallpaper_simulation";
+
+#[derive(Default)]
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            advance_simulations
+                .label(AdvanceSimulations)
+                .after(ResolveParameters),
+        );
+
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .init_resource::<ExtractedSimulations>()
+                .init_resource::<SimulationPipelines>()
+                .init_resource::<SimulationStates>()
+                .init_resource::<PreparedSimulations>()
+                .add_system_to_stage(RenderStage::Extract, extract_simulations)
+                .add_system_to_stage(RenderStage::Queue, queue_simulations);
+            let mut graph = render_app.world.resource_mut::<RenderGraph>();
+            graph.add_node(SIMULATION_NODE, SimulationNode);
+            graph.add_node_edge(SIMULATION_NODE, CAMERA_DRIVER).unwrap();
+        }
+    }
+}
+
+/// Compute shader entry points dispatched over storage textures and buffers every tick, before
+/// anything is drawn:
+///
+/// ```ron
+/// simulation: Some((
+///     shader: "life.wgsl",
+///     steps: [(entry_point: "seed", once: true), (entry_point: "step")],
+///     rate: Some(30.0),
+///     textures: [(name: "cells", format: Rgba8Unorm)],
+/// )),
+/// ```
+///
+/// The shader gets its uniforms at `@group(0) @binding(0)`, with `delta`, the seconds a tick
+/// covers, on top of the built in ones. Each texture follows as a `texture_2d<f32>` holding the
+/// state after the last step, read with `textureLoad`, and a write only storage texture for the
+/// step to write the new state to. Texels a step doesn't write keep their value. Storage buffers
+/// come after the textures and keep their contents from one step to the next.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimulationDeclaration {
+    /// Relative to the manifest like the other shaders, and an asset path once loaded.
+    pub shader: String,
+    /// Entry points dispatched in order every tick, each seeing what the one before it wrote.
+    pub steps: Vec<SimulationStep>,
+    /// Ticks per second of wallpaper time, or one tick per frame if not set.
+    #[serde(default)]
+    pub rate: Option<f32>,
+    /// Size of the textures relative to the canvas.
+    #[serde(default = "full_scale")]
+    pub scale: f32,
+    #[serde(default)]
+    pub textures: Vec<StorageTextureDeclaration>,
+    #[serde(default)]
+    pub buffers: Vec<StorageBufferDeclaration>,
+    /// The layout of the shader's uniform buffer, filled in by the loader.
+    #[serde(skip)]
+    pub layout: UniformLayout,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimulationStep {
+    pub entry_point: String,
+    /// Only dispatched on the first tick, to set up the initial state.
+    #[serde(default)]
+    pub once: bool,
+    /// How many invocations to dispatch, or one for each texel of the textures if not set.
+    #[serde(default)]
+    pub invocations: Option<(u32, u32, u32)>,
+    /// The entry point's `@workgroup_size`, filled in by the loader.
+    #[serde(skip)]
+    pub workgroup_size: [u32; 3],
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StorageTextureDeclaration {
+    pub name: String,
+    #[serde(default)]
+    pub format: StorageFormat,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StorageBufferDeclaration {
+    pub name: String,
+    /// Size in bytes. The buffer starts out zeroed.
+    pub size: u64,
+}
+
+impl SimulationDeclaration {
+    pub(super) fn validate(&self) -> Result<(), String> {
+        if self.steps.is_empty() {
+            return Err("the simulation has no steps".to_string());
+        }
+        if !(self.scale.is_finite() && self.scale > 0.0) {
+            return Err("scale of the simulation isn't positive".to_string());
+        }
+        if let Some(rate) = self.rate {
+            if !(rate.is_finite() && rate > 0.0) {
+                return Err("rate of the simulation isn't positive".to_string());
+            }
+        }
+        let mut textures = HashSet::new();
+        for texture in &self.textures {
+            if !textures.insert(texture.name.as_str()) {
+                return Err(format!("texture {:?} is declared twice", texture.name));
+            }
+        }
+        let mut buffers = HashSet::new();
+        for buffer in &self.buffers {
+            if !buffers.insert(buffer.name.as_str()) {
+                return Err(format!("buffer {:?} is declared twice", buffer.name));
+            }
+            if buffer.size == 0 || buffer.size % 4 != 0 {
+                return Err(format!(
+                    "size of buffer {:?} isn't a positive multiple of 4",
+                    buffer.name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The texture format of a simulation texture, which has to work as a storage texture and be
+/// filterable for shaders to sample.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum StorageFormat {
+    Rgba8Unorm,
+    #[default]
+    Rgba16Float,
+}
+
+impl StorageFormat {
+    pub fn texture_format(self) -> TextureFormat {
+        match self {
+            StorageFormat::Rgba8Unorm => TextureFormat::Rgba8Unorm,
+            StorageFormat::Rgba16Float => TextureFormat::Rgba16Float,
+        }
+    }
+}
+
+/// Label for the system that works out which simulation ticks run this frame. Systems binding
+/// simulation textures run after it, to see them at the size they're simulated at.
+#[derive(SystemLabel)]
+pub struct AdvanceSimulations;
+
+/// The compute shaders of a shader wallpaper, run on the GPU before anything is drawn.
+#[derive(Component)]
+pub(super) struct Simulation {
+    declaration: SimulationDeclaration,
+    shader: Handle<Shader>,
+    textures: Vec<SimulationTexture>,
+    size: UVec2,
+    /// Bumped whenever the textures are recreated, which loses their contents, so that the
+    /// simulation is set up again.
+    generation: u32,
+    /// Ticks since the simulation was set up.
+    tick: u32,
+    /// Wallpaper time not simulated yet, for a simulation with a fixed rate.
+    behind: f32,
+    /// The uniforms of every tick to run this frame.
+    ticks: Vec<Vec<u8>>,
+}
+
+/// A texture of a simulation. Steps write to the scratch texture, which is copied back into the
+/// state texture after every step, so that texels a step doesn't write keep their value.
+struct SimulationTexture {
+    name: String,
+    format: StorageFormat,
+    state: Handle<Image>,
+    scratch: Handle<Image>,
+}
+
+impl Simulation {
+    pub(super) fn new(
+        declaration: &SimulationDeclaration,
+        asset_server: &AssetServer,
+        images: &mut Assets<Image>,
+    ) -> Self {
+        let textures = declaration
+            .textures
+            .iter()
+            .map(|texture| SimulationTexture {
+                name: texture.name.clone(),
+                format: texture.format,
+                state: images.add(storage_image(
+                    texture.format,
+                    TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                )),
+                scratch: images.add(storage_image(
+                    texture.format,
+                    TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC,
+                )),
+            })
+            .collect();
+        Simulation {
+            declaration: declaration.clone(),
+            shader: asset_server.load(declaration.shader.as_str()),
+            textures,
+            size: UVec2::ONE,
+            generation: 0,
+            tick: 0,
+            behind: 0.0,
+            ticks: Vec::new(),
+        }
+    }
+
+    /// The texture called `name` as of the latest step.
+    pub(super) fn texture(&self, name: &str) -> Option<Handle<Image>> {
+        self.textures
+            .iter()
+            .find(|texture| texture.name == name)
+            .map(|texture| texture.state.clone())
+    }
+
+    /// How many ticks to run this frame, and how many seconds each of them covers.
+    fn due_ticks(&mut self, clock: &WallpaperClock) -> (usize, f32) {
+        let Some(rate) = self.declaration.rate else {
+            // Nothing moves while the clock is paused, but a new simulation still gets set up.
+            let due = !clock.delta().is_zero() || self.tick == 0;
+            return (usize::from(due), clock.delta_seconds());
+        };
+        let interval = rate.recip();
+        self.behind =
+            (self.behind + clock.delta_seconds()).min(interval * MAX_TICKS_PER_FRAME as f32);
+        let mut count = (self.behind / interval) as usize;
+        self.behind -= count as f32 * interval;
+        if self.tick == 0 {
+            count = count.max(1);
+        }
+        (count, interval)
+    }
+}
+
+fn storage_image(format: StorageFormat, usage: TextureUsages) -> Image {
+    let size = Extent3d {
+        width: 1,
+        height: 1,
+        ..default()
+    };
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("simulation_texture"),
+            size,
+            dimension: TextureDimension::D2,
+            format: format.texture_format(),
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: usage | TextureUsages::COPY_SRC | TextureUsages::COPY_DST,
+        },
+        ..default()
+    };
+    image.resize(size);
+    image
+}
+
+fn advance_simulations(
+    clock: Res<WallpaperClock>,
+    windows: Res<Windows>,
+    canvas: Res<WallpaperCanvas>,
+    parameters: Res<Parameters>,
+    mut images: ResMut<Assets<Image>>,
+    mut simulations: Query<&mut Simulation>,
+) {
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+    let resolution = canvas.resolution(window);
+
+    for mut simulation in &mut simulations {
+        let simulation = &mut *simulation;
+        let size = (resolution * simulation.declaration.scale)
+            .round()
+            .max(Vec2::ONE)
+            .as_uvec2();
+        if size != simulation.size {
+            let extent = Extent3d {
+                width: size.x,
+                height: size.y,
+                ..default()
+            };
+            for texture in &simulation.textures {
+                for handle in [&texture.state, &texture.scratch] {
+                    if let Some(image) = images.get_mut(handle) {
+                        image.resize(extent);
+                    }
+                }
+            }
+            simulation.size = size;
+            simulation.generation = simulation.generation.wrapping_add(1);
+            simulation.tick = 0;
+            simulation.behind = 0.0;
+        }
+
+        simulation.ticks.clear();
+        let (count, delta) = simulation.due_ticks(&clock);
+        let layout = &simulation.declaration.layout;
+        for index in 0..count {
+            // Each tick sees the time at its end.
+            let time =
+                clock.elapsed_seconds() - simulation.behind - (count - 1 - index) as f32 * delta;
+            let mut uniforms = layout.buffer();
+            layout.write(&mut uniforms, "time", UniformValue::F32(time));
+            layout.write(&mut uniforms, "frame", UniformValue::U32(simulation.tick));
+            layout.write(
+                &mut uniforms,
+                "resolution",
+                UniformValue::Vec2(size.as_vec2()),
+            );
+            layout.write(&mut uniforms, "delta", UniformValue::F32(delta));
+            for (name, value) in parameters.values() {
+                if let Some(value) = value.uniform() {
+                    layout.write(&mut uniforms, name, value);
+                }
+            }
+            simulation.ticks.push(uniforms);
+            simulation.tick = simulation.tick.wrapping_add(1);
+        }
+    }
+}
@@ -0,0 +1,466 @@
+use std::borrow::Cow;
+
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_graph::{Node, NodeRunError, RenderGraphContext};
+use bevy::render::render_resource::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType,
+    BufferDescriptor, BufferUsages, CachedComputePipelineId, CommandEncoder, ComputePassDescriptor,
+    ComputePipelineDescriptor, Extent3d, ImageCopyTexture, Origin3d, PipelineCache, ShaderStages,
+    StorageTextureAccess, Texture, TextureAspect, TextureSampleType, TextureViewDimension,
+    TextureViewId,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice, RenderQueue};
+use bevy::render::texture::GpuImage;
+use bevy::render::Extract;
+use bevy::utils::HashMap;
+
+use super::Simulation;
+use crate::quality::Quality;
+use crate::wallpaper::{SimulationStep, StorageFormat};
+
+/// What the render world needs of a [`Simulation`] for one frame.
+struct ExtractedSimulation {
+    entity: Entity,
+    shader: Handle<Shader>,
+    steps: Vec<SimulationStep>,
+    /// The format, state and scratch texture of each texture.
+    textures: Vec<(StorageFormat, Handle<Image>, Handle<Image>)>,
+    buffer_sizes: Vec<u64>,
+    size: UVec2,
+    generation: u32,
+    ticks: Vec<Vec<u8>>,
+}
+
+#[derive(Default, Resource)]
+pub(super) struct ExtractedSimulations(Vec<ExtractedSimulation>);
+
+pub(super) fn extract_simulations(
+    mut extracted: ResMut<ExtractedSimulations>,
+    simulations: Extract<Query<(Entity, &Simulation)>>,
+) {
+    extracted.0.clear();
+    for (entity, simulation) in &simulations {
+        extracted.0.push(ExtractedSimulation {
+            entity,
+            shader: simulation.shader.clone_weak(),
+            steps: simulation.declaration.steps.clone(),
+            textures: simulation
+                .textures
+                .iter()
+                .map(|texture| {
+                    (
+                        texture.format,
+                        texture.state.clone_weak(),
+                        texture.scratch.clone_weak(),
+                    )
+                })
+                .collect(),
+            buffer_sizes: simulation
+                .declaration
+                .buffers
+                .iter()
+                .map(|buffer| buffer.size)
+                .collect(),
+            size: simulation.size,
+            generation: simulation.generation,
+            ticks: simulation.ticks.clone(),
+        });
+    }
+}
+
+/// What a simulation's bind group layout depends on: the formats of its textures and how many
+/// buffers it has.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct LayoutKey {
+    formats: Vec<StorageFormat>,
+    buffers: usize,
+}
+
+/// Bind group layouts and compute pipelines, shared by every simulation that can use them.
+#[derive(Default, Resource)]
+pub(super) struct SimulationPipelines {
+    layouts: HashMap<LayoutKey, BindGroupLayout>,
+    pipelines: HashMap<(Handle<Shader>, String, LayoutKey, Quality), CachedComputePipelineId>,
+}
+
+impl SimulationPipelines {
+    fn layout(&mut self, render_device: &RenderDevice, key: &LayoutKey) -> BindGroupLayout {
+        self.layouts
+            .entry(key.clone())
+            .or_insert_with(|| {
+                let mut entries = vec![BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }];
+                for (index, format) in key.formats.iter().enumerate() {
+                    entries.push(BindGroupLayoutEntry {
+                        binding: 1 + index as u32 * 2,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: false },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    });
+                    entries.push(BindGroupLayoutEntry {
+                        binding: 2 + index as u32 * 2,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::StorageTexture {
+                            access: StorageTextureAccess::WriteOnly,
+                            format: format.texture_format(),
+                            view_dimension: TextureViewDimension::D2,
+                        },
+                        count: None,
+                    });
+                }
+                for index in 0..key.buffers {
+                    entries.push(BindGroupLayoutEntry {
+                        binding: (1 + key.formats.len() * 2 + index) as u32,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    });
+                }
+                render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: Some("simulation_layout"),
+                    entries: &entries,
+                })
+            })
+            .clone()
+    }
+
+    fn pipeline(
+        &mut self,
+        pipeline_cache: &mut PipelineCache,
+        shader: &Handle<Shader>,
+        entry_point: &str,
+        key: &LayoutKey,
+        layout: &BindGroupLayout,
+        quality: Quality,
+    ) -> CachedComputePipelineId {
+        *self
+            .pipelines
+            .entry((
+                shader.clone_weak(),
+                entry_point.to_string(),
+                key.clone(),
+                quality,
+            ))
+            .or_insert_with(|| {
+                pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                    label: Some("simulation_pipeline".into()),
+                    layout: Some(vec![layout.clone()]),
+                    shader: shader.clone_weak(),
+                    shader_defs: vec![quality.shader_def()],
+                    entry_point: Cow::Owned(entry_point.to_string()),
+                })
+            })
+    }
+}
+
+/// What outlives a frame of each simulation in the render world.
+#[derive(Default)]
+struct SimulationState {
+    /// The generation the simulation was last set up at.
+    generation: Option<u32>,
+    buffers: Vec<Buffer>,
+    buffer_sizes: Vec<u64>,
+    /// The views of the state and scratch textures the bind groups were made with.
+    views: Vec<TextureViewId>,
+    /// A uniform buffer for each tick run in one frame, with the bind group binding it, kept for
+    /// as long as the textures and buffers stay the same.
+    ticks: Vec<(Buffer, BindGroup)>,
+}
+
+#[derive(Default, Resource)]
+pub(super) struct SimulationStates(HashMap<Entity, SimulationState>);
+
+/// A simulation ready to be dispatched this frame.
+struct PreparedSimulation {
+    /// The state and scratch texture of each texture.
+    textures: Vec<(Texture, Texture)>,
+    size: UVec2,
+    dispatches: Vec<Dispatch>,
+}
+
+struct Dispatch {
+    pipeline: CachedComputePipelineId,
+    bind_group: BindGroup,
+    workgroups: [u32; 3],
+}
+
+#[derive(Default, Resource)]
+pub(super) struct PreparedSimulations(Vec<PreparedSimulation>);
+
+#[allow(clippy::too_many_arguments)]
+pub(super) fn queue_simulations(
+    extracted: Res<ExtractedSimulations>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    images: Res<RenderAssets<Image>>,
+    quality: Res<Quality>,
+    mut pipeline_cache: ResMut<PipelineCache>,
+    mut pipelines: ResMut<SimulationPipelines>,
+    mut states: ResMut<SimulationStates>,
+    mut prepared: ResMut<PreparedSimulations>,
+) {
+    prepared.0.clear();
+    states.0.retain(|entity, _| {
+        extracted
+            .0
+            .iter()
+            .any(|simulation| simulation.entity == *entity)
+    });
+
+    for simulation in &extracted.0 {
+        let key = LayoutKey {
+            formats: simulation
+                .textures
+                .iter()
+                .map(|(format, _, _)| *format)
+                .collect(),
+            buffers: simulation.buffer_sizes.len(),
+        };
+        let layout = pipelines.layout(&render_device, &key);
+        let ids: Vec<CachedComputePipelineId> = simulation
+            .steps
+            .iter()
+            .map(|step| {
+                pipelines.pipeline(
+                    &mut pipeline_cache,
+                    &simulation.shader,
+                    &step.entry_point,
+                    &key,
+                    &layout,
+                    *quality,
+                )
+            })
+            .collect();
+        // Ticks are dropped until every step can run, rather than running some steps without
+        // the others.
+        if ids
+            .iter()
+            .any(|id| pipeline_cache.get_compute_pipeline(*id).is_none())
+        {
+            continue;
+        }
+        let Some(textures) = simulation
+            .textures
+            .iter()
+            .map(|(_, state, scratch)| Some((images.get(state)?, images.get(scratch)?)))
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+        if textures
+            .iter()
+            .any(|(state, _)| state.size != simulation.size.as_vec2())
+        {
+            continue;
+        }
+
+        let state = states.0.entry(simulation.entity).or_default();
+        if state.buffer_sizes != simulation.buffer_sizes {
+            state.buffers = simulation
+                .buffer_sizes
+                .iter()
+                .map(|size| {
+                    render_device.create_buffer(&BufferDescriptor {
+                        label: Some("simulation_buffer"),
+                        size: *size,
+                        usage: BufferUsages::STORAGE,
+                        mapped_at_creation: false,
+                    })
+                })
+                .collect();
+            state.buffer_sizes = simulation.buffer_sizes.clone();
+            state.ticks.clear();
+        }
+        let views: Vec<TextureViewId> = textures
+            .iter()
+            .flat_map(|(state, scratch)| [state.texture_view.id(), scratch.texture_view.id()])
+            .collect();
+        if state.views != views {
+            state.views = views;
+            state.ticks.clear();
+        }
+        let set_up = state.generation == Some(simulation.generation);
+
+        let mut dispatches = Vec::new();
+        for (tick, uniforms) in simulation.ticks.iter().enumerate() {
+            // Bindings can't be empty, and uniform buffers are sized in multiples of 16 bytes.
+            let mut contents = uniforms.clone();
+            contents.resize((contents.len().max(1) + 15) / 16 * 16, 0);
+            let fits = state
+                .ticks
+                .get(tick)
+                .map_or(false, |(buffer, _)| buffer.size() == contents.len() as u64);
+            if !fits {
+                let buffer = render_device.create_buffer(&BufferDescriptor {
+                    label: Some("simulation_uniforms"),
+                    size: contents.len() as u64,
+                    usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                let bind_group = simulation_bind_group(
+                    &render_device,
+                    &layout,
+                    &buffer,
+                    &textures,
+                    &state.buffers,
+                );
+                state.ticks.truncate(tick);
+                state.ticks.push((buffer, bind_group));
+            }
+            let (buffer, bind_group) = &state.ticks[tick];
+            render_queue.write_buffer(buffer, 0, &contents);
+
+            for (step, id) in simulation.steps.iter().zip(&ids) {
+                // Steps that set the simulation up only run on its first tick.
+                if step.once && (set_up || tick > 0) {
+                    continue;
+                }
+                let (x, y, z) =
+                    step.invocations
+                        .unwrap_or((simulation.size.x, simulation.size.y, 1));
+                let [width, height, depth] = step.workgroup_size;
+                dispatches.push(Dispatch {
+                    pipeline: *id,
+                    bind_group: bind_group.clone(),
+                    workgroups: [
+                        (x + width - 1) / width,
+                        (y + height - 1) / height,
+                        (z + depth - 1) / depth,
+                    ],
+                });
+            }
+        }
+        if !simulation.ticks.is_empty() {
+            state.generation = Some(simulation.generation);
+        }
+
+        prepared.0.push(PreparedSimulation {
+            textures: textures
+                .iter()
+                .map(|(state, scratch)| (state.texture.clone(), scratch.texture.clone()))
+                .collect(),
+            size: simulation.size,
+            dispatches,
+        });
+    }
+}
+
+fn simulation_bind_group(
+    render_device: &RenderDevice,
+    layout: &BindGroupLayout,
+    uniforms: &Buffer,
+    textures: &[(&GpuImage, &GpuImage)],
+    buffers: &[Buffer],
+) -> BindGroup {
+    let mut entries = vec![BindGroupEntry {
+        binding: 0,
+        resource: uniforms.as_entire_binding(),
+    }];
+    for (index, (state, scratch)) in textures.iter().enumerate() {
+        entries.push(BindGroupEntry {
+            binding: 1 + index as u32 * 2,
+            resource: BindingResource::TextureView(&state.texture_view),
+        });
+        entries.push(BindGroupEntry {
+            binding: 2 + index as u32 * 2,
+            resource: BindingResource::TextureView(&scratch.texture_view),
+        });
+    }
+    for (index, buffer) in buffers.iter().enumerate() {
+        entries.push(BindGroupEntry {
+            binding: (1 + textures.len() * 2 + index) as u32,
+            resource: buffer.as_entire_binding(),
+        });
+    }
+    render_device.create_bind_group(&BindGroupDescriptor {
+        label: Some("simulation_bind_group"),
+        layout,
+        entries: &entries,
+    })
+}
+
+/// Runs the steps of every simulation, before any camera draws.
+pub(super) struct SimulationNode;
+
+impl Node for SimulationNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        for simulation in &world.resource::<PreparedSimulations>().0 {
+            if simulation.dispatches.is_empty() {
+                continue;
+            }
+            let extent = Extent3d {
+                width: simulation.size.x,
+                height: simulation.size.y,
+                depth_or_array_layers: 1,
+            };
+            // Scratch textures start out as the state, so whatever a step doesn't write is kept.
+            for (state, scratch) in &simulation.textures {
+                copy_texture(&mut render_context.command_encoder, state, scratch, extent);
+            }
+            for dispatch in &simulation.dispatches {
+                // Shaders that were edited are compiled again before their pipelines are ready.
+                let Some(pipeline) = pipeline_cache.get_compute_pipeline(dispatch.pipeline) else {
+                    continue;
+                };
+                {
+                    let mut pass =
+                        render_context
+                            .command_encoder
+                            .begin_compute_pass(&ComputePassDescriptor {
+                                label: Some("simulation_step"),
+                            });
+                    pass.set_pipeline(pipeline);
+                    pass.set_bind_group(0, &dispatch.bind_group, &[]);
+                    let [x, y, z] = dispatch.workgroups;
+                    pass.dispatch_workgroups(x, y, z);
+                }
+                for (state, scratch) in &simulation.textures {
+                    copy_texture(&mut render_context.command_encoder, scratch, state, extent);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn copy_texture(encoder: &mut CommandEncoder, from: &Texture, to: &Texture, extent: Extent3d) {
+    encoder.copy_texture_to_texture(
+        ImageCopyTexture {
+            texture: from,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        ImageCopyTexture {
+            texture: to,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        extent,
+    );
+}
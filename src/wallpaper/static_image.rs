@@ -0,0 +1,237 @@
+use std::str::FromStr;
+
+use bevy::asset::{AssetLoader, Error, LoadContext, LoadedAsset};
+use bevy::core_pipeline::clear_color::ClearColorConfig;
+use bevy::math::Rect;
+use bevy::prelude::*;
+use bevy::render::render_resource::{AddressMode, SamplerDescriptor};
+use bevy::render::texture::ImageSampler;
+use bevy::utils::BoxedFuture;
+use bevy::window::RequestRedraw;
+
+use super::animated_image::{
+    is_png, AnimatedImage, ImageAnimation, PngAnimations, ANIMATION_LABEL,
+};
+use super::wic::decode_with_wic;
+use crate::canvas::{ResizeCanvas, WallpaperCanvas};
+
+#[derive(Default)]
+pub struct StaticImagePlugin;
+
+impl Plugin for StaticImagePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset_loader::<ImageFileLoader>()
+            .add_system_to_stage(CoreStage::PostUpdate, fit_images.after(ResizeCanvas));
+    }
+}
+
+/// How an image wallpaper is laid out on the canvas, which is stretched over every window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FitMode {
+    /// Scale to cover the whole canvas, cropping whatever spills over.
+    #[default]
+    Fill,
+    /// Scale to fit inside the canvas, showing the background around it.
+    Fit,
+    /// Scale both axes to the canvas, ignoring the aspect ratio.
+    Stretch,
+    /// Draw at native resolution in the middle of the canvas.
+    Center,
+    /// Repeat at native resolution from the top left corner.
+    Tile,
+}
+
+impl FromStr for FitMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fill" => Ok(FitMode::Fill),
+            "fit" => Ok(FitMode::Fit),
+            "stretch" => Ok(FitMode::Stretch),
+            "center" => Ok(FitMode::Center),
+            "tile" => Ok(FitMode::Tile),
+            _ => Err(format!(
+                "unknown fit mode {s:?}, expected fill, fit, stretch, center or tile"
+            )),
+        }
+    }
+}
+
+/// A PNG, JPEG, WebP, AVIF or GIF image. Animated GIF, PNG and WebP files play on the
+/// wallpaper clock.
+#[derive(Debug, Clone)]
+pub struct ImageWallpaper {
+    /// Asset path of the image. Absolute paths work too.
+    pub path: String,
+    pub fit: FitMode,
+    /// Shown wherever the image doesn't cover the canvas.
+    pub background: Color,
+}
+
+impl ImageWallpaper {
+    pub(super) fn spawn(&self, world: &mut World) {
+        let texture = world.resource::<AssetServer>().load(self.path.as_str());
+        world.spawn(Camera2dBundle {
+            camera_2d: Camera2d {
+                clear_color: ClearColorConfig::Custom(self.background),
+            },
+            ..default()
+        });
+        let animation = (can_animate(&self.path) && !is_png(&self.path)).then(|| {
+            world
+                .resource::<AssetServer>()
+                .load(format!("{}#{ANIMATION_LABEL}", self.path))
+        });
+        let mut sprite = world.spawn((
+            SpriteBundle {
+                texture,
+                ..default()
+            },
+            ImageFit(self.fit),
+        ));
+        if let Some(animation) = animation {
+            sprite.insert(ImageAnimation::new(animation));
+        }
+        let sprite = sprite.id();
+        if is_png(&self.path) {
+            world.resource::<PngAnimations>().load(&self.path, sprite);
+        }
+    }
+}
+
+fn can_animate(path: &str) -> bool {
+    let extension = path.rsplit_once('.').map_or("", |(_, extension)| extension);
+    ["gif", "png", "apng", "webp"]
+        .iter()
+        .any(|animated| extension.eq_ignore_ascii_case(animated))
+}
+
+/// Lays the sprite's image out on the canvas.
+#[derive(Component)]
+pub(super) struct ImageFit(pub(super) FitMode);
+
+/// Size and texture region of the sprite showing an image of `image` pixels on a canvas of
+/// `canvas` pixels.
+fn layout(mode: FitMode, image: Vec2, canvas: Vec2) -> (Vec2, Option<Rect>) {
+    match mode {
+        FitMode::Fill => (image * (canvas / image).max_element(), None),
+        FitMode::Fit => (image * (canvas / image).min_element(), None),
+        FitMode::Stretch => (canvas, None),
+        FitMode::Center => (image, None),
+        // A region larger than the image samples past its edges, which the repeating sampler
+        // set up in `fit_images` wraps around.
+        FitMode::Tile => (canvas, Some(Rect::from_corners(Vec2::ZERO, canvas))),
+    }
+}
+
+fn repeating_sampler() -> ImageSampler {
+    ImageSampler::Descriptor(SamplerDescriptor {
+        address_mode_u: AddressMode::Repeat,
+        address_mode_v: AddressMode::Repeat,
+        ..ImageSampler::linear_descriptor()
+    })
+}
+
+fn is_repeating(sampler: &ImageSampler) -> bool {
+    matches!(
+        sampler,
+        ImageSampler::Descriptor(SamplerDescriptor {
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            ..
+        })
+    )
+}
+
+/// Lays images out on the canvas they're drawn to, whatever size the windows it's stretched over
+/// are, again whenever the canvas changes size.
+fn fit_images(
+    canvas: Res<WallpaperCanvas>,
+    mut images: ResMut<Assets<Image>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    mut redraw: EventWriter<RequestRedraw>,
+    mut sprites: Query<(
+        &ImageFit,
+        &Handle<Image>,
+        &mut Sprite,
+        ChangeTrackers<ImageFit>,
+    )>,
+    mut fitted_to: Local<Vec2>,
+) {
+    let Some(resolution) = images.get(canvas.live()).map(|canvas| canvas.size()) else {
+        return;
+    };
+    let refit_all = resolution != *fitted_to;
+    *fitted_to = resolution;
+    let loaded: Vec<Handle<Image>> = image_events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                Some(handle.clone())
+            }
+            AssetEvent::Removed { .. } => None,
+        })
+        .collect();
+
+    for (fit, handle, mut sprite, fit_tracker) in &mut sprites {
+        if !(refit_all || fit_tracker.is_added() || loaded.contains(handle)) {
+            continue;
+        }
+        let Some(image) = images.get(handle) else {
+            // Nothing else wakes a reactive event loop once the image finishes loading.
+            redraw.send(RequestRedraw);
+            continue;
+        };
+
+        let (size, rect) = layout(fit.0, image.size(), resolution);
+        sprite.custom_size = Some(size);
+        sprite.rect = rect;
+
+        if fit.0 == FitMode::Tile && !is_repeating(&image.sampler_descriptor) {
+            if let Some(image) = images.get_mut(handle) {
+                image.sampler_descriptor = repeating_sampler();
+            }
+        }
+    }
+}
+
+/// Loads the image formats Bevy doesn't: WebP, GIF and APNG through the `image` crate and AVIF
+/// through the system codecs. GIF, APNG and WebP files carry an [`AnimatedImage`] labeled
+/// [`ANIMATION_LABEL`] next to the still image.
+///
+/// PNGs are left to Bevy's own loader, which everything else loads them with, and their
+/// animations come from [`PngAnimations`] instead.
+#[derive(Default)]
+pub struct ImageFileLoader;
+
+impl AssetLoader for ImageFileLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let extension = load_context
+                .path()
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+                .unwrap_or_default();
+            let image = if extension == "avif" {
+                decode_with_wic(bytes).map_err(|err| Error::msg(err.to_string()))?
+            } else {
+                Image::from_dynamic(image::load_from_memory(bytes)?, true)
+            };
+            load_context.set_default_asset(LoadedAsset::new(image));
+            if extension != "avif" {
+                let animation = AnimatedImage::decode(bytes, &extension)?;
+                load_context.set_labeled_asset(ANIMATION_LABEL, LoadedAsset::new(animation));
+            }
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["apng", "gif", "webp", "avif"]
+    }
+}
@@ -0,0 +1,146 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bevy::math::UVec2;
+use bevy::utils::{tracing::trace, Duration};
+use crossbeam_channel::{Receiver, Sender};
+
+pub type VideoError = Box<dyn std::error::Error + Send + Sync>;
+
+/// How many decoded frames may wait for their turn on the main thread. Each one is a full frame
+/// of pixels, so this stays small.
+const QUEUED_FRAMES: usize = 4;
+
+/// A decoded video frame.
+pub struct VideoFrame {
+    /// Rgba8 sRGB pixels, row by row without padding.
+    pub data: Vec<u8>,
+    pub size: UVec2,
+    /// When the frame shows, from the start of the video.
+    pub pts: Duration,
+    /// How long the frame shows for.
+    pub duration: Duration,
+}
+
+/// Reads frames out of a video, one after the other.
+pub trait VideoDecoder: Send {
+    /// The next frame, or `None` at the end of the video.
+    fn next_frame(&mut self) -> Result<Option<VideoFrame>, VideoError>;
+
+    /// Moves so that the next frame is the one showing at `to`.
+    fn seek(&mut self, to: Duration) -> Result<(), VideoError>;
+}
+
+/// Opens videos it knows how to decode. Backends are registered in [`VideoBackends`].
+pub trait VideoBackend: Send + Sync + 'static {
+    /// Returns `None` if this backend doesn't handle `path` so that the next one can try.
+    ///
+    /// `frame_rate` is only a hint for sources that don't have one of their own.
+    fn open(
+        &self,
+        path: &Path,
+        frame_rate: f32,
+    ) -> Option<Result<Box<dyn VideoDecoder>, VideoError>>;
+}
+
+/// Messages from a decoder thread to its player. Everything but failures carries the generation
+/// of the seek it follows, so that the player can tell frames it asked for from stale ones.
+pub(super) enum DecoderMessage {
+    Frame(VideoFrame, u32),
+    /// The video starts over after `duration`.
+    Looped(Duration, u32),
+    Ended(u32),
+    Failed(VideoError),
+}
+
+pub(super) enum DecoderCommand {
+    Seek { to: Duration, generation: u32 },
+}
+
+/// Opens `path` with the first backend that takes it and decodes it on a thread of its own,
+/// staying a few frames ahead of playback. The thread stops once either channel is dropped.
+pub(super) fn spawn_decoder(
+    backends: Vec<Arc<dyn VideoBackend>>,
+    path: PathBuf,
+    frame_rate: f32,
+    looping: bool,
+) -> (Sender<DecoderCommand>, Receiver<DecoderMessage>) {
+    let (command_sender, commands) = crossbeam_channel::unbounded();
+    let (messages, message_receiver) = crossbeam_channel::bounded(QUEUED_FRAMES);
+
+    let name = format!("video decoder {}", path.display());
+    let spawned = std::thread::Builder::new().name(name).spawn(move || {
+        let opened = backends
+            .iter()
+            .find_map(|backend| backend.open(&path, frame_rate))
+            .unwrap_or_else(|| Err(format!("no video decoder for {}", path.display()).into()));
+        let result = match opened {
+            Ok(decoder) => decode(decoder, &commands, &messages, looping),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            let _ = messages.send(DecoderMessage::Failed(err));
+        }
+        trace!("Video decoder for {} stopped", path.display());
+    });
+    if let Err(err) = spawned {
+        let (failed, receiver) = crossbeam_channel::bounded(1);
+        let _ = failed.send(DecoderMessage::Failed(err.into()));
+        return (command_sender, receiver);
+    }
+    (command_sender, message_receiver)
+}
+
+fn decode(
+    mut decoder: Box<dyn VideoDecoder>,
+    commands: &Receiver<DecoderCommand>,
+    messages: &Sender<DecoderMessage>,
+    looping: bool,
+) -> Result<(), VideoError> {
+    let mut generation = 0;
+    // End of the last frame decoded since the last seek, which is where the video loops.
+    let mut end: Option<Duration> = None;
+    loop {
+        for command in commands.try_iter() {
+            let DecoderCommand::Seek {
+                to,
+                generation: seek,
+            } = command;
+            decoder.seek(to)?;
+            generation = seek;
+            end = None;
+        }
+
+        let message = match decoder.next_frame()? {
+            Some(frame) => {
+                end = Some(frame.pts + frame.duration);
+                DecoderMessage::Frame(frame, generation)
+            }
+            // Only loop once something played, or a video without frames would spin here.
+            None if looping && end.is_some() => {
+                decoder.seek(Duration::ZERO)?;
+                DecoderMessage::Looped(end.take().unwrap_or_default(), generation)
+            }
+            None => {
+                if messages.send(DecoderMessage::Ended(generation)).is_err() {
+                    return Ok(());
+                }
+                // Nothing to do until the player seeks somewhere else.
+                let Ok(DecoderCommand::Seek {
+                    to,
+                    generation: seek,
+                }) = commands.recv()
+                else {
+                    return Ok(());
+                };
+                decoder.seek(to)?;
+                generation = seek;
+                end = None;
+                continue;
+            }
+        };
+        if messages.send(message).is_err() {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+use bevy::math::UVec2;
+use bevy::utils::Duration;
+
+use super::decoder::{VideoBackend, VideoDecoder, VideoError, VideoFrame};
+
+/// Decodes video files by reading raw frames from an `ffmpeg` process, so anything the installed
+/// ffmpeg can play works as a wallpaper.
+#[derive(Default)]
+pub struct FfmpegBackend;
+
+impl VideoBackend for FfmpegBackend {
+    fn open(
+        &self,
+        path: &Path,
+        _frame_rate: f32,
+    ) -> Option<Result<Box<dyn VideoDecoder>, VideoError>> {
+        if !path.is_file() {
+            return None;
+        }
+        Some(FfmpegDecoder::open(path).map(|decoder| Box::new(decoder) as _))
+    }
+}
+
+struct FfmpegDecoder {
+    path: PathBuf,
+    size: UVec2,
+    frame_duration: Duration,
+    process: Option<Child>,
+    /// Where the running process started decoding from.
+    start: Duration,
+    decoded: u32,
+}
+
+impl FfmpegDecoder {
+    fn open(path: &Path) -> Result<Self, VideoError> {
+        let (size, frame_duration) = probe(path)?;
+        let mut decoder = FfmpegDecoder {
+            path: path.to_owned(),
+            size,
+            frame_duration,
+            process: None,
+            start: Duration::ZERO,
+            decoded: 0,
+        };
+        decoder.spawn(Duration::ZERO)?;
+        Ok(decoder)
+    }
+
+    fn spawn(&mut self, from: Duration) -> Result<(), VideoError> {
+        self.stop();
+        let mut command = Command::new("ffmpeg");
+        // Seeking before the input decodes from the previous keyframe but only outputs frames
+        // from `from` onwards.
+        command
+            .args(["-v", "error", "-nostdin", "-ss"])
+            .arg(format!("{:.3}", from.as_secs_f64()))
+            .arg("-i")
+            .arg(&self.path)
+            .args(["-an", "-f", "rawvideo", "-pix_fmt", "rgba", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        self.process = Some(command.spawn()?);
+        self.start = from;
+        self.decoded = 0;
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Some(mut process) = self.process.take() {
+            let _ = process.kill();
+            let _ = process.wait();
+        }
+    }
+}
+
+impl VideoDecoder for FfmpegDecoder {
+    fn next_frame(&mut self) -> Result<Option<VideoFrame>, VideoError> {
+        let Some(stdout) = self
+            .process
+            .as_mut()
+            .and_then(|process| process.stdout.as_mut())
+        else {
+            return Ok(None);
+        };
+        let mut data = vec![0; self.size.x as usize * self.size.y as usize * 4];
+        match stdout.read_exact(&mut data) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                self.stop();
+                return Ok(None);
+            }
+            Err(err) => return Err(err.into()),
+        }
+        let frame = VideoFrame {
+            data,
+            size: self.size,
+            pts: self.start + self.frame_duration * self.decoded,
+            duration: self.frame_duration,
+        };
+        self.decoded += 1;
+        Ok(Some(frame))
+    }
+
+    fn seek(&mut self, to: Duration) -> Result<(), VideoError> {
+        self.spawn(to)
+    }
+}
+
+impl Drop for FfmpegDecoder {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Asks `ffprobe` for the size and frame duration of the first video stream in `path`.
+fn probe(path: &Path) -> Result<(UVec2, Duration), VideoError> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args(["-show_entries", "stream=width,height,avg_frame_rate"])
+        .args(["-of", "csv=p=0"])
+        .arg(path)
+        .output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed on {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    // Something like `1920,1080,30000/1001`.
+    let output = String::from_utf8(output.stdout)?;
+    let fields: Vec<&str> = output.trim().split(',').collect();
+    let [width, height, frame_rate] = fields[..] else {
+        return Err(format!("{} has no video stream", path.display()).into());
+    };
+    let size = UVec2::new(width.parse()?, height.parse()?);
+    let (numerator, denominator) = frame_rate.split_once('/').unwrap_or((frame_rate, "1"));
+    let (numerator, denominator): (f64, f64) = (numerator.parse()?, denominator.parse()?);
+    if numerator <= 0.0 || denominator <= 0.0 {
+        return Err(format!("{} has no frame rate", path.display()).into());
+    }
+    Ok((size, Duration::from_secs_f64(denominator / numerator)))
+}
@@ -0,0 +1,76 @@
+use std::path::{Path, PathBuf};
+
+use bevy::math::UVec2;
+use bevy::utils::Duration;
+
+use super::decoder::{VideoBackend, VideoDecoder, VideoError, VideoFrame};
+
+/// Plays a folder of images as a video, in file name order. Frames are decoded as they're
+/// needed, so the folder can be much larger than what fits in memory.
+#[derive(Default)]
+pub struct ImageSequenceBackend;
+
+impl VideoBackend for ImageSequenceBackend {
+    fn open(
+        &self,
+        path: &Path,
+        frame_rate: f32,
+    ) -> Option<Result<Box<dyn VideoDecoder>, VideoError>> {
+        if !path.is_dir() {
+            return None;
+        }
+        Some(ImageSequence::open(path, frame_rate).map(|sequence| Box::new(sequence) as _))
+    }
+}
+
+struct ImageSequence {
+    frames: Vec<PathBuf>,
+    frame_duration: Duration,
+    next: usize,
+}
+
+impl ImageSequence {
+    fn open(path: &Path, frame_rate: f32) -> Result<Self, VideoError> {
+        if frame_rate.is_nan() || frame_rate <= 0.0 {
+            return Err(format!("invalid frame rate {frame_rate}").into());
+        }
+        let mut frames = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let path = entry?.path();
+            if path.is_file() && image::ImageFormat::from_path(&path).is_ok() {
+                frames.push(path);
+            }
+        }
+        if frames.is_empty() {
+            return Err(format!("no images in {}", path.display()).into());
+        }
+        frames.sort();
+        Ok(ImageSequence {
+            frames,
+            frame_duration: Duration::from_secs_f32(1.0 / frame_rate),
+            next: 0,
+        })
+    }
+}
+
+impl VideoDecoder for ImageSequence {
+    fn next_frame(&mut self) -> Result<Option<VideoFrame>, VideoError> {
+        let Some(path) = self.frames.get(self.next) else {
+            return Ok(None);
+        };
+        let image = image::open(path)?.into_rgba8();
+        let frame = VideoFrame {
+            size: UVec2::new(image.width(), image.height()),
+            data: image.into_raw(),
+            pts: self.frame_duration * self.next as u32,
+            duration: self.frame_duration,
+        };
+        self.next += 1;
+        Ok(Some(frame))
+    }
+
+    fn seek(&mut self, to: Duration) -> Result<(), VideoError> {
+        self.next = (to.as_secs_f64() / self.frame_duration.as_secs_f64()) as usize;
+        Ok(())
+    }
+}
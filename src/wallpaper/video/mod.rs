@@ -0,0 +1,352 @@
+mod decoder;
+#[cfg(feature = "ffmpeg")]
+mod ffmpeg;
+mod image_sequence;
+
+pub use decoder::{VideoBackend, VideoFrame};
+#[cfg(feature = "ffmpeg")]
+pub use ffmpeg::FfmpegBackend;
+pub use image_sequence::ImageSequenceBackend;
+
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bevy::core_pipeline::clear_color::ClearColorConfig;
+use bevy::prelude::*;
+use bevy::render::render_asset::{PrepareAssetLabel, RenderAssets};
+use bevy::render::render_resource::{
+    Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, TextureAspect, TextureDimension,
+    TextureFormat,
+};
+use bevy::render::renderer::RenderQueue;
+use bevy::render::{RenderApp, RenderStage};
+use bevy::utils::{tracing::warn, Duration};
+use crossbeam_channel::{Receiver, Sender};
+
+use super::static_image::{FitMode, ImageFit};
+use super::OutgoingWallpaperEntity;
+use crate::clock::WallpaperClock;
+use crate::wallpaper_render_plugin::NextUpdate;
+use decoder::{spawn_decoder, DecoderCommand, DecoderMessage};
+
+/// File extensions handed to the video backends rather than loaded as images.
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "m4v", "mkv", "webm", "mov", "avi", "wmv"];
+
+/// How soon to check back when the decoder hasn't caught up with playback yet.
+const DECODER_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+#[derive(Default)]
+pub struct VideoPlugin;
+
+impl Plugin for VideoPlugin {
+    fn build(&self, app: &mut App) {
+        let mut backends = VideoBackends::default();
+        #[cfg(feature = "ffmpeg")]
+        backends.register(FfmpegBackend);
+        backends.register(ImageSequenceBackend);
+
+        app.insert_resource(backends)
+            .add_event::<VideoCommand>()
+            .add_system(play_videos);
+
+        // Frames are written straight into the texture the sprite already samples, rather than
+        // through the image asset, which would recreate the texture for every frame.
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .insert_resource(FrameUploadReceiver(receiver))
+                .add_system_to_stage(
+                    RenderStage::Prepare,
+                    upload_video_frames.after(PrepareAssetLabel::AssetPrepare),
+                );
+            app.insert_resource(FrameUploads(sender));
+        }
+    }
+}
+
+/// Whether `path` is something to play with a [`VideoWallpaper`]: a video file, or a folder
+/// holding an image sequence.
+pub fn is_video(path: &Path) -> bool {
+    path.is_dir()
+        || path.extension().map_or(false, |extension| {
+            VIDEO_EXTENSIONS
+                .iter()
+                .any(|video| extension.eq_ignore_ascii_case(video))
+        })
+}
+
+/// A video file, or a folder of images played back as one.
+#[derive(Debug, Clone)]
+pub struct VideoWallpaper {
+    /// File system path of the video. Unlike images these aren't assets.
+    pub path: PathBuf,
+    pub fit: FitMode,
+    /// Shown wherever the video doesn't cover the canvas, and before the first frame.
+    pub background: Color,
+    /// Start over at the end rather than stopping on the last frame.
+    pub looping: bool,
+    /// Playback speed, on top of the wallpaper clock's.
+    pub speed: f32,
+    /// Frame rate of image sequences, which don't carry one of their own.
+    pub frame_rate: f32,
+}
+
+impl VideoWallpaper {
+    pub(super) fn spawn(&self, world: &mut World) {
+        let texture = world.resource_mut::<Assets<Image>>().add(Image::new_fill(
+            Extent3d::default(),
+            TextureDimension::D2,
+            &[0, 0, 0, 0],
+            TextureFormat::Rgba8UnormSrgb,
+        ));
+        let backends = world.resource::<VideoBackends>().0.clone();
+        let (commands, messages) =
+            spawn_decoder(backends, self.path.clone(), self.frame_rate, self.looping);
+
+        world.spawn(Camera2dBundle {
+            camera_2d: Camera2d {
+                clear_color: ClearColorConfig::Custom(self.background),
+            },
+            ..default()
+        });
+        world.spawn((
+            SpriteBundle {
+                texture,
+                ..default()
+            },
+            ImageFit(self.fit),
+            VideoPlayer {
+                commands,
+                messages,
+                generation: 0,
+                playhead: Duration::ZERO,
+                upcoming: None,
+                speed: self.speed,
+                ended: false,
+                uploaded_size: None,
+            },
+        ));
+    }
+}
+
+/// The backends video wallpapers are opened with, in the order they're tried.
+#[derive(Default, Clone, Resource)]
+pub struct VideoBackends(Vec<Arc<dyn VideoBackend>>);
+
+impl VideoBackends {
+    pub fn register(&mut self, backend: impl VideoBackend) {
+        self.0.push(Arc::new(backend));
+    }
+}
+
+/// Controls playback of the active video wallpaper.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VideoCommand {
+    /// Jumps to this far into the video.
+    Seek(Duration),
+    SetSpeed(f32),
+}
+
+#[derive(Component)]
+struct VideoPlayer {
+    commands: Sender<DecoderCommand>,
+    messages: Receiver<DecoderMessage>,
+    /// Bumped on every seek, so that frames decoded before it can be told apart.
+    generation: u32,
+    /// How far into the video playback is.
+    playhead: Duration,
+    upcoming: Option<Upcoming>,
+    speed: f32,
+    ended: bool,
+    /// Size of the frame last written to the image asset. Frames of the same size can skip the
+    /// asset and go straight to the texture.
+    uploaded_size: Option<UVec2>,
+}
+
+/// The next thing due from the decoder.
+enum Upcoming {
+    Frame(VideoFrame),
+    /// The video starts over after this long.
+    Loop(Duration),
+}
+
+impl Upcoming {
+    fn at(&self) -> Duration {
+        match self {
+            Upcoming::Frame(frame) => frame.pts,
+            Upcoming::Loop(duration) => *duration,
+        }
+    }
+}
+
+impl VideoPlayer {
+    fn apply(&mut self, command: VideoCommand) {
+        match command {
+            VideoCommand::Seek(to) => {
+                self.generation = self.generation.wrapping_add(1);
+                self.playhead = to;
+                self.upcoming = None;
+                self.ended = false;
+                let _ = self.commands.send(DecoderCommand::Seek {
+                    to,
+                    generation: self.generation,
+                });
+            }
+            VideoCommand::SetSpeed(speed) => self.speed = speed.max(0.0),
+        }
+    }
+
+    /// Moves the playhead on by `delta` of clock time and returns the frame that should show now,
+    /// if it changed. Frames the decoder delivered too late to show are skipped.
+    fn advance(&mut self, delta: Duration) -> Option<VideoFrame> {
+        if !self.ended {
+            self.playhead += delta.mul_f32(self.speed);
+        }
+        let mut due = None;
+        loop {
+            if self.upcoming.is_none() {
+                self.upcoming = match self.messages.try_recv() {
+                    Ok(DecoderMessage::Frame(frame, generation))
+                        if generation == self.generation =>
+                    {
+                        Some(Upcoming::Frame(frame))
+                    }
+                    Ok(DecoderMessage::Looped(duration, generation))
+                        if generation == self.generation =>
+                    {
+                        Some(Upcoming::Loop(duration))
+                    }
+                    Ok(DecoderMessage::Ended(generation)) if generation == self.generation => {
+                        self.ended = true;
+                        None
+                    }
+                    Ok(DecoderMessage::Failed(err)) => {
+                        warn!("Video playback failed: {}", err);
+                        self.ended = true;
+                        None
+                    }
+                    // Left over from before a seek.
+                    Ok(_) => continue,
+                    Err(_) => None,
+                };
+            }
+            match self.upcoming.take() {
+                Some(upcoming) if upcoming.at() <= self.playhead => match upcoming {
+                    Upcoming::Frame(frame) => due = Some(frame),
+                    Upcoming::Loop(duration) => self.playhead -= duration,
+                },
+                upcoming => {
+                    self.upcoming = upcoming;
+                    return due;
+                }
+            }
+        }
+    }
+
+    /// How much clock time until the next frame is due, if one ever is.
+    fn until_next_frame(&self) -> Option<Duration> {
+        if self.ended || self.speed <= 0.0 {
+            return None;
+        }
+        let Some(upcoming) = &self.upcoming else {
+            return Some(DECODER_POLL_INTERVAL);
+        };
+        Some(
+            upcoming
+                .at()
+                .saturating_sub(self.playhead)
+                .div_f32(self.speed),
+        )
+    }
+}
+
+/// Frames on their way to the render world, with the image they belong in.
+#[derive(Resource)]
+struct FrameUploads(Sender<(Handle<Image>, VideoFrame)>);
+
+#[derive(Resource)]
+struct FrameUploadReceiver(Receiver<(Handle<Image>, VideoFrame)>);
+
+fn play_videos(
+    clock: Res<WallpaperClock>,
+    uploads: Option<Res<FrameUploads>>,
+    mut commands: EventReader<VideoCommand>,
+    mut images: ResMut<Assets<Image>>,
+    mut next_update: ResMut<NextUpdate>,
+    mut players: Query<(
+        &mut VideoPlayer,
+        &Handle<Image>,
+        Option<&OutgoingWallpaperEntity>,
+    )>,
+) {
+    let commands: Vec<VideoCommand> = commands.iter().copied().collect();
+    for (mut player, handle, outgoing) in &mut players {
+        if outgoing.is_none() {
+            for command in &commands {
+                player.apply(*command);
+            }
+        }
+
+        if let Some(frame) = player.advance(clock.delta()) {
+            match &uploads {
+                Some(uploads) if player.uploaded_size == Some(frame.size) => {
+                    let _ = uploads.0.send((handle.clone_weak(), frame));
+                }
+                // The first frame, or one of a new size, needs a texture to go into first.
+                _ => {
+                    if let Some(image) = images.get_mut(handle) {
+                        image.texture_descriptor.size = Extent3d {
+                            width: frame.size.x,
+                            height: frame.size.y,
+                            ..default()
+                        };
+                        image.data = frame.data;
+                        player.uploaded_size = Some(frame.size);
+                    }
+                }
+            }
+        }
+
+        if let Some(wait) = player
+            .until_next_frame()
+            .and_then(|wait| clock.real_duration(wait))
+        {
+            next_update.request_in(wait);
+        }
+    }
+}
+
+fn upload_video_frames(
+    uploads: Res<FrameUploadReceiver>,
+    images: Res<RenderAssets<Image>>,
+    queue: Res<RenderQueue>,
+) {
+    for (handle, frame) in uploads.0.try_iter() {
+        let Some(image) = images.get(&handle) else {
+            continue;
+        };
+        if image.size != frame.size.as_vec2() {
+            continue;
+        }
+        queue.write_texture(
+            ImageCopyTexture {
+                texture: &image.texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            &frame.data,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(frame.size.x * 4),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: frame.size.x,
+                height: frame.size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
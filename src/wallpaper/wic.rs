@@ -0,0 +1,54 @@
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::Image;
+use bevy::utils::default;
+use windows::{
+    core::IUnknown,
+    Win32::Graphics::Imaging::{
+        CLSID_WICImagingFactory, GUID_WICPixelFormat32bppRGBA, IWICImagingFactory,
+        WICConvertBitmapSource, WICDecodeMetadataCacheOnDemand,
+    },
+    Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_MULTITHREADED,
+    },
+};
+
+/// Decodes the first frame of an image with the Windows Imaging Component, for formats that only
+/// have a system codec. AVIF for example needs the AV1 Video Extension from the Microsoft Store.
+pub fn decode_with_wic(bytes: &[u8]) -> windows::core::Result<Image> {
+    unsafe {
+        // Asset loaders run on the IO task pool, so each thread needs to set up COM for itself.
+        // This fails harmlessly if the thread already did.
+        let _ = CoInitializeEx(std::ptr::null(), COINIT_MULTITHREADED);
+
+        let factory: IWICImagingFactory = CoCreateInstance(
+            &CLSID_WICImagingFactory,
+            None::<IUnknown>,
+            CLSCTX_INPROC_SERVER,
+        )?;
+        let stream = factory.CreateStream()?;
+        stream.InitializeFromMemory(bytes)?;
+        let decoder = factory.CreateDecoderFromStream(
+            &stream,
+            std::ptr::null(),
+            WICDecodeMetadataCacheOnDemand,
+        )?;
+        let frame = decoder.GetFrame(0)?;
+        let source = WICConvertBitmapSource(&GUID_WICPixelFormat32bppRGBA, &frame)?;
+
+        let (mut width, mut height) = (0, 0);
+        source.GetSize(&mut width, &mut height)?;
+        let mut data = vec![0; width as usize * height as usize * 4];
+        source.CopyPixels(std::ptr::null(), width * 4, &mut data)?;
+
+        Ok(Image::new(
+            Extent3d {
+                width,
+                height,
+                ..default()
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+        ))
+    }
+}
@@ -0,0 +1,308 @@
+use std::path::PathBuf;
+
+use bevy::app::{App, Plugin};
+use bevy::asset::Handle;
+use bevy::ecs::event::{Events, ManualEventReader};
+use bevy::ecs::prelude::*;
+use bevy::math::UVec2;
+use bevy::render::main_graph::node::CAMERA_DRIVER;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext};
+use bevy::render::render_resource::{Buffer, MapMode, TextureFormat};
+use bevy::render::renderer::{RenderContext, RenderDevice};
+use bevy::render::settings::{Backends, WgpuSettings};
+use bevy::render::texture::Image;
+use bevy::render::{Extract, RenderApp, RenderStage};
+use bevy::utils::{
+    tracing::{error, info, warn},
+    Duration, Instant,
+};
+use bevy::window::{CreateWindow, Window, WindowCreated, Windows};
+use image::RgbaImage;
+use wgpu::Maintain;
+
+use super::{NextUpdate, SystemWoke};
+use crate::canvas::WallpaperCanvas;
+use crate::clock::WallpaperClock;
+use crate::screenshot::{copy_to_buffer, read_buffer, readback_buffer};
+
+const CAPTURE_NODE: &str = "headless_capture";
+
+/// How long the canvas has to stay the same with the clock stopped before the wallpaper counts as
+/// loaded.
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// How long to wait for the wallpaper to load before capturing whatever it shows.
+const MAX_LOAD_TIME: Duration = Duration::from_secs(30);
+
+/// Renders without a desktop, as an alternative to the [`WallpaperRenderPlugin`]. Windows only
+/// exist as far as bevy is concerned, at the size in the [`HeadlessSettings`], and frames of the
+/// canvas go to the [`HeadlessOutput`] instead of being shown.
+///
+/// Bevy 0.9 can't ask wgpu for its fallback adapter. Without a GPU, wgpu still settles for a
+/// software adapter the system lists among its regular ones, like the Microsoft Basic Render
+/// Driver (WARP) on Windows, and [`check_adapter`] turns having neither into an error rather than
+/// bevy's panic.
+///
+/// [`WallpaperRenderPlugin`]: super::WallpaperRenderPlugin
+#[derive(Default)]
+pub struct HeadlessPlugin;
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NextUpdate>()
+            .init_resource::<HeadlessSettings>()
+            .add_event::<SystemWoke>()
+            .set_runner(headless_runner);
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<CaptureBuffer>()
+            .add_system_to_stage(RenderStage::Extract, extract_capture_source)
+            .add_system_to_stage(RenderStage::Prepare, prepare_capture_buffer);
+        let mut graph = render_app.world.resource_mut::<RenderGraph>();
+        graph.add_node(CAPTURE_NODE, CaptureNode);
+        graph.add_node_edge(CAMERA_DRIVER, CAPTURE_NODE).unwrap();
+    }
+}
+
+/// Checks that wgpu has an adapter to render offscreen with, GPU or not, before bevy panics for
+/// the lack of one. Has to be called before the render plugin is added.
+pub fn check_adapter() -> Result<(), String> {
+    let backends = WgpuSettings::default()
+        .backends
+        .unwrap_or(Backends::PRIMARY);
+    let instance = wgpu::Instance::new(backends);
+    if instance.enumerate_adapters(backends).next().is_none() {
+        return Err(format!(
+            "there's no GPU or software renderer to render with through {backends:?}"
+        ));
+    }
+    Ok(())
+}
+
+/// What to render without a desktop.
+#[derive(Debug, Clone, Resource)]
+pub struct HeadlessSettings {
+    /// In pixels.
+    pub size: UVec2,
+    pub frames: u32,
+    /// Wallpaper time between frames.
+    pub frame_time: Duration,
+}
+
+impl Default for HeadlessSettings {
+    fn default() -> Self {
+        HeadlessSettings {
+            size: UVec2::new(1920, 1080),
+            frames: 1,
+            frame_time: Duration::from_secs_f64(1.0 / 30.0),
+        }
+    }
+}
+
+/// Takes the frames rendered without a desktop, in order, as sRGB RGBA.
+pub trait FrameSink: Send + Sync + 'static {
+    fn write(&mut self, frame: RgbaImage) -> Result<(), String>;
+
+    /// Called after the last frame.
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Where the [`headless_runner`] puts its frames.
+#[derive(Resource)]
+pub struct HeadlessOutput(pub Box<dyn FrameSink>);
+
+/// Writes every frame to a folder, as `0000.png`, `0001.png` and so on.
+pub struct PngFrames {
+    folder: PathBuf,
+    index: u32,
+}
+
+impl PngFrames {
+    pub fn new(folder: impl Into<PathBuf>) -> Result<Self, String> {
+        let folder = folder.into();
+        std::fs::create_dir_all(&folder)
+            .map_err(|err| format!("can't create {}: {err}", folder.display()))?;
+        Ok(PngFrames { folder, index: 0 })
+    }
+}
+
+impl FrameSink for PngFrames {
+    fn write(&mut self, frame: RgbaImage) -> Result<(), String> {
+        let path = self.folder.join(format!("{:04}.png", self.index));
+        frame
+            .save(&path)
+            .map_err(|err| format!("can't write {}: {err}", path.display()))?;
+        info!("Wrote {}", path.display());
+        self.index += 1;
+        Ok(())
+    }
+}
+
+/// Loads the wallpaper with the clock stopped, then captures a frame every `frame_time` of
+/// wallpaper time into the [`HeadlessOutput`]. Exits the process with an error if that fails.
+pub fn headless_runner(mut app: App) {
+    let settings = app.world.resource::<HeadlessSettings>().clone();
+    let Some(HeadlessOutput(mut output)) = app.world.remove_resource::<HeadlessOutput>() else {
+        error!("There is no HeadlessOutput to write the frames to");
+        return;
+    };
+    let result = render_frames(&mut app, &settings, &mut *output).and_then(|()| output.finish());
+    if let Err(err) = result {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn render_frames(
+    app: &mut App,
+    settings: &HeadlessSettings,
+    output: &mut dyn FrameSink,
+) -> Result<(), String> {
+    let mut create_window_reader = ManualEventReader::<CreateWindow>::default();
+
+    // Assets load and pipelines compile in the background, so wait for the canvas to settle.
+    app.world.resource_mut::<WallpaperClock>().pause();
+    let started = Instant::now();
+    let mut settled_since = Instant::now();
+    let mut frame = None;
+    loop {
+        create_windows(&mut app.world, &mut create_window_reader, settings.size);
+        app.update();
+        let next = read_frame(app);
+        if next != frame {
+            settled_since = Instant::now();
+        }
+        frame = next;
+        if frame.is_some() && settled_since.elapsed() >= SETTLE_TIME {
+            break;
+        }
+        if started.elapsed() >= MAX_LOAD_TIME {
+            warn!(
+                "The wallpaper was still changing after {:?} with the clock stopped, capturing it \
+                 anyway",
+                MAX_LOAD_TIME
+            );
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    {
+        let mut clock = app.world.resource_mut::<WallpaperClock>();
+        clock.set_step(Some(settings.frame_time));
+        clock.resume();
+    }
+    for index in 0..settings.frames {
+        if index > 0 {
+            create_windows(&mut app.world, &mut create_window_reader, settings.size);
+            app.update();
+            frame = read_frame(app);
+        }
+        let frame = frame
+            .take()
+            .ok_or_else(|| format!("nothing was rendered for frame {index}"))?;
+        output.write(frame)?;
+    }
+    Ok(())
+}
+
+/// Stands in for winit, making windows that have nothing behind them.
+fn create_windows(
+    world: &mut World,
+    create_window_reader: &mut ManualEventReader<CreateWindow>,
+    size: UVec2,
+) {
+    let created: Vec<CreateWindow> = create_window_reader
+        .iter(world.resource::<Events<CreateWindow>>())
+        .cloned()
+        .collect();
+    for event in created {
+        let window = Window::new(event.id, &event.descriptor, size.x, size.y, 1.0, None, None);
+        world.resource_mut::<Windows>().add(window);
+        world.send_event(WindowCreated { id: event.id });
+    }
+}
+
+/// Waits for the frame the last update rendered and reads it back from the capture buffer.
+fn read_frame(app: &App) -> Option<RgbaImage> {
+    let render_app = app.get_sub_app(RenderApp).ok()?;
+    let capture = render_app.world.resource::<CaptureBuffer>();
+    let (buffer, size, format) = capture.buffer.as_ref()?;
+    let render_device = render_app.world.resource::<RenderDevice>();
+
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    buffer.slice(..).map_async(MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    render_device.poll(Maintain::Wait);
+    receiver.recv().ok()?.ok()?;
+
+    read_buffer(buffer, *size, *format)
+}
+
+/// The canvas image the capture copies, in the render world.
+#[derive(Resource)]
+struct CaptureSource(Handle<Image>);
+
+/// A buffer the canvas is copied into every frame, with the size and format it was made for.
+#[derive(Default, Resource)]
+struct CaptureBuffer {
+    buffer: Option<(Buffer, UVec2, TextureFormat)>,
+}
+
+fn extract_capture_source(mut commands: Commands, canvas: Extract<Res<WallpaperCanvas>>) {
+    commands.insert_resource(CaptureSource(canvas.live().clone_weak()));
+}
+
+fn prepare_capture_buffer(
+    source: Res<CaptureSource>,
+    images: Res<RenderAssets<Image>>,
+    render_device: Res<RenderDevice>,
+    mut capture: ResMut<CaptureBuffer>,
+) {
+    let Some(image) = images.get(&source.0) else {
+        return;
+    };
+    let size = image.size.as_uvec2();
+    let format = image.texture_format;
+    if let Some((_, existing_size, existing_format)) = &capture.buffer {
+        if *existing_size == size && *existing_format == format {
+            return;
+        }
+    }
+    let buffer = readback_buffer(&render_device, size);
+    capture.buffer = Some((buffer, size, format));
+}
+
+/// Copies the canvas into the [`CaptureBuffer`] once every camera has drawn to it.
+struct CaptureNode;
+
+impl Node for CaptureNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let Some(source) = world.get_resource::<CaptureSource>() else {
+            return Ok(());
+        };
+        let Some((buffer, size, _)) = &world.resource::<CaptureBuffer>().buffer else {
+            return Ok(());
+        };
+        let Some(image) = world.resource::<RenderAssets<Image>>().get(&source.0) else {
+            return Ok(());
+        };
+        if image.size.as_uvec2() != *size {
+            return Ok(());
+        }
+        copy_to_buffer(&mut render_context.command_encoder, image, buffer);
+        Ok(())
+    }
+}
@@ -1,10 +1,14 @@
+mod headless;
 mod windows_voodoo;
 mod winit_config;
 mod winit_windows;
 
+pub use headless::*;
 pub use winit_config::*;
 pub use winit_windows::*;
 
+use std::time::SystemTime;
+
 use bevy::app::{App, AppExit, CoreStage, Plugin};
 use bevy::ecs::prelude::*;
 use bevy::ecs::{
@@ -15,7 +19,7 @@ use bevy::ecs::{
 use bevy::math::{ivec2, DVec2, UVec2, Vec2};
 use bevy::utils::{
     tracing::{info, trace, warn},
-    Instant,
+    Duration, Instant,
 };
 use bevy::window::{
     CreateWindow, ModifiesWindows, RequestRedraw, WindowBackendScaleFactorChanged,
@@ -32,13 +36,26 @@ use winit::{
 #[derive(Default)]
 pub struct WallpaperRenderPlugin;
 
+/// Sent when the event loop resumes after the system was suspended or asleep.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemWoke;
+
+/// A gap between updates this much longer than the configured wait means the machine was asleep
+/// rather than us simply waiting for events.
+const SLEEP_DETECTION_SLACK: Duration = Duration::from_secs(30);
+
 impl Plugin for WallpaperRenderPlugin {
     fn build(&self, app: &mut App) {
         app.init_non_send_resource::<WinitWindows>()
             .init_resource::<WinitSettings>()
+            .init_resource::<NextUpdate>()
+            .init_resource::<Dormant>()
+            .init_resource::<StartHidden>()
+            .add_event::<SystemWoke>()
             .set_runner(winit_runner)
             .add_system_to_stage(CoreStage::PostUpdate, change_window.label(ModifiesWindows));
         let event_loop = EventLoop::new();
+        app.insert_resource(EventLoopWaker::new(event_loop.create_proxy()));
         let create_window_reader = WinitCreateWindowReader::default();
         app.insert_resource(create_window_reader)
             .insert_non_send_resource(event_loop);
@@ -232,7 +249,12 @@ struct WinitPersistentState {
     redraw_request_sent: bool,
     /// Tracks if the event loop was started this frame because of a `WaitUntil` timeout.
     timeout_reached: bool,
+    /// Tracks whether an [`EventLoopWaker`] woke the event loop this frame.
+    woken: bool,
     last_update: Instant,
+    /// Wall clock time of the last update, which unlike `last_update` keeps advancing while the
+    /// machine is asleep.
+    last_update_wall: SystemTime,
 }
 impl Default for WinitPersistentState {
     fn default() -> Self {
@@ -241,7 +263,9 @@ impl Default for WinitPersistentState {
             low_power_event: false,
             redraw_request_sent: false,
             timeout_reached: false,
+            woken: false,
             last_update: Instant::now(),
+            last_update_wall: SystemTime::now(),
         }
     }
 }
@@ -283,16 +307,28 @@ pub fn winit_runner_with(mut app: App) {
                 // the frame.
                 let auto_timeout_reached = matches!(start, StartCause::ResumeTimeReached { .. });
                 let now = Instant::now();
-                let manual_timeout_reached = match winit_config.update_mode(focused) {
-                    UpdateMode::Continuous => false,
-                    UpdateMode::Reactive { max_wait }
-                    | UpdateMode::ReactiveLowPower { max_wait } => {
-                        now.duration_since(winit_state.last_update) >= *max_wait
-                    }
-                };
+                let (manual_timeout_reached, expected_wait) =
+                    match winit_config.update_mode(focused) {
+                        UpdateMode::Continuous => (false, Duration::ZERO),
+                        UpdateMode::Reactive { max_wait }
+                        | UpdateMode::ReactiveLowPower { max_wait } => (
+                            now.duration_since(winit_state.last_update) >= *max_wait,
+                            *max_wait,
+                        ),
+                    };
+                let dormant = app.world.resource::<Dormant>().0;
+                let slept = winit_state.active
+                    && !dormant
+                    && SystemTime::now()
+                        .duration_since(winit_state.last_update_wall)
+                        .map_or(false, |since| since > expected_wait + SLEEP_DETECTION_SLACK);
+                if slept {
+                    app.world.send_event(SystemWoke);
+                }
                 // The low_power_event state and timeout must be reset at the start of every frame.
                 winit_state.low_power_event = false;
-                winit_state.timeout_reached = auto_timeout_reached || manual_timeout_reached;
+                winit_state.timeout_reached =
+                    auto_timeout_reached || manual_timeout_reached || slept;
             }
             event::Event::WindowEvent {
                 event,
@@ -413,11 +449,15 @@ pub fn winit_runner_with(mut app: App) {
                     _ => {}
                 }
             }
+            event::Event::UserEvent(()) => {
+                winit_state.woken = true;
+            }
             event::Event::Suspended => {
                 winit_state.active = false;
             }
             event::Event::Resumed => {
                 winit_state.active = true;
+                app.world.send_event(SystemWoke);
             }
             event::Event::MainEventsCleared => {
                 handle_create_window_events(
@@ -426,7 +466,9 @@ pub fn winit_runner_with(mut app: App) {
                     &mut create_window_event_reader,
                 );
                 let winit_config = app.world.resource::<WinitSettings>();
-                let update = if winit_state.active {
+                let update = if app.world.resource::<Dormant>().0 {
+                    winit_state.woken
+                } else if winit_state.active {
                     let windows = app.world.resource::<Windows>();
                     let focused = windows.iter().any(|w| w.is_focused());
                     match winit_config.update_mode(focused) {
@@ -442,20 +484,28 @@ pub fn winit_runner_with(mut app: App) {
                 };
                 if update {
                     winit_state.last_update = Instant::now();
+                    winit_state.last_update_wall = SystemTime::now();
                     app.update();
                 }
+                winit_state.woken = false;
             }
             Event::RedrawEventsCleared => {
                 {
+                    let requested = app.world.resource_mut::<NextUpdate>().take();
                     let winit_config = app.world.resource::<WinitSettings>();
                     let windows = app.world.resource::<Windows>();
                     let focused = windows.iter().any(|w| w.is_focused());
                     let now = Instant::now();
+                    let dormant = app.world.resource::<Dormant>().0;
                     use UpdateMode::*;
                     *control_flow = match winit_config.update_mode(focused) {
+                        _ if dormant => ControlFlow::Wait,
                         Continuous => ControlFlow::Poll,
                         Reactive { max_wait } | ReactiveLowPower { max_wait } => {
-                            ControlFlow::WaitUntil(now + *max_wait)
+                            let deadline = now + *max_wait;
+                            ControlFlow::WaitUntil(
+                                requested.map_or(deadline, |requested| requested.min(deadline)),
+                            )
                         }
                     };
                 }
@@ -464,7 +514,10 @@ pub fn winit_runner_with(mut app: App) {
                 // purpose of a redraw request!
                 let mut redraw = false;
                 if let Some(app_redraw_events) = app.world.get_resource::<Events<RequestRedraw>>() {
-                    if redraw_event_reader.iter(app_redraw_events).last().is_some() {
+                    // Redraws can wait until a dormant app wakes up.
+                    if redraw_event_reader.iter(app_redraw_events).last().is_some()
+                        && !app.world.resource::<Dormant>().0
+                    {
                         *control_flow = ControlFlow::Poll;
                         redraw = true;
                     }
@@ -493,6 +546,7 @@ fn handle_create_window_events(
     create_window_event_reader: &mut ManualEventReader<CreateWindow>,
 ) {
     let world = world.cell();
+    let visible = !world.get_resource::<StartHidden>().unwrap().0;
     let mut winit_windows = world.get_non_send_resource_mut::<WinitWindows>().unwrap();
     let mut windows = world.get_resource_mut::<Windows>().unwrap();
     let create_window_events = world.get_resource::<Events<CreateWindow>>().unwrap();
@@ -502,6 +556,7 @@ fn handle_create_window_events(
             event_loop,
             create_window_event.id,
             &create_window_event.descriptor,
+            visible,
         );
         windows.add(window);
         window_created_events.send(WindowCreated {
@@ -1,5 +1,8 @@
+use std::sync::{Arc, Mutex};
+
 use bevy::ecs::system::Resource;
-use bevy::utils::Duration;
+use bevy::utils::{Duration, Instant};
+use winit::event_loop::EventLoopProxy;
 
 /// A resource for configuring usage of the `rust_winit` library.
 #[derive(Debug, Resource)]
@@ -59,7 +62,7 @@ impl Default for WinitSettings {
 }
 
 /// Configure how the winit event loop should update.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum UpdateMode {
     /// The event loop will update continuously, running as fast as possible.
     Continuous,
@@ -90,3 +93,51 @@ pub enum UpdateMode {
     /// power consumption by only updated the app when absolutely necessary.
     ReactiveLowPower { max_wait: Duration },
 }
+
+/// Asks the event loop to wake up for an update before the reactive update modes' `max_wait`
+/// runs out, for things that know exactly when they next change, like the frames of an animated
+/// image.
+///
+/// Requests only last for the frame they're made in, so keep making them for as long as updates
+/// are needed. The earliest request wins.
+#[derive(Debug, Default, Resource)]
+pub struct NextUpdate(Option<Instant>);
+
+impl NextUpdate {
+    pub fn request(&mut self, at: Instant) {
+        self.0 = Some(self.0.map_or(at, |requested| requested.min(at)));
+    }
+
+    pub fn request_in(&mut self, delay: Duration) {
+        self.request(Instant::now() + delay);
+    }
+
+    pub(crate) fn take(&mut self) -> Option<Instant> {
+        self.0.take()
+    }
+}
+
+/// Stops the event loop from updating the app at all while set, so that nothing gets simulated or
+/// rendered, until an [`EventLoopWaker`] wakes it up.
+#[derive(Debug, Default, Resource)]
+pub struct Dormant(pub bool);
+
+/// Creates windows hidden while set, for whatever sets it to show them once they have something
+/// to show.
+#[derive(Debug, Default, Resource)]
+pub struct StartHidden(pub bool);
+
+/// Wakes the event loop for an update from any thread, even while it's [`Dormant`].
+#[derive(Debug, Clone, Resource)]
+pub struct EventLoopWaker(Arc<Mutex<EventLoopProxy<()>>>);
+
+impl EventLoopWaker {
+    pub(crate) fn new(proxy: EventLoopProxy<()>) -> Self {
+        EventLoopWaker(Arc::new(Mutex::new(proxy)))
+    }
+
+    pub fn wake(&self) {
+        // Only fails once the event loop is gone, when there's nothing left to wake.
+        let _ = self.0.lock().unwrap().send_event(());
+    }
+}
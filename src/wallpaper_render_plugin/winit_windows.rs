@@ -22,6 +22,7 @@ impl WinitWindows {
         event_loop: &winit::event_loop::EventLoopWindowTarget<()>,
         window_id: WindowId,
         window_descriptor: &WindowDescriptor,
+        visible: bool,
     ) -> Window {
         let parent = unsafe { get_workerw() };
         let builder = winit::window::WindowBuilder::new();
@@ -30,6 +31,7 @@ impl WinitWindows {
             .with_always_on_top(true)
             .with_maximized(true)
             .with_decorations(false)
+            .with_visible(visible)
             .build(&event_loop)
             .expect("can create window");
         let winit_id: winit::window::WindowId = unsafe { std::mem::transmute(parent) };
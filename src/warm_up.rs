@@ -0,0 +1,259 @@
+use std::path::{Path, PathBuf};
+
+use bevy::app::{App, CoreStage, Plugin};
+use bevy::asset::{AssetServer, Assets, FileAssetIo};
+use bevy::ecs::prelude::*;
+use bevy::render::render_resource::{CachedPipelineState, PipelineCache};
+use bevy::render::renderer::RenderAdapterInfo;
+use bevy::render::{RenderApp, RenderStage};
+use bevy::utils::{
+    tracing::{info, warn},
+    Duration, Instant,
+};
+use crossbeam_channel::{Receiver, Sender};
+
+use crate::clock::WallpaperClock;
+use crate::content_hash::ContentHash;
+use crate::parameters::Parameters;
+use crate::quality::Quality;
+use crate::wallpaper::WallpaperManifest;
+use crate::wallpaper_render_plugin::{StartHidden, WinitWindows};
+
+/// How long the number of pipelines has to stay the same, with none waiting to compile, before
+/// warm-up counts as done when there's no record of how many there will be.
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// How long the window stays hidden at most, however many pipelines are still compiling.
+const MAX_WARM_UP: Duration = Duration::from_secs(10);
+
+/// Keeps the wallpaper window hidden at startup, with the clock stopped, until the pipelines it
+/// draws with have compiled, so that logging in doesn't show a blank or half drawn wallpaper that
+/// stutters while shaders compile.
+///
+/// wgpu 0.14 has no way to save compiled pipelines, and bevy's pipeline cache can't be handed
+/// shaders it didn't preprocess itself, so neither is kept between runs, and pipelines only
+/// compile faster on later starts where the driver keeps a shader cache of its own. What is kept
+/// is the uniform layouts and workgroup sizes reflected from wallpaper shaders, see
+/// [`reflect_uniform_layout`](crate::shader_material::reflect_uniform_layout), and how many
+/// pipelines the wallpaper ended up with, under a hash of the adapter, the driver, the quality
+/// preset and the wallpaper's files, so that warm-up ends as soon as they've all compiled instead
+/// of waiting for the count to settle.
+#[derive(Default)]
+pub struct WarmUpPlugin;
+
+impl Plugin for WarmUpPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        app.insert_resource(StartHidden(true))
+            .insert_resource(WarmUp {
+                started: Instant::now(),
+                finished: false,
+                receiver,
+                record: None,
+                pipelines: 0,
+                settled_since: Instant::now(),
+                clock_paused: None,
+            })
+            .add_system_to_stage(CoreStage::Last, run_warm_up);
+
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .insert_resource(PipelineCountSender(sender))
+            .add_system_to_stage(RenderStage::Cleanup, count_pipelines);
+    }
+}
+
+/// How far along warm-up is.
+#[derive(Resource)]
+pub struct WarmUp {
+    started: Instant,
+    finished: bool,
+    receiver: Receiver<PipelineCount>,
+    /// What's kept about the wallpaper's pipelines, once the wallpaper is known.
+    record: Option<Record>,
+    pipelines: usize,
+    settled_since: Instant,
+    /// Whether the clock was paused before warm-up held it, to leave it that way afterwards.
+    clock_paused: Option<bool>,
+}
+
+impl WarmUp {
+    /// Whether the window has been shown.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+}
+
+struct Record {
+    /// Where the number of pipelines is kept, if there's somewhere to keep it.
+    path: Option<PathBuf>,
+    /// The number of pipelines on the last run.
+    expected: Option<usize>,
+}
+
+/// The pipelines in bevy's pipeline cache after a frame.
+#[derive(Debug, Clone, Copy)]
+struct PipelineCount {
+    total: usize,
+    /// Waiting for their shaders to load. The rest have compiled or failed to.
+    queued: usize,
+}
+
+#[derive(Resource)]
+struct PipelineCountSender(Sender<PipelineCount>);
+
+fn count_pipelines(pipeline_cache: Res<PipelineCache>, sender: Res<PipelineCountSender>) {
+    let mut count = PipelineCount {
+        total: 0,
+        queued: 0,
+    };
+    for pipeline in pipeline_cache.pipelines() {
+        count.total += 1;
+        if matches!(pipeline.state, CachedPipelineState::Queued) {
+            count.queued += 1;
+        }
+    }
+    // The last count is read every frame until warm-up is over, and never again after that.
+    let _ = sender.0.try_send(count);
+}
+
+/// Where the pipeline counts are kept, in the local app data folder since they're only a hint.
+fn records_dir() -> Option<PathBuf> {
+    let local_app_data = std::env::var_os("LOCALAPPDATA")?;
+    Some(
+        Path::new(&local_app_data)
+            .join("desktop")
+            .join("pipeline-counts"),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_warm_up(
+    mut warm_up: ResMut<WarmUp>,
+    mut clock: ResMut<WallpaperClock>,
+    mut start_hidden: ResMut<StartHidden>,
+    parameters: Res<Parameters>,
+    quality: Res<Quality>,
+    adapter: Res<RenderAdapterInfo>,
+    asset_server: Res<AssetServer>,
+    manifests: Res<Assets<WallpaperManifest>>,
+    winit_windows: NonSend<WinitWindows>,
+) {
+    if warm_up.finished {
+        return;
+    }
+    let warm_up = &mut *warm_up;
+    warm_up.clock_paused.get_or_insert(clock.is_paused());
+    clock.pause();
+    if warm_up.record.is_none() {
+        warm_up.record = record(&parameters, &quality, &adapter, &asset_server, &manifests);
+    }
+
+    let Some(count) = warm_up.receiver.try_iter().last() else {
+        return;
+    };
+    if count.total != warm_up.pipelines || count.queued > 0 {
+        warm_up.pipelines = count.total;
+        warm_up.settled_since = Instant::now();
+    }
+    let done = match &warm_up.record {
+        Some(Record {
+            expected: Some(expected),
+            ..
+        }) => count.queued == 0 && count.total >= *expected,
+        Some(Record { expected: None, .. }) => warm_up.settled_since.elapsed() >= SETTLE_TIME,
+        // The wallpaper isn't known yet.
+        None => false,
+    };
+    let elapsed = warm_up.started.elapsed();
+    if !done && elapsed < MAX_WARM_UP {
+        return;
+    }
+
+    if done {
+        info!(
+            "Compiled {} pipelines in {:.1}s, showing the wallpaper",
+            count.total,
+            elapsed.as_secs_f32()
+        );
+    } else {
+        warn!(
+            "Pipelines were still compiling after {:?}, showing the wallpaper anyway",
+            MAX_WARM_UP
+        );
+    }
+    if let Some(Record {
+        path: Some(path),
+        expected,
+    }) = &warm_up.record
+    {
+        if done && *expected != Some(count.total) {
+            if let Err(err) = write_record(path, count.total) {
+                warn!("Can't keep the number of pipelines: {}", err);
+            }
+        }
+    }
+    warm_up.finished = true;
+    start_hidden.0 = false;
+    if warm_up.clock_paused == Some(false) {
+        clock.resume();
+    }
+    for window in winit_windows.windows.values() {
+        window.set_visible(true);
+    }
+}
+
+/// What's kept about the pipelines of the active wallpaper, once it and its manifest have loaded.
+fn record(
+    parameters: &Parameters,
+    quality: &Quality,
+    adapter: &RenderAdapterInfo,
+    asset_server: &AssetServer,
+    manifests: &Assets<WallpaperManifest>,
+) -> Option<Record> {
+    let wallpaper = parameters.wallpaper()?;
+    let mut hash = ContentHash::default();
+    hash.write(env!("CARGO_PKG_VERSION").as_bytes());
+    let adapter = &adapter.0;
+    hash.write(
+        format!(
+            "{} {:04x}:{:04x} {} {} {:?}",
+            adapter.name,
+            adapter.vendor,
+            adapter.device,
+            adapter.driver,
+            adapter.driver_info,
+            adapter.backend
+        )
+        .as_bytes(),
+    );
+    hash.write(format!("{wallpaper} {quality:?}").as_bytes());
+    if let Some(handle) = parameters.manifest() {
+        let manifest = manifests.get(handle)?;
+        let assets = FileAssetIo::get_base_path().join("assets");
+        if let Some(path) = asset_server.get_handle_path(handle) {
+            hash.write(&std::fs::read(assets.join(path.path())).unwrap_or_default());
+        }
+        // Paths in a loaded manifest are asset paths.
+        for file in manifest.files() {
+            hash.write(&std::fs::read(assets.join(file)).unwrap_or_default());
+        }
+    }
+    let path = records_dir().map(|folder| folder.join(format!("{}.txt", hash.key())));
+    let expected = path
+        .as_ref()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|count| count.trim().parse().ok());
+    Some(Record { path, expected })
+}
+
+fn write_record(path: &Path, pipelines: usize) -> Result<(), String> {
+    if let Some(folder) = path.parent() {
+        std::fs::create_dir_all(folder)
+            .map_err(|err| format!("can't create {}: {err}", folder.display()))?;
+    }
+    std::fs::write(path, pipelines.to_string())
+        .map_err(|err| format!("can't write {}: {err}", path.display()))
+}
@@ -0,0 +1,202 @@
+//! Renders the bundled wallpapers offscreen at fixed wallpaper times and compares the frames with
+//! the reference images in `tests/golden`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to write every frame as the new reference, for a new wallpaper or
+//! after a change that's meant to alter how one looks, and commit the images. A frame without a
+//! reference fails. When a frame doesn't match, it's written next to an image of the differences
+//! in the `golden` folder of cargo's temporary target directory.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use image::{Rgba, RgbaImage};
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 180;
+
+/// Frames are captured at 0, 1.5 and 3 seconds of wallpaper time.
+const FRAMES: u32 = 3;
+const FRAME_TIME: &str = "1.5";
+
+/// How far apart two colors can be in Oklab before the pixel counts as changed. About the
+/// smallest difference people notice, which leaves room for GPUs rounding differently.
+const TOLERANCE: f32 = 0.02;
+
+/// The fraction of pixels that may change before a frame fails.
+const MAX_CHANGED: f32 = 0.001;
+
+#[test]
+fn cube_demo() {
+    check("cube_demo", None);
+}
+
+#[test]
+fn plasma() {
+    check("plasma", Some("plasma.wallpaper.ron"));
+}
+
+#[test]
+fn trails() {
+    check("trails", Some("trails.wallpaper.ron"));
+}
+
+#[test]
+fn clouds() {
+    check("clouds", Some("clouds.wallpaper.ron"));
+}
+
+#[test]
+fn reaction_diffusion() {
+    check(
+        "reaction_diffusion",
+        Some("reaction_diffusion.wallpaper.ron"),
+    );
+}
+
+#[test]
+fn blobs() {
+    check("blobs", Some("blobs.wallpaper.ron"));
+}
+
+/// Renders the wallpaper with the manifest in `assets/wallpapers`, or the built in one without,
+/// and compares every frame with its reference.
+fn check(name: &str, manifest: Option<&str>) {
+    let output = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("golden")
+        .join(name);
+    let _ = std::fs::remove_dir_all(&output);
+    std::fs::create_dir_all(&output).unwrap();
+    render(&output, manifest);
+
+    let references = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(name);
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failures = Vec::new();
+    for index in 0..FRAMES {
+        let file = format!("{index:04}.png");
+        let rendered = output.join(&file);
+        let actual = image::open(&rendered)
+            .unwrap_or_else(|err| panic!("{name} didn't render frame {index}: {err}"))
+            .into_rgba8();
+        let reference = references.join(&file);
+        if update {
+            std::fs::create_dir_all(&references).unwrap();
+            actual.save(&reference).unwrap();
+            continue;
+        }
+        if !reference.exists() {
+            failures.push(format!(
+                "frame {index}: there's no reference {}, run with UPDATE_GOLDEN=1 to write it",
+                reference.display()
+            ));
+            continue;
+        }
+        let expected = image::open(&reference)
+            .unwrap_or_else(|err| panic!("can't read {}: {err}", reference.display()))
+            .into_rgba8();
+        if let Some(failure) = compare(
+            &expected,
+            &actual,
+            &output.join(format!("{index:04}.diff.png")),
+        ) {
+            failures.push(format!("frame {index}: {failure}"));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{name} changed:\n{}",
+        failures.join("\n")
+    );
+}
+
+fn render(output: &Path, manifest: Option<&str>) {
+    // An empty config, so that the user's own doesn't change what gets rendered.
+    let config = output.join("config.ron");
+    std::fs::write(&config, "()").unwrap();
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_desktop"));
+    if let Some(manifest) = manifest {
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "assets", "wallpapers", manifest]
+            .iter()
+            .collect();
+        command.arg(path);
+    }
+    let status = command
+        .arg("--config")
+        .arg(&config)
+        .args(["--quality", "high"])
+        .arg("--headless")
+        .arg(output)
+        .args(["--size", &format!("{WIDTH}x{HEIGHT}")])
+        .args(["--frames", &FRAMES.to_string()])
+        .args(["--frame-time", FRAME_TIME])
+        .status()
+        .expect("can't run desktop");
+    assert!(status.success(), "desktop exited with {status}");
+}
+
+/// Compares two frames, writing an image of where they differ if too much of it changed.
+fn compare(expected: &RgbaImage, actual: &RgbaImage, diff_path: &Path) -> Option<String> {
+    if expected.dimensions() != actual.dimensions() {
+        return Some(format!(
+            "rendered at {:?} instead of {:?}",
+            actual.dimensions(),
+            expected.dimensions()
+        ));
+    }
+    let mut diff = RgbaImage::new(expected.width(), expected.height());
+    let mut changed = 0;
+    for ((expected, actual), diff) in expected
+        .pixels()
+        .zip(actual.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let difference = oklab(expected)
+            .iter()
+            .zip(oklab(actual))
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f32>()
+            .sqrt();
+        // Changed pixels in red over a faded copy of the reference.
+        *diff = if difference > TOLERANCE {
+            changed += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = expected.0;
+            let gray = ((r as u32 + g as u32 + b as u32) / 3 / 4) as u8;
+            Rgba([gray, gray, gray, 255])
+        };
+    }
+    let fraction = changed as f32 / (expected.width() * expected.height()) as f32;
+    if fraction <= MAX_CHANGED {
+        return None;
+    }
+    diff.save(diff_path).unwrap();
+    Some(format!(
+        "{:.2}% of the pixels changed, see {}",
+        fraction * 100.0,
+        diff_path.display()
+    ))
+}
+
+/// Björn Ottosson's Oklab, where distances between colors roughly match how different they look.
+fn oklab(color: &Rgba<u8>) -> [f32; 3] {
+    let [r, g, b] = [color[0], color[1], color[2]].map(|c| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}